@@ -88,10 +88,8 @@ fn render_html(data: &StatementData) -> String {
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Read JSON files
-    let plays_data =
-        fs::read_to_string("chapter-01/plays.json").expect("Failed to read plays.json");
-    let invoices_data =
-        fs::read_to_string("chapter-01/invoices.json").expect("Failed to read invoices.json");
+    let plays_data = fs::read_to_string("chapter-01/plays.json")?;
+    let invoices_data = fs::read_to_string("chapter-01/invoices.json")?;
 
     let plays: HashMap<String, Play> = serde_json::from_str(&plays_data)?;
     let invoices: Vec<Invoice> = serde_json::from_str(&invoices_data)?;
@@ -0,0 +1,119 @@
+//! Runs independent jobs on a pool of threads and hands back their results in
+//! the order the jobs were given, however the threads finish.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// Runs `work` on every item on up to `jobs` threads. The items and whatever
+/// `work` borrows are shared by the threads, not copied.
+pub(crate) fn run<T, R, F>(items: &[T], jobs: usize, work: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let threads = jobs.clamp(1, items.len().max(1));
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    // Each thread takes the next item until none are left, so
+                    // slow items do not hold up the others.
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            return done;
+                        };
+                        done.push((index, work(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    results.sort_unstable_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// How a batch went, printed when it is done.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BatchStats {
+    pub jobs: usize,
+    pub total: usize,
+    pub failed: usize,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for BatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+        write!(
+            f,
+            "{} of {} statements done, {} failed, in {:.2}s on {} {}",
+            self.total - self.failed,
+            self.total,
+            self.failed,
+            seconds,
+            self.jobs,
+            if self.jobs == 1 { "thread" } else { "threads" }
+        )?;
+        if seconds > 0.0 {
+            write!(f, " ({:.1} statements/s)", self.total as f64 / seconds)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, thread::ThreadId};
+
+    use super::*;
+
+    #[test]
+    fn test_results_keep_the_order_of_the_items() {
+        let items: Vec<u64> = (0..100).collect();
+        let threads = Mutex::new(Vec::<ThreadId>::new());
+        let results = run(&items, 4, |n| {
+            // Later items finish first.
+            thread::sleep(Duration::from_micros(100 - n));
+            threads.lock().unwrap().push(thread::current().id());
+            n * 2
+        });
+        assert_eq!(results, (0..100).map(|n| n * 2).collect::<Vec<_>>());
+        let mut threads = threads.into_inner().unwrap();
+        threads.sort_unstable_by_key(|id| format!("{id:?}"));
+        threads.dedup();
+        assert!(threads.len() <= 4);
+
+        assert_eq!(run(&[] as &[u64], 8, |n| *n), Vec::<u64>::new());
+        assert_eq!(run(&[1, 2], 0, |n| n + 1), [2, 3]);
+    }
+
+    #[test]
+    fn test_stats_summary() {
+        let stats = BatchStats {
+            jobs: 4,
+            total: 200,
+            failed: 2,
+            elapsed: Duration::from_millis(500),
+        };
+        assert_eq!(
+            stats.to_string(),
+            "198 of 200 statements done, 2 failed, in 0.50s on 4 threads (400.0 statements/s)"
+        );
+    }
+}
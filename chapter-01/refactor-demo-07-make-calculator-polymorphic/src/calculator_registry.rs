@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use super::{
+    Performance, Play,
+    create_statement_data::{PerformanceCalculator, PerformanceCalculatorBase, RuleCalculator},
+    pricing::PricingRules,
+};
+
+pub(crate) type CalculatorFactory =
+    Box<dyn Fn(&Performance, Play) -> Box<dyn PerformanceCalculator> + Send + Sync>;
+
+/// Maps play kinds to the factories that build their calculators.
+///
+/// New kinds such as "musical" or "history" are added with
+/// [`CalculatorRegistry::register`] instead of editing the factory function.
+#[derive(Default)]
+pub(crate) struct CalculatorRegistry {
+    factories: HashMap<String, CalculatorFactory>,
+}
+
+impl CalculatorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a rule-based calculator for every kind in `rules`.
+    pub fn from_rules(rules: &PricingRules) -> Self {
+        let mut registry = Self::new();
+        for (kind, rule) in rules.iter() {
+            let rule = rule.clone();
+            registry.register(kind, move |perf, play| {
+                Box::new(RuleCalculator {
+                    rule: rule.clone(),
+                    base: PerformanceCalculatorBase {
+                        performance: perf.clone(),
+                        play,
+                    },
+                })
+            });
+        }
+        registry
+    }
+
+    /// Registers `factory` for `kind`, replacing any previous factory.
+    pub fn register<F>(&mut self, kind: impl Into<String>, factory: F)
+    where
+        F: Fn(&Performance, Play) -> Box<dyn PerformanceCalculator> + Send + Sync + 'static,
+    {
+        self.factories.insert(kind.into(), Box::new(factory));
+    }
+
+    pub fn create(&self, perf: &Performance, play: Play) -> Option<Box<dyn PerformanceCalculator>> {
+        self.factories
+            .get(&play.kind)
+            .map(|factory| factory(perf, play))
+    }
+
+    /// The registered kinds, sorted for stable diagnostics.
+    pub fn kinds(&self) -> Vec<&str> {
+        let mut kinds: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+        kinds.sort_unstable();
+        kinds
+    }
+}
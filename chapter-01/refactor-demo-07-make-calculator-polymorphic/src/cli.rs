@@ -0,0 +1,436 @@
+use std::{fmt, path::PathBuf, time::Duration};
+
+use super::{Invoice, error::StatementError, input::DataFormat, locale::Locale};
+
+pub(crate) const USAGE: &str = "\
+usage: statement [COMMAND] [OPTIONS]
+
+commands:
+  statement    render statements for all invoices or the selected ones (default)
+  validate     check the plays and all invoices, reporting every problem with
+               its line and column; --format text or json
+  report       print a revenue report over the selected invoices
+  list-plays   print the plays in the catalogue
+  convert      write the plays and invoices into --out DIR in --to FORMAT
+  help         print this message
+
+input:
+  --data-dir DIR     where the data files are (default: the current directory)
+  --plays PATH       the play catalogue (default: DIR/plays.json)
+  --invoices PATH    the invoices (default: DIR/invoices.json)
+  --ledger PATH      keep credits in this ledger, posting each statement's;
+                     without it credits come from each invoice's credit_balance
+  --templates DIR    user templates (default: DIR/templates)
+  --input-format FORMAT
+                     read plays and invoices as json, yaml, toml or csv,
+                     or invoices as ndjson, which statements are streamed
+                     from without --ledger
+                     (default: by file extension, else json)
+  --customer NAME    only the invoices of this customer
+  --invoice ID       only the invoice with this id
+
+output:
+  --format FORMAT    statement, report or play list format (default: text)
+  --out DIR          write one file per statement or report into DIR
+  --errors PATH      where NDJSON invoices that fail are logged
+                     (default: next to the invoices, as NAME.errors.ndjson)
+  --locale LOCALE    language for invoices that do not name one, e.g. de-DE
+  --template NAME    render with a user template; implies --format template
+  --detailed         show price components
+  --charts           embed SVG charts in HTML statements
+  --ascii            draw tables without box-drawing characters
+  --jobs N           make statements on N threads, one customer per thread at
+                     a time, and print throughput stats at the end
+  --to FORMAT        the data format convert writes: json, yaml, toml, csv
+                     or ndjson
+
+email:
+  --email DIR        write statements as .eml files into DIR
+  --subject TEXT     subject line; {customer} and {invoice} are replaced
+  --attach-pdf       attach the PDF statement
+  --smtp HOST:PORT   also send each email through this SMTP server
+  --smtp-timeout SECONDS
+                     how long the SMTP server gets to connect and answer
+                     each command (default: 30)
+
+exit status: 0 on success, 64 for a bad command line, 65 for invalid data,
+74 when a file cannot be read or written.
+";
+
+/// The subcommand, the first argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Command {
+    Statement,
+    Validate,
+    Report,
+    ListPlays,
+    Convert,
+    Help,
+}
+
+/// The parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Cli {
+    pub command: Command,
+    /// Where data files are looked up unless given one by one.
+    pub data_dir: PathBuf,
+    pub plays: Option<PathBuf>,
+    pub invoices: Option<PathBuf>,
+    /// The credit ledger; statements use none without it.
+    pub ledger: Option<PathBuf>,
+    pub templates: Option<PathBuf>,
+    /// The format of the plays and invoices, when not told by extension.
+    pub input_format: Option<DataFormat>,
+    pub customer: Option<String>,
+    pub invoice: Option<String>,
+    pub format: Option<String>,
+    pub out_dir: Option<PathBuf>,
+    /// The side file for streamed invoices that fail.
+    pub errors: Option<PathBuf>,
+    pub locale: Option<Locale>,
+    pub template: Option<String>,
+    pub detailed: bool,
+    pub charts: bool,
+    pub ascii: bool,
+    /// Threads for statements; sequential without.
+    pub jobs: Option<usize>,
+    /// The format `convert` writes.
+    pub to: Option<DataFormat>,
+    pub email: Option<PathBuf>,
+    pub subject: Option<String>,
+    pub attach_pdf: bool,
+    pub smtp: Option<String>,
+    /// How long the SMTP server gets at each step; the sender's default
+    /// without.
+    pub smtp_timeout: Option<Duration>,
+}
+
+/// Why a run failed, which decides the exit status.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CliError {
+    /// The command line is wrong.
+    Usage(String),
+    /// The input files are readable but wrong.
+    Data(String),
+    /// A file or connection failed.
+    Io(String),
+}
+
+impl CliError {
+    /// The status to exit with, following BSD `sysexits.h`.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Usage(_) => 64,
+            CliError::Data(_) => 65,
+            CliError::Io(_) => 74,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) | CliError::Data(message) | CliError::Io(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<StatementError> for CliError {
+    fn from(err: StatementError) -> Self {
+        match err {
+            StatementError::Io { .. } | StatementError::Write { .. } => {
+                CliError::Io(err.to_string())
+            }
+            _ => CliError::Data(err.to_string()),
+        }
+    }
+}
+
+impl Cli {
+    /// Parses the arguments after the program name. Without a command the
+    /// statements are rendered, as before there were commands.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut args = args.into_iter().peekable();
+        let command = match args.peek().map(String::as_str) {
+            Some("statement") => Command::Statement,
+            Some("validate") => Command::Validate,
+            Some("report") => Command::Report,
+            Some("list-plays") => Command::ListPlays,
+            Some("convert") => Command::Convert,
+            Some("help" | "--help" | "-h") => Command::Help,
+            Some(arg) if !arg.starts_with('-') => {
+                return Err(CliError::Usage(format!("unknown command {arg:?}")));
+            }
+            _ => Command::Statement,
+        };
+        if args.peek().is_some_and(|arg| !arg.starts_with('-')) {
+            args.next();
+        }
+        let mut cli = Cli {
+            command,
+            // The current directory, as file names are given relative to it.
+            data_dir: PathBuf::new(),
+            plays: None,
+            invoices: None,
+            ledger: None,
+            templates: None,
+            input_format: None,
+            customer: None,
+            invoice: None,
+            format: None,
+            out_dir: None,
+            errors: None,
+            locale: None,
+            template: None,
+            detailed: false,
+            charts: false,
+            ascii: false,
+            jobs: None,
+            to: None,
+            email: None,
+            subject: None,
+            attach_pdf: false,
+            smtp: None,
+            smtp_timeout: None,
+        };
+        while let Some(arg) = args.next() {
+            // `--flag=value` and `--flag value` are the same.
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let switch = match flag.as_str() {
+                "--detailed" => Some(&mut cli.detailed),
+                "--charts" => Some(&mut cli.charts),
+                "--ascii" => Some(&mut cli.ascii),
+                "--attach-pdf" => Some(&mut cli.attach_pdf),
+                _ => None,
+            };
+            if let Some(switch) = switch {
+                if inline.is_some() {
+                    return Err(CliError::Usage(format!("{flag} takes no value")));
+                }
+                *switch = true;
+                continue;
+            }
+            if matches!(flag.as_str(), "--help" | "-h") {
+                cli.command = Command::Help;
+                continue;
+            }
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| CliError::Usage(format!("{flag} needs a value")))
+            };
+            match flag.as_str() {
+                "--data-dir" => cli.data_dir = value()?.into(),
+                "--plays" => cli.plays = Some(value()?.into()),
+                "--invoices" => cli.invoices = Some(value()?.into()),
+                "--ledger" => cli.ledger = Some(value()?.into()),
+                "--templates" => cli.templates = Some(value()?.into()),
+                "--input-format" => {
+                    cli.input_format = Some(value()?.parse().map_err(CliError::Usage)?);
+                }
+                "--customer" => cli.customer = Some(value()?),
+                "--invoice" => cli.invoice = Some(value()?),
+                "--format" => cli.format = Some(value()?),
+                "--out" => cli.out_dir = Some(value()?.into()),
+                "--errors" => cli.errors = Some(value()?.into()),
+                "--locale" => cli.locale = Some(value()?.parse().map_err(CliError::Usage)?),
+                "--template" => cli.template = Some(value()?),
+                "--jobs" => {
+                    let jobs = value()?;
+                    match jobs.parse() {
+                        Ok(jobs) if jobs > 0 => cli.jobs = Some(jobs),
+                        _ => {
+                            return Err(CliError::Usage(format!(
+                                "--jobs needs a number of threads, found {jobs:?}"
+                            )));
+                        }
+                    }
+                }
+                "--to" => cli.to = Some(value()?.parse().map_err(CliError::Usage)?),
+                "--email" => cli.email = Some(value()?.into()),
+                "--subject" => cli.subject = Some(value()?),
+                "--smtp" => cli.smtp = Some(value()?),
+                "--smtp-timeout" => {
+                    let seconds = value()?;
+                    match seconds.parse() {
+                        Ok(seconds) if seconds > 0 => {
+                            cli.smtp_timeout = Some(Duration::from_secs(seconds));
+                        }
+                        _ => {
+                            return Err(CliError::Usage(format!(
+                                "--smtp-timeout needs a number of seconds, found {seconds:?}"
+                            )));
+                        }
+                    }
+                }
+                _ if flag.starts_with('-') => {
+                    return Err(CliError::Usage(format!("unknown option {flag}")));
+                }
+                _ => return Err(CliError::Usage(format!("unexpected argument {flag:?}"))),
+            }
+        }
+        Ok(cli)
+    }
+
+    /// Whether `--customer` and `--invoice` pick `invoice`; all are picked
+    /// without them.
+    pub fn selects(&self, invoice: &Invoice) -> bool {
+        self.customer
+            .as_ref()
+            .is_none_or(|customer| *customer == invoice.customer)
+            && (self.invoice.is_none() || invoice.id == self.invoice)
+    }
+
+    /// The path of data file `name`, e.g. `pricing.json`.
+    pub fn data_file(&self, name: &str) -> PathBuf {
+        self.data_dir.join(name)
+    }
+
+    pub fn plays_path(&self) -> PathBuf {
+        self.plays
+            .clone()
+            .unwrap_or_else(|| self.data_file("plays.json"))
+    }
+
+    pub fn invoices_path(&self) -> PathBuf {
+        self.invoices
+            .clone()
+            .unwrap_or_else(|| self.data_file("invoices.json"))
+    }
+
+    pub fn templates_dir(&self) -> PathBuf {
+        self.templates
+            .clone()
+            .unwrap_or_else(|| self.data_file("templates"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::Path};
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, CliError> {
+        Cli::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_commands_and_options() {
+        let cli = parse(&[]).unwrap();
+        assert_eq!(cli.command, Command::Statement);
+        assert_eq!(cli.plays_path(), Path::new("plays.json"));
+        assert_eq!(cli.ledger, None);
+
+        let cli = parse(&[
+            "report",
+            "--format=csv",
+            "--customer",
+            "BigCo",
+            "--data-dir",
+            "/srv/data",
+            "--invoices",
+            "march.json",
+            "--locale",
+            "de-de",
+            "--detailed",
+        ])
+        .unwrap();
+        assert_eq!(cli.command, Command::Report);
+        assert_eq!(cli.format.as_deref(), Some("csv"));
+        assert_eq!(cli.customer.as_deref(), Some("BigCo"));
+        assert_eq!(cli.plays_path(), Path::new("/srv/data/plays.json"));
+        assert_eq!(cli.invoices_path(), Path::new("march.json"));
+        assert_eq!(cli.locale, Some(Locale::DeDe));
+        assert!(cli.detailed);
+        assert_eq!(parse(&["--jobs=8"]).unwrap().jobs, Some(8));
+        assert_eq!(
+            parse(&["--smtp-timeout", "5"]).unwrap().smtp_timeout,
+            Some(Duration::from_secs(5))
+        );
+
+        assert_eq!(
+            parse(&["--format", "html"]).unwrap().command,
+            Command::Statement
+        );
+        assert_eq!(parse(&["list-plays", "-h"]).unwrap().command, Command::Help);
+
+        let cli = parse(&[
+            "convert",
+            "--to",
+            "yaml",
+            "--input-format=csv",
+            "--out",
+            "data",
+        ])
+        .unwrap();
+        assert_eq!(cli.command, Command::Convert);
+        assert_eq!(cli.to, Some(DataFormat::Yaml));
+        assert_eq!(cli.input_format, Some(DataFormat::Csv));
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        let error = |args: &[&str]| parse(args).unwrap_err();
+        assert_eq!(
+            error(&["print"]),
+            CliError::Usage("unknown command \"print\"".into())
+        );
+        assert_eq!(
+            error(&["--colour"]),
+            CliError::Usage("unknown option --colour".into())
+        );
+        assert_eq!(
+            error(&["--out"]),
+            CliError::Usage("--out needs a value".into())
+        );
+        assert_eq!(error(&["--detailed=yes"]).exit_code(), 64);
+        assert_eq!(
+            error(&["--jobs", "0"]),
+            CliError::Usage("--jobs needs a number of threads, found \"0\"".into())
+        );
+        assert_eq!(
+            error(&["--smtp-timeout=0"]),
+            CliError::Usage("--smtp-timeout needs a number of seconds, found \"0\"".into())
+        );
+        assert_eq!(error(&["statement", "BigCo"]).exit_code(), 64);
+        assert!(
+            error(&["--locale", "xx"])
+                .to_string()
+                .starts_with("unknown locale")
+        );
+    }
+
+    #[test]
+    fn test_exit_codes_tell_data_from_io_errors() {
+        let missing = CliError::from(StatementError::io(
+            "plays.json",
+            io::Error::from(io::ErrorKind::NotFound),
+        ));
+        assert_eq!(missing.exit_code(), 74);
+        let unwritable = CliError::from(StatementError::write(
+            "out/statement-bigco.txt",
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        ));
+        assert_eq!(unwritable.exit_code(), 74);
+        assert!(
+            unwritable
+                .to_string()
+                .starts_with("failed to write out/statement-bigco.txt")
+        );
+        let bad = CliError::from(StatementError::InsufficientCredits {
+            customer: "BigCo".to_string(),
+            requested: 10,
+            available: 5,
+        });
+        assert_eq!(bad.exit_code(), 65);
+    }
+}
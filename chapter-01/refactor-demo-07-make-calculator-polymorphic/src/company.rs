@@ -0,0 +1,36 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::{error::StatementError, party::Party, read_json};
+
+/// The theatre company issuing statements, as found in `company.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct Company {
+    pub name: String,
+    /// Postal address, one line per entry.
+    #[serde(default)]
+    pub address: Vec<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub phone: Option<String>,
+    /// The company as seller on e-invoices.
+    #[serde(default)]
+    pub party: Option<Party>,
+}
+
+impl Company {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, StatementError> {
+        read_json(path)
+    }
+
+    /// The name, address and contact lines, in letterhead order.
+    pub fn lines(&self) -> Vec<&str> {
+        let mut lines = vec![self.name.as_str()];
+        lines.extend(self.address.iter().map(String::as_str));
+        lines.extend(self.email.as_deref());
+        lines.extend(self.phone.as_deref());
+        lines
+    }
+}
@@ -1,87 +1,498 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
 
-use super::{Invoice, Performance, Play};
+use serde::Serialize;
 
-#[derive(Debug, Clone, Default)]
+use super::{
+    Invoice, Performance, Play,
+    calculator_registry::CalculatorRegistry,
+    credits::RedemptionPolicy,
+    date::Date,
+    email::Mailbox,
+    error::StatementError,
+    exchange::{Conversion, ExchangeRates},
+    ledger::{Ledger, PostedCredits, Posting},
+    locale::Locale,
+    money::{Currency, Money, MoneyError},
+    party::Party,
+    pricing::{PriceComponent, PricingRule, sum_components},
+    tax::{TaxEngine, TaxLine},
+};
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub(crate) struct PerformanceData {
     pub play: Play,
     pub audience: u32,
-    pub amount: u32,
+    pub date: Option<Date>,
+    pub venue: Option<String>,
+    /// The price from the pricing rules.
+    pub amount: Money,
+    /// How the price is made up; always adds up to `amount`.
+    pub components: Vec<PriceComponent>,
     pub total_credits: u32,
+    pub net_amount: Money,
+    pub tax_lines: Vec<TaxLine>,
+    /// Taxes of the jurisdiction the play kind is exempt from.
+    pub tax_exemptions: Vec<String>,
+    pub gross_amount: Money,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub(crate) struct StatementData {
     pub customer: String,
+    pub invoice_id: Option<String>,
+    pub invoice_date: Option<Date>,
+    pub buyer: Option<Party>,
+    /// Who statements are emailed to.
+    pub contact: Option<Mailbox>,
+    /// The invoice's locale, or the default one from the context.
+    pub locale: Option<Locale>,
     pub performances: Vec<PerformanceData>,
-    pub total_amount: u32,
+    pub total_amount: Money,
     pub total_volume_credits: u32,
+    pub total_net: Money,
+    /// Tax lines summed over all performances, one per tax and rate.
+    pub tax_totals: Vec<TaxLine>,
+    pub total_tax: Money,
+    pub total_gross: Money,
+    pub credits_redeemed: u32,
+    /// Discount bought with redeemed credits, as a positive amount.
+    pub discount: Money,
+    /// What the customer owes after the discount.
+    pub balance_due: Money,
+    /// Credits the customer holds after this invoice.
+    pub credits_remaining: u32,
+    /// The balance in the invoice's billing currency, if it asked for one.
+    pub converted_total: Option<Conversion>,
 }
 
-pub fn create_statement_data(invoice: &Invoice, plays: &HashMap<String, Play>) -> StatementData {
+/// The reference data every statement is computed against.
+#[derive(Clone, Copy)]
+pub(crate) struct StatementContext<'a> {
+    pub plays: &'a HashMap<String, Play>,
+    pub registry: &'a CalculatorRegistry,
+    pub rates: Option<&'a ExchangeRates>,
+    pub taxes: Option<&'a TaxEngine>,
+    pub redemption: Option<&'a RedemptionPolicy>,
+    /// When set, balances come from the ledger, and statements that have been
+    /// written or sent are posted to it with [`StatementContext::post`].
+    pub ledger: Option<&'a Mutex<Ledger>>,
+    /// For invoices that do not name a locale.
+    pub locale: Option<Locale>,
+}
+
+impl<'a> StatementContext<'a> {
+    pub fn new(plays: &'a HashMap<String, Play>, registry: &'a CalculatorRegistry) -> Self {
+        StatementContext {
+            plays,
+            registry,
+            rates: None,
+            taxes: None,
+            redemption: None,
+            ledger: None,
+            locale: None,
+        }
+    }
+
+    pub fn with_rates(self, rates: &'a ExchangeRates) -> Self {
+        StatementContext {
+            rates: Some(rates),
+            ..self
+        }
+    }
+
+    pub fn with_taxes(self, taxes: &'a TaxEngine) -> Self {
+        StatementContext {
+            taxes: Some(taxes),
+            ..self
+        }
+    }
+
+    pub fn with_redemption(self, redemption: &'a RedemptionPolicy) -> Self {
+        StatementContext {
+            redemption: Some(redemption),
+            ..self
+        }
+    }
+
+    pub fn with_ledger(self, ledger: &'a Mutex<Ledger>) -> Self {
+        StatementContext {
+            ledger: Some(ledger),
+            ..self
+        }
+    }
+
+    pub fn with_locale(self, locale: Locale) -> Self {
+        StatementContext {
+            locale: Some(locale),
+            ..self
+        }
+    }
+}
+
+pub fn create_statement_data(
+    invoice: &Invoice,
+    context: &StatementContext,
+) -> Result<StatementData, StatementError> {
+    let StatementContext {
+        plays, registry, ..
+    } = *context;
     let mut statement_data = StatementData {
         customer: invoice.customer.clone(),
+        invoice_id: invoice.id.clone(),
+        invoice_date: invoice.date,
+        buyer: invoice.buyer.clone(),
+        contact: invoice.contact.clone(),
+        locale: invoice.locale.or(context.locale),
         performances: invoice
             .performances
             .iter()
-            .map(|perf| enrich_performance(perf, plays))
-            .collect(),
+            .enumerate()
+            .map(|(index, perf)| {
+                enrich_performance(perf, plays, registry)
+                    .map_err(|err| err.at(&invoice.customer, index))
+            })
+            .collect::<Result<_, _>>()?,
         ..Default::default()
     };
-    statement_data.total_amount = total_amount(&statement_data);
-    statement_data.total_volume_credits = total_volume_credits(&statement_data);
+    apply_taxes(&mut statement_data, invoice, context.taxes)?;
+    statement_data.total_amount = total_money(&statement_data, |p| p.amount)?;
+    statement_data.total_volume_credits = total_volume_credits(&statement_data)?;
+    statement_data.total_net = total_money(&statement_data, |p| p.net_amount)?;
+    statement_data.total_gross = total_money(&statement_data, |p| p.gross_amount)?;
+    statement_data.total_tax = statement_data
+        .total_gross
+        .checked_sub(statement_data.total_net)
+        .map_err(|_| StatementError::TotalOverflow {
+            customer: statement_data.customer.clone(),
+        })?;
+    statement_data.tax_totals = tax_totals(&statement_data)?;
+    let ledger = context
+        .ledger
+        .map(|ledger| ledger.lock().unwrap_or_else(PoisonError::into_inner));
+    let available = match &ledger {
+        Some(ledger) => ledger.balance_for(&invoice.customer, invoice.id.as_deref())?,
+        None => invoice.credit_balance,
+    };
+    redeem_credits(&mut statement_data, invoice, available, context.redemption)?;
+    // A rerun must not print credits other than the ones in the books.
+    if let (Some(ledger), Some(id)) = (&ledger, &invoice.id) {
+        let found = PostedCredits {
+            redeemed: statement_data.credits_redeemed,
+            earned: statement_data.total_volume_credits,
+        };
+        match ledger.posted(&invoice.customer, id) {
+            Some(posted) if posted != found => {
+                return Err(StatementError::PostedCreditsDiffer {
+                    customer: invoice.customer.clone(),
+                    invoice: id.clone(),
+                    posted,
+                    found,
+                });
+            }
+            _ => {}
+        }
+    }
+    drop(ledger);
+    statement_data.converted_total = converted_total(&statement_data, invoice, context.rates)?;
+    // Chronological order; undated performances keep their order at the end.
+    // Sorted last, so that errors give the performance's index in the invoice.
     statement_data
+        .performances
+        .sort_by_key(|perf| (perf.date.is_none(), perf.date));
+    Ok(statement_data)
+}
+
+impl StatementContext<'_> {
+    /// Posts the credits of a statement that has been written or sent to the
+    /// ledger, if there is one. Returns a warning if they could not be.
+    pub fn post(&self, data: &StatementData) -> Option<String> {
+        let mut ledger = self.ledger?.lock().unwrap_or_else(PoisonError::into_inner);
+        match ledger.post_statement(data) {
+            Posting::NoInvoiceId if data.total_volume_credits > 0 || data.credits_redeemed > 0 => {
+                Some(format!(
+                    "{}: the invoice has no id, so its credits are not posted to the ledger",
+                    data.customer
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Credits held before this invoice can be redeemed; the ones earned on it are
+// only added to what remains.
+fn redeem_credits(
+    statement_data: &mut StatementData,
+    invoice: &Invoice,
+    available: u32,
+    policy: Option<&RedemptionPolicy>,
+) -> Result<(), StatementError> {
+    if invoice.redeem_credits > available {
+        return Err(StatementError::InsufficientCredits {
+            customer: invoice.customer.clone(),
+            requested: invoice.redeem_credits,
+            available,
+        });
+    }
+    let overflow = || StatementError::TotalOverflow {
+        customer: invoice.customer.clone(),
+    };
+    let redemption = policy
+        .cloned()
+        .unwrap_or_default()
+        .redeem(invoice.redeem_credits, statement_data.total_gross)
+        .map_err(|_| overflow())?;
+    statement_data.credits_redeemed = redemption.credits;
+    statement_data.balance_due = statement_data
+        .total_gross
+        .checked_sub(redemption.discount)
+        .map_err(|_| overflow())?;
+    statement_data.discount = redemption.discount;
+    statement_data.credits_remaining = (available - redemption.credits)
+        .checked_add(statement_data.total_volume_credits)
+        .ok_or_else(overflow)?;
+    Ok(())
+}
+
+// Without a jurisdiction the price is both net and gross.
+fn apply_taxes(
+    statement_data: &mut StatementData,
+    invoice: &Invoice,
+    taxes: Option<&TaxEngine>,
+) -> Result<(), StatementError> {
+    let jurisdiction = match (&invoice.jurisdiction, taxes) {
+        (Some(id), Some(taxes)) => {
+            Some(
+                taxes
+                    .jurisdiction(id)
+                    .ok_or_else(|| StatementError::UnknownJurisdiction {
+                        customer: invoice.customer.clone(),
+                        jurisdiction: id.clone(),
+                    })?,
+            )
+        }
+        (Some(id), None) => {
+            return Err(StatementError::UnknownJurisdiction {
+                customer: invoice.customer.clone(),
+                jurisdiction: id.clone(),
+            });
+        }
+        (None, _) => None,
+    };
+    for (index, perf) in statement_data.performances.iter_mut().enumerate() {
+        match (jurisdiction, taxes) {
+            (Some(jurisdiction), Some(taxes)) => {
+                let taxed = taxes
+                    .apply(jurisdiction, perf.amount, &perf.play.kind)
+                    .map_err(|err| PerformanceError::from(err).at(&invoice.customer, index))?;
+                perf.net_amount = taxed.net;
+                perf.gross_amount = taxed.gross;
+                perf.tax_lines = taxed.lines;
+                perf.tax_exemptions = taxed.exemptions;
+            }
+            _ => {
+                perf.net_amount = perf.amount;
+                perf.gross_amount = perf.amount;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn tax_totals(statement_data: &StatementData) -> Result<Vec<TaxLine>, StatementError> {
+    let mut totals: Vec<TaxLine> = Vec::new();
+    for (index, perf) in statement_data.performances.iter().enumerate() {
+        for line in &perf.tax_lines {
+            match totals
+                .iter_mut()
+                .find(|t| t.name == line.name && t.rate == line.rate)
+            {
+                Some(total) => {
+                    total.amount = total.amount.checked_add(line.amount).map_err(|err| {
+                        PerformanceError::from(err).at(&statement_data.customer, index)
+                    })?;
+                }
+                None => totals.push(line.clone()),
+            }
+        }
+    }
+    Ok(totals)
+}
+
+fn converted_total(
+    statement_data: &StatementData,
+    invoice: &Invoice,
+    rates: Option<&ExchangeRates>,
+) -> Result<Option<Conversion>, StatementError> {
+    let total = statement_data.balance_due;
+    let Some(currency) = invoice.currency.filter(|c| *c != total.currency) else {
+        return Ok(None);
+    };
+    let missing_rate = || StatementError::MissingRate {
+        customer: invoice.customer.clone(),
+        from: total.currency,
+        to: currency,
+        date: invoice.date,
+    };
+    match rates
+        .ok_or_else(missing_rate)?
+        .convert(total, currency, invoice.date)
+    {
+        Some(Ok(conversion)) => Ok(Some(conversion)),
+        Some(Err(_)) => Err(StatementError::TotalOverflow {
+            customer: invoice.customer.clone(),
+        }),
+        None => Err(missing_rate()),
+    }
+}
+
+/// A failure for a single performance, before the invoice context is known.
+enum PerformanceError {
+    MissingPlay(String),
+    UnknownPlayKind(String, Vec<String>),
+    OverCapacity(u32, u32),
+    InconsistentBreakdown,
+    Overflow,
+    Money(MoneyError),
+}
+
+impl From<MoneyError> for PerformanceError {
+    fn from(err: MoneyError) -> Self {
+        PerformanceError::Money(err)
+    }
+}
+
+impl PerformanceError {
+    fn at(self, customer: &str, performance: usize) -> StatementError {
+        let customer = customer.to_string();
+        match self {
+            PerformanceError::MissingPlay(play_id) => StatementError::MissingPlay {
+                customer,
+                performance,
+                play_id,
+            },
+            PerformanceError::UnknownPlayKind(kind, registered) => {
+                StatementError::UnknownPlayKind {
+                    customer,
+                    performance,
+                    kind,
+                    registered,
+                }
+            }
+            PerformanceError::OverCapacity(audience, capacity) => StatementError::OverCapacity {
+                customer,
+                performance,
+                audience,
+                capacity,
+            },
+            PerformanceError::InconsistentBreakdown => StatementError::InconsistentBreakdown {
+                customer,
+                performance,
+            },
+            PerformanceError::Money(MoneyError::CurrencyMismatch(expected, found)) => {
+                StatementError::CurrencyMismatch {
+                    customer,
+                    performance,
+                    expected,
+                    found,
+                }
+            }
+            PerformanceError::Overflow | PerformanceError::Money(_) => StatementError::Overflow {
+                customer,
+                performance,
+            },
+        }
+    }
 }
 
-fn play_for(perf: &Performance, plays: &HashMap<String, Play>) -> Play {
-    plays.get(&perf.play_id).expect("Play not found").clone()
+fn play_for(perf: &Performance, plays: &HashMap<String, Play>) -> Result<Play, PerformanceError> {
+    plays
+        .get(&perf.play_id)
+        .cloned()
+        .ok_or_else(|| PerformanceError::MissingPlay(perf.play_id.clone()))
 }
-// The first step is to apply Replace Type Code with Subclasses (362) to
-// introduce subclasses and deprecate the type code.
-fn enrich_performance(perf: &Performance, plays: &HashMap<String, Play>) -> PerformanceData {
+// Replace Type Code with Subclasses (362) gave every play type a calculator
+// of its own. The prices now come from a rule table, so one RuleCalculator
+// serves every kind in pricing.json, and the PerformanceCalculator trait keeps
+// the call polymorphic for kinds registered with calculators of their own.
+fn enrich_performance(
+    perf: &Performance,
+    plays: &HashMap<String, Play>,
+    registry: &CalculatorRegistry,
+) -> Result<PerformanceData, PerformanceError> {
+    if let Some(capacity) = perf.capacity.filter(|capacity| perf.audience > *capacity) {
+        return Err(PerformanceError::OverCapacity(perf.audience, capacity));
+    }
     let mut result = PerformanceData {
         audience: perf.audience,
+        date: perf.date,
+        venue: perf.venue.clone(),
         ..Default::default()
     };
-    let calculator = create_performance_calculator(perf, play_for(perf, plays));
-    result.amount = calculator.get_amount();
-    result.total_credits = calculator.get_volume_credits();
-    result.play = calculator.get_play().clone();
-    result
-}
-// The factory function determines which subclass instance to return.
-fn create_performance_calculator(perf: &Performance, play: Play) -> Box<dyn PerformanceCalculator> {
-    match play.kind.as_str() {
-        "tragedy" => Box::new(TragedyCalculator {
-            base: PerformanceCalculatorBase {
-                performance: perf.clone(),
-                play,
-            },
-        }),
-        "comedy" => Box::new(ComedyCalculator {
-            base: PerformanceCalculatorBase {
-                performance: perf.clone(),
-                play,
-            },
-        }),
-        _ => panic!("unknown type: {}", play.kind),
+    let calculator = create_performance_calculator(perf, play_for(perf, plays)?, registry)?;
+    result.amount = calculator.get_amount()?;
+    result.components = calculator.get_components()?;
+    if sum_components(&result.components, result.amount.currency)? != result.amount {
+        return Err(PerformanceError::InconsistentBreakdown);
     }
+    result.total_credits = calculator
+        .get_volume_credits()
+        .ok_or(PerformanceError::Overflow)?;
+    result.play = calculator.get_play().clone();
+    Ok(result)
+}
+// The factory function looks the play's kind up in the registry, so new kinds
+// can be registered without editing it.
+fn create_performance_calculator(
+    perf: &Performance,
+    play: Play,
+    registry: &CalculatorRegistry,
+) -> Result<Box<dyn PerformanceCalculator>, PerformanceError> {
+    let kind = play.kind.clone();
+    registry.create(perf, play).ok_or_else(|| {
+        PerformanceError::UnknownPlayKind(
+            kind,
+            registry.kinds().into_iter().map(String::from).collect(),
+        )
+    })
 }
 
-fn total_amount(statement_data: &StatementData) -> u32 {
-    statement_data
+// The statement is billed in the currency of its first performance; a
+// performance priced in another currency is reported as a mismatch.
+fn total_money(
+    statement_data: &StatementData,
+    value: impl Fn(&PerformanceData) -> Money,
+) -> Result<Money, StatementError> {
+    let currency = statement_data
         .performances
-        .iter()
-        .map(|p| p.amount)
-        .sum::<u32>()
+        .first()
+        .map_or_else(Currency::default, |p| p.amount.currency);
+    statement_data.performances.iter().enumerate().try_fold(
+        Money::zero(currency),
+        |total, (index, perf)| {
+            total
+                .checked_add(value(perf))
+                .map_err(|err| PerformanceError::from(err).at(&statement_data.customer, index))
+        },
+    )
 }
 
-fn total_volume_credits(statement_data: &StatementData) -> u32 {
+fn total_volume_credits(statement_data: &StatementData) -> Result<u32, StatementError> {
     statement_data
         .performances
         .iter()
-        .map(|p| p.total_credits)
-        .sum::<u32>()
+        .enumerate()
+        .try_fold(0u32, |total, (index, perf)| {
+            total
+                .checked_add(perf.total_credits)
+                .ok_or_else(|| StatementError::Overflow {
+                    customer: statement_data.customer.clone(),
+                    performance: index,
+                })
+        })
 }
 
 #[derive(Debug, Clone, Default)]
@@ -89,59 +500,52 @@ pub(crate) struct PerformanceCalculatorBase {
     pub performance: Performance,
     pub play: Play,
 }
-trait PerformanceCalculator {
-    fn audience(&self) -> i32;
+pub(crate) trait PerformanceCalculator {
+    fn audience(&self) -> u32;
+
+    fn get_amount(&self) -> Result<Money, MoneyError>;
 
-    fn get_amount(&self) -> u32;
+    /// The itemized breakdown of [`get_amount`](Self::get_amount); the
+    /// components must add up to it.
+    fn get_components(&self) -> Result<Vec<PriceComponent>, MoneyError> {
+        Ok(vec![PriceComponent::new(
+            "performance fee",
+            self.get_amount()?,
+        )])
+    }
 
     fn get_play(&self) -> &Play;
 
-    fn get_volume_credits(&self) -> u32 {
-        self.base_volume_credits()
+    /// The credits earned, or `None` if they overflow.
+    fn get_volume_credits(&self) -> Option<u32> {
+        Some(self.base_volume_credits())
     }
 
     fn base_volume_credits(&self) -> u32 {
-        (self.audience() - 30).max(0) as u32
+        self.audience().saturating_sub(30)
     }
 }
 
-struct TragedyCalculator {
-    base: PerformanceCalculatorBase,
+/// The calculator for kinds priced by a [`PricingRule`], built by
+/// [`CalculatorRegistry::from_rules`].
+pub(crate) struct RuleCalculator {
+    pub base: PerformanceCalculatorBase,
+    pub rule: PricingRule,
 }
-impl PerformanceCalculator for TragedyCalculator {
-    fn get_amount(&self) -> u32 {
-        let mut result = 40000;
-        if self.audience() > 30 {
-            result += 1000 * (self.audience() - 30) as u32;
-        }
-        result
-    }
-
-    fn audience(&self) -> i32 {
-        self.base.performance.audience as i32
+impl PerformanceCalculator for RuleCalculator {
+    fn get_amount(&self) -> Result<Money, MoneyError> {
+        self.rule
+            .amount_on(self.base.performance.audience, self.base.performance.date)
     }
-    fn get_play(&self) -> &Play {
-        &self.base.play
-    }
-}
-
-struct ComedyCalculator {
-    base: PerformanceCalculatorBase,
-}
-impl PerformanceCalculator for ComedyCalculator {
-    fn get_amount(&self) -> u32 {
-        let mut result = 30000;
-        if self.audience() > 20 {
-            result += 10000 + 500 * (self.audience() - 20) as u32;
-        }
-        result += 300 * self.audience() as u32;
-        result
+    fn get_components(&self) -> Result<Vec<PriceComponent>, MoneyError> {
+        self.rule
+            .components(self.base.performance.audience, self.base.performance.date)
     }
-    fn get_volume_credits(&self) -> u32 {
-        self.base_volume_credits() + self.audience() as u32 / 5
+    fn get_volume_credits(&self) -> Option<u32> {
+        self.rule.volume_credits(self.base.performance.audience)
     }
-    fn audience(&self) -> i32 {
-        self.base.performance.audience as i32
+    fn audience(&self) -> u32 {
+        self.base.performance.audience
     }
     fn get_play(&self) -> &Play {
         &self.base.play
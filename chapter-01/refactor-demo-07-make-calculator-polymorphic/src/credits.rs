@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::{
+    error::StatementError,
+    exchange::Rate,
+    money::{Money, MoneyError},
+    read_json,
+};
+
+/// How volume credits turn into discounts, as found in `credits.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RedemptionPolicy {
+    /// Discount per credit, in the minor unit of the statement currency.
+    pub credit_value: u32,
+    /// The largest share of the amount owed a discount may cover, in percent.
+    #[serde(default)]
+    pub max_discount_percent: Option<Rate>,
+}
+
+// One credit is worth one dollar, with no cap.
+impl Default for RedemptionPolicy {
+    fn default() -> Self {
+        RedemptionPolicy {
+            credit_value: 100,
+            max_discount_percent: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Redemption {
+    /// Credits actually used, which the cap may make fewer than requested.
+    pub credits: u32,
+    pub discount: Money,
+}
+
+impl RedemptionPolicy {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, StatementError> {
+        read_json(path)
+    }
+
+    /// Redeems up to `requested` credits against `owed`. The caller checks
+    /// that the customer holds that many.
+    pub fn redeem(&self, requested: u32, owed: Money) -> Result<Redemption, MoneyError> {
+        if requested == 0 || self.credit_value == 0 || owed.minor_units <= 0 {
+            return Ok(Redemption {
+                credits: 0,
+                discount: Money::zero(owed.currency),
+            });
+        }
+        let cap = match self.max_discount_percent {
+            Some(percent) => {
+                let hundred = 100 * 10i128.pow(percent.scale);
+                let cap = i128::from(owed.minor_units)
+                    .checked_mul(percent.digits)
+                    .ok_or(MoneyError::Overflow)?
+                    / hundred;
+                cap.min(i128::from(owed.minor_units))
+            }
+            None => i128::from(owed.minor_units),
+        };
+        let affordable = cap / i128::from(self.credit_value);
+        let credits = u32::try_from(affordable.min(i128::from(requested)))
+            .map_err(|_| MoneyError::Overflow)?;
+        let discount =
+            Money::new(self.credit_value.into(), owed.currency).checked_mul(credits.into())?;
+        Ok(Redemption { credits, discount })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Currency;
+
+    #[test]
+    fn test_discount_is_capped() {
+        let policy = RedemptionPolicy {
+            credit_value: 500,
+            max_discount_percent: "10".parse().ok(),
+        };
+        let owed = Money::new(173000, Currency::Usd);
+
+        let within_cap = policy.redeem(20, owed).unwrap();
+        assert_eq!(within_cap.credits, 20);
+        assert_eq!(within_cap.discount.to_string(), "$100.00");
+
+        let capped = policy.redeem(47, owed).unwrap();
+        assert_eq!(capped.credits, 34);
+        assert_eq!(capped.discount.to_string(), "$170.00");
+    }
+
+    #[test]
+    fn test_overflow_is_an_error() {
+        let policy = RedemptionPolicy {
+            credit_value: 1,
+            max_discount_percent: "99999999999999999999.5".parse().ok(),
+        };
+        assert_eq!(
+            policy.redeem(1, Money::new(i64::MAX, Currency::Usd)),
+            Err(MoneyError::Overflow)
+        );
+    }
+}
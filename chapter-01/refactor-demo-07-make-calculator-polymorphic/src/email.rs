@@ -0,0 +1,631 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    company::Company,
+    create_statement_data::StatementData,
+    date::Date,
+    error::StatementError,
+    locale::{self, MessageArg},
+    render::{HtmlRenderer, PdfRenderer, StatementRenderer, TextRenderer},
+};
+
+mod smtp;
+
+pub(crate) use smtp::SmtpSender;
+
+/// An address with an optional display name, as in
+/// `BigCo Accounts <accounts@bigco.example>`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "MailboxFields")]
+pub(crate) struct Mailbox {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+struct MailboxFields {
+    #[serde(default)]
+    name: Option<String>,
+    email: String,
+}
+
+/// Mailboxes end up in headers, so a line break in one would start a header
+/// of its own.
+impl TryFrom<MailboxFields> for Mailbox {
+    type Error = String;
+
+    fn try_from(fields: MailboxFields) -> Result<Self, Self::Error> {
+        let mailbox = Mailbox {
+            name: fields.name,
+            email: fields.email,
+        };
+        if mailbox.is_valid() {
+            Ok(mailbox)
+        } else {
+            Err(format!(
+                "control character in mailbox {:?}",
+                mailbox.to_string()
+            ))
+        }
+    }
+}
+
+/// A file sent along with the message body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Attachment {
+    pub filename: String,
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// A statement ready to be written out as an RFC 5322 message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Email {
+    pub from: Mailbox,
+    pub to: Mailbox,
+    pub subject: String,
+    /// Seconds since the Unix epoch, for the `Date` header.
+    pub date: u64,
+    /// Without the angle brackets.
+    pub message_id: String,
+    pub text: String,
+    pub html: String,
+    pub attachments: Vec<Attachment>,
+}
+
+/// How statements are turned into emails.
+#[derive(Debug, Clone)]
+pub(crate) struct EmailOptions {
+    pub from: Mailbox,
+    /// `{customer}` and `{invoice}` are replaced by the customer and invoice
+    /// id. Without one the subject is the localised statement title.
+    pub subject: Option<String>,
+    pub attach_pdf: bool,
+    /// Passed on to the renderers of the text, HTML and PDF parts.
+    pub detailed: bool,
+    /// The letterhead of the HTML part and the PDF attachment.
+    pub letterhead: Option<Company>,
+}
+
+/// Something that delivers emails, such as an SMTP server.
+pub(crate) trait MailSender {
+    fn send(&self, email: &Email) -> Result<(), SendError>;
+}
+
+/// Why a message could not be delivered.
+#[derive(Debug)]
+pub(crate) enum SendError {
+    Io(io::Error),
+    /// The server answered `command` with an unexpected `reply`.
+    Rejected {
+        command: String,
+        reply: String,
+    },
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Io(err) => write!(f, "connection failed: {err}"),
+            SendError::Rejected { command, reply } => {
+                write!(f, "server rejected {command}: {reply}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SendError::Io(err) => Some(err),
+            SendError::Rejected { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for SendError {
+    fn from(err: io::Error) -> Self {
+        SendError::Io(err)
+    }
+}
+
+impl Mailbox {
+    /// The company's statements come from its own address, if it has one.
+    pub fn from_company(company: &Company) -> Option<Self> {
+        Some(Mailbox {
+            name: Some(company.name.clone()),
+            email: company.email.clone()?,
+        })
+    }
+
+    fn is_valid(&self) -> bool {
+        !has_control(&self.email) && !self.name.as_deref().is_some_and(has_control)
+    }
+
+    // The part after the `@`, for message ids.
+    fn domain(&self) -> &str {
+        self.email
+            .rsplit_once('@')
+            .map_or("localhost", |(_, domain)| domain)
+    }
+}
+
+impl fmt::Display for Mailbox {
+    /// Formats the mailbox for a header, encoding or quoting the name as
+    /// needed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name.as_deref() {
+            None | Some("") => write!(f, "<{}>", self.email),
+            Some(name) if !name.is_ascii() => {
+                write!(f, "{} <{}>", encode_words(name), self.email)
+            }
+            Some(name) if name.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ') => {
+                write!(f, "{name} <{}>", self.email)
+            }
+            Some(name) => {
+                let quoted = name.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "\"{quoted}\" <{}>", self.email)
+            }
+        }
+    }
+}
+
+// Both boundaries start with `=_`, which neither quoted-printable nor base64
+// output can contain, so no body line can be mistaken for one.
+const MIXED_BOUNDARY: &str = "=_mixed";
+const ALTERNATIVE_BOUNDARY: &str = "=_alternative";
+
+impl Email {
+    /// The complete message with CRLF line endings, as a `.eml` file holds it.
+    pub fn to_eml(&self) -> String {
+        let mut eml = String::new();
+        let mut header = |name: &str, value: &str| {
+            eml += &fold_header(name, value);
+        };
+        header("From", &self.from.to_string());
+        header("To", &self.to.to_string());
+        header("Subject", &encode_words(&self.subject));
+        header("Date", &rfc5322_date(self.date));
+        header("Message-ID", &format!("<{}>", self.message_id));
+        header("MIME-Version", "1.0");
+
+        let alternative = format!(
+            "Content-Type: multipart/alternative; boundary=\"{ALTERNATIVE_BOUNDARY}\"\r\n\r\n\
+             --{ALTERNATIVE_BOUNDARY}\r\n{}\
+             --{ALTERNATIVE_BOUNDARY}\r\n{}\
+             --{ALTERNATIVE_BOUNDARY}--\r\n",
+            text_part("text/plain", &self.text),
+            text_part("text/html", &self.html),
+        );
+        if self.attachments.is_empty() {
+            return eml + &alternative;
+        }
+        eml += &format!(
+            "Content-Type: multipart/mixed; boundary=\"{MIXED_BOUNDARY}\"\r\n\r\n\
+             --{MIXED_BOUNDARY}\r\n{alternative}"
+        );
+        for attachment in &self.attachments {
+            eml += &format!(
+                "--{MIXED_BOUNDARY}\r\n\
+                 Content-Type: {}; name=\"{}\"\r\n\
+                 Content-Transfer-Encoding: base64\r\n\
+                 Content-Disposition: attachment; filename=\"{}\"\r\n\r\n{}",
+                attachment.content_type,
+                attachment.filename,
+                attachment.filename,
+                base64(&attachment.data)
+            );
+        }
+        eml + &format!("--{MIXED_BOUNDARY}--\r\n")
+    }
+}
+
+fn text_part(content_type: &str, body: &str) -> String {
+    format!(
+        "Content-Type: {content_type}; charset=utf-8\r\n\
+         Content-Transfer-Encoding: quoted-printable\r\n\r\n{}\r\n",
+        quoted_printable(body)
+    )
+}
+
+/// Builds the email for a statement addressed to the invoice's contact.
+/// `stem` names the attachments, as given by `render::file_stem`. `date` is in
+/// seconds since the Unix epoch.
+pub(crate) fn compose(
+    data: &StatementData,
+    stem: &str,
+    options: &EmailOptions,
+    date: u64,
+) -> Result<Email, StatementError> {
+    let to = data
+        .contact
+        .clone()
+        .ok_or_else(|| StatementError::MissingContact {
+            customer: data.customer.clone(),
+        })?;
+    let subject = match &options.subject {
+        Some(subject) => subject
+            .replace("{customer}", &data.customer)
+            .replace("{invoice}", data.invoice_id.as_deref().unwrap_or("")),
+        // Every catalogue has the statement title.
+        None => locale::message(
+            data.locale,
+            "statement-for",
+            &[("customer", MessageArg::Text(data.customer.clone()))],
+        )
+        .unwrap_or_default(),
+    };
+    let invalid = |header| StatementError::InvalidHeader {
+        customer: data.customer.clone(),
+        header,
+    };
+    if has_control(&subject) {
+        return Err(invalid("subject"));
+    }
+    if !options.from.is_valid() {
+        return Err(invalid("sender"));
+    }
+    if !to.is_valid() {
+        return Err(invalid("recipient"));
+    }
+    let text = TextRenderer {
+        detailed: options.detailed,
+    }
+    .render(data)?;
+    let html = HtmlRenderer {
+        detailed: options.detailed,
+        letterhead: options.letterhead.clone(),
+        // Mail clients tend to drop inline SVG.
+        charts: false,
+    }
+    .render(data)?;
+    let mut attachments = Vec::new();
+    if options.attach_pdf {
+        let pdf = PdfRenderer {
+            detailed: options.detailed,
+            letterhead: options.letterhead.clone(),
+        }
+        .render(data)?;
+        attachments.push(Attachment {
+            filename: format!("{stem}.pdf"),
+            content_type: "application/pdf",
+            data: pdf.into_bytes(),
+        });
+    }
+    Ok(Email {
+        message_id: format!("{stem}.{date}@{}", options.from.domain()),
+        from: options.from.clone(),
+        to,
+        subject,
+        date,
+        text,
+        html,
+        attachments,
+    })
+}
+
+/// Writes the email to `dir` as `<stem>.eml`, returning the path.
+pub(crate) fn write_eml(
+    dir: impl AsRef<Path>,
+    stem: &str,
+    email: &Email,
+) -> Result<PathBuf, StatementError> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).map_err(|err| StatementError::write(dir, err))?;
+    let path = dir.join(format!("{stem}.eml"));
+    fs::write(&path, email.to_eml()).map_err(|err| StatementError::write(&path, err))?;
+    Ok(path)
+}
+
+/// `Fri, 01 Mar 2024 09:30:00 +0000`, always in UTC.
+fn rfc5322_date(seconds: u64) -> String {
+    const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let date = Date::from_unix_seconds(seconds);
+    let time = seconds % 86_400;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+        DAYS[date.weekday() as usize],
+        date.day(),
+        MONTHS[date.month() as usize - 1],
+        date.year(),
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn has_control(text: &str) -> bool {
+    text.chars().any(char::is_control)
+}
+
+/// The `name: value` header line, folded at spaces into lines of at most 78
+/// characters where the words allow it.
+fn fold_header(name: &str, value: &str) -> String {
+    let mut header = format!("{name}:");
+    let mut width = header.len();
+    let mut first = true;
+    for word in value.split(' ') {
+        if !first && width + 1 + word.len() > 78 {
+            header += "\r\n";
+            width = 0;
+        }
+        header += " ";
+        header += word;
+        width += 1 + word.len();
+        first = false;
+    }
+    header + "\r\n"
+}
+
+/// Header text as is when it is ASCII, otherwise as RFC 2047 encoded words
+/// separated by spaces, which the header can be folded at.
+fn encode_words(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_string();
+    }
+    // 45 bytes encode to 60 characters, which with `=?utf-8?B?` and `?=`
+    // keeps each word within the 75 RFC 2047 allows.
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in text.chars() {
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(format!("=?utf-8?B?{}?=", base64_line(chunk.as_bytes())));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(format!("=?utf-8?B?{}?=", base64_line(chunk.as_bytes())));
+    words.join(" ")
+}
+
+/// RFC 2045 quoted-printable with CRLF line endings and lines of at most 76
+/// characters.
+fn quoted_printable(text: &str) -> String {
+    let mut lines = Vec::new();
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let bytes = line.as_bytes();
+        let mut encoded = String::new();
+        let mut width = 0;
+        for (index, &byte) in bytes.iter().enumerate() {
+            let last = index + 1 == bytes.len();
+            // Trailing whitespace would be stripped in transit.
+            let literal = matches!(byte, b'!'..=b'<' | b'>'..=b'~')
+                || (matches!(byte, b' ' | b'\t') && !last);
+            let piece = if literal {
+                char::from(byte).to_string()
+            } else {
+                format!("={byte:02X}")
+            };
+            // Leave room for the `=` of a soft line break, unless this piece
+            // ends the line.
+            let limit = if last { 76 } else { 75 };
+            if width + piece.len() > limit {
+                encoded.push_str("=\r\n");
+                width = 0;
+            }
+            width += piece.len();
+            encoded.push_str(&piece);
+        }
+        lines.push(encoded);
+    }
+    lines.join("\r\n")
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_line(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(
+                    BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize],
+                ));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Base64 in lines of 76 characters, each ending in CRLF.
+fn base64(data: &[u8]) -> String {
+    data.chunks(57)
+        .map(|chunk| base64_line(chunk) + "\r\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::{Currency, Money};
+
+    fn options() -> EmailOptions {
+        EmailOptions {
+            from: Mailbox {
+                name: Some("Bankside Players Ltd".to_string()),
+                email: "box-office@bankside-players.example".to_string(),
+            },
+            subject: None,
+            attach_pdf: false,
+            detailed: false,
+            letterhead: None,
+        }
+    }
+
+    fn data() -> StatementData {
+        StatementData {
+            customer: "BigCo".to_string(),
+            invoice_id: Some("INV 42".to_string()),
+            contact: Some(Mailbox {
+                name: Some("Zoë Müller".to_string()),
+                email: "accounts@bigco.example".to_string(),
+            }),
+            total_amount: Money::new(173000, Currency::Usd),
+            total_gross: Money::new(173000, Currency::Usd),
+            balance_due: Money::new(173000, Currency::Usd),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64_line(b"Ma"), "TWE=");
+        assert_eq!(base64_line(b"Man is"), "TWFuIGlz");
+        assert_eq!(
+            base64(&[0; 58]).lines().map(str::len).collect::<Vec<_>>(),
+            [76, 4]
+        );
+        assert_eq!(
+            quoted_printable("Café = 5€ \nok"),
+            "Caf=C3=A9 =3D 5=E2=82=AC=20\r\nok"
+        );
+        let long = quoted_printable(&"x".repeat(100));
+        assert_eq!(long, format!("{}=\r\n{}", "x".repeat(75), "x".repeat(25)));
+        assert_eq!(encode_words("Statement"), "Statement");
+        assert_eq!(encode_words("Relevé"), "=?utf-8?B?UmVsZXbDqQ==?=");
+        assert!(
+            encode_words(&"é".repeat(40))
+                .split(' ')
+                .all(|word| word.len() <= 75)
+        );
+        let words = "word ".repeat(30);
+        let subject = fold_header("Subject", &words);
+        assert!(subject.split("\r\n").all(|line| line.len() <= 78));
+        // Unfolding gives the header back.
+        assert_eq!(
+            subject.replace("\r\n ", " "),
+            format!("Subject: {words}\r\n")
+        );
+        assert_eq!(
+            rfc5322_date(1_709_285_400),
+            "Fri, 01 Mar 2024 09:30:00 +0000"
+        );
+    }
+
+    #[test]
+    fn test_mailbox_headers() {
+        let mailbox = |name: Option<&str>| Mailbox {
+            name: name.map(str::to_string),
+            email: "a@b.example".to_string(),
+        };
+        assert_eq!(mailbox(None).to_string(), "<a@b.example>");
+        assert_eq!(mailbox(Some("BigCo")).to_string(), "BigCo <a@b.example>");
+        assert_eq!(
+            mailbox(Some("Acme, \"Inc.\"")).to_string(),
+            "\"Acme, \\\"Inc.\\\"\" <a@b.example>"
+        );
+        assert_eq!(
+            mailbox(Some("Zoë")).to_string(),
+            "=?utf-8?B?Wm/Dqw==?= <a@b.example>"
+        );
+    }
+
+    #[test]
+    fn test_headers_cannot_be_injected() {
+        let mailbox = serde_json::from_str::<Mailbox>(
+            r#"{"name": "BigCo\r\nBcc: all@example.com", "email": "a@b.example"}"#,
+        );
+        assert!(
+            mailbox
+                .unwrap_err()
+                .to_string()
+                .starts_with("control character in mailbox")
+        );
+        assert!(serde_json::from_str::<Mailbox>(r#"{"email": "a@b.example\n"}"#).is_err());
+
+        let data = StatementData {
+            customer: "BigCo\r\nBcc: all@example.com".to_string(),
+            ..data()
+        };
+        let options = EmailOptions {
+            subject: Some("Statement for {customer}".to_string()),
+            ..options()
+        };
+        assert!(matches!(
+            compose(&data, "statement-bigco-1", &options, 0),
+            Err(StatementError::InvalidHeader {
+                header: "subject",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_eml_has_text_and_html_alternatives() {
+        let email = compose(&data(), "statement-inv-42", &options(), 1_709_285_400).unwrap();
+        assert_eq!(email.subject, "Statement for BigCo");
+        let eml = email.to_eml();
+        assert!(eml.starts_with(
+            "From: Bankside Players Ltd <box-office@bankside-players.example>\r\n\
+             To: =?utf-8?B?Wm/DqyBNw7xsbGVy?= <accounts@bigco.example>\r\n\
+             Subject: Statement for BigCo\r\n\
+             Date: Fri, 01 Mar 2024 09:30:00 +0000\r\n\
+             Message-ID: <statement-inv-42.1709285400@bankside-players.example>\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: multipart/alternative; boundary=\"=_alternative\"\r\n\r\n\
+             --=_alternative\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+             Statement for BigCo\r\nAmount owed is $1730.00\r\n"
+        ));
+        assert!(eml.contains("\r\n--=_alternative\r\nContent-Type: text/html; charset=utf-8\r\n"));
+        assert!(eml.ends_with("\r\n--=_alternative--\r\n"));
+        assert!(!eml.contains("multipart/mixed"));
+        assert!(eml.lines().all(|line| line.len() <= 76));
+        assert!(eml.split("\r\n").all(|line| !line.contains('\n')));
+    }
+
+    #[test]
+    fn test_pdf_attachment_and_subject() {
+        let options = EmailOptions {
+            subject: Some("Invoice {invoice} for {customer}".to_string()),
+            attach_pdf: true,
+            ..options()
+        };
+        let email = compose(&data(), "statement-inv-42", &options, 0).unwrap();
+        assert_eq!(email.subject, "Invoice INV 42 for BigCo");
+        assert_eq!(email.attachments[0].filename, "statement-inv-42.pdf");
+        assert!(email.attachments[0].data.starts_with(b"%PDF-"));
+        let eml = email.to_eml();
+        assert!(eml.contains(
+            "Content-Type: multipart/mixed; boundary=\"=_mixed\"\r\n\r\n\
+             --=_mixed\r\n\
+             Content-Type: multipart/alternative; boundary=\"=_alternative\"\r\n"
+        ));
+        assert!(eml.contains(
+            "--=_alternative--\r\n\
+             --=_mixed\r\n\
+             Content-Type: application/pdf; name=\"statement-inv-42.pdf\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             Content-Disposition: attachment; filename=\"statement-inv-42.pdf\"\r\n\r\n\
+             JVBERi0"
+        ));
+        assert!(eml.ends_with("\r\n--=_mixed--\r\n"));
+
+        let no_contact = StatementData {
+            contact: None,
+            ..data()
+        };
+        assert!(matches!(
+            compose(&no_contact, "statement-inv-42", &options, 0),
+            Err(StatementError::MissingContact { customer }) if customer == "BigCo"
+        ));
+    }
+}
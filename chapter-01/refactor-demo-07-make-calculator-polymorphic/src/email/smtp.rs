@@ -0,0 +1,248 @@
+//! A minimal SMTP client: plain text, no authentication, one message per
+//! connection. Enough for a local relay or a test server.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::{Email, MailSender, SendError};
+
+/// How long the server gets to accept, answer or take each write, unless
+/// told otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends emails through the SMTP server at `address`, e.g. `localhost:25`.
+#[derive(Debug, Clone)]
+pub(crate) struct SmtpSender {
+    pub address: String,
+    /// The name this client gives in `EHLO`.
+    pub hostname: String,
+    /// How long to wait for the server at each step; must not be zero.
+    pub timeout: Duration,
+}
+
+impl SmtpSender {
+    pub fn new(address: impl Into<String>) -> Self {
+        SmtpSender {
+            address: address.into(),
+            hostname: "localhost".to_string(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    // Tries each address `address` resolves to, as `TcpStream::connect`
+    // does, but gives up on each after the timeout.
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last_error = None;
+        for address in self.address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} resolves to no address", self.address),
+            )
+        }))
+    }
+}
+
+struct Session {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Session {
+    // Reads a possibly multi-line reply, failing unless its code is `expected`.
+    fn expect(&mut self, command: &str, expected: &str) -> Result<(), SendError> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(SendError::Rejected {
+                    command: command.to_string(),
+                    reply: "connection closed".to_string(),
+                });
+            }
+            reply += line.trim_end();
+            // `250-` continues a reply, `250 ` ends it.
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+            reply.push(' ');
+        }
+        if reply.starts_with(expected) {
+            Ok(())
+        } else {
+            Err(SendError::Rejected {
+                command: command.to_string(),
+                reply,
+            })
+        }
+    }
+
+    fn command(&mut self, command: &str, expected: &str) -> Result<(), SendError> {
+        self.writer.write_all(format!("{command}\r\n").as_bytes())?;
+        self.expect(command, expected)
+    }
+}
+
+impl MailSender for SmtpSender {
+    fn send(&self, email: &Email) -> Result<(), SendError> {
+        let stream = self.connect()?;
+        let mut session = Session {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        session.expect("connect", "220")?;
+        session.command(&format!("EHLO {}", self.hostname), "250")?;
+        session.command(&format!("MAIL FROM:<{}>", email.from.email), "250")?;
+        session.command(&format!("RCPT TO:<{}>", email.to.email), "25")?;
+        session.command("DATA", "354")?;
+        // A line starting with `.` gets another one, so that only the final
+        // `.` line ends the message.
+        let mut data = String::new();
+        for line in email.to_eml().split_inclusive("\r\n") {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+        }
+        data.push_str(".\r\n");
+        session.writer.write_all(data.as_bytes())?;
+        session.expect("DATA", "250")?;
+        session.command("QUIT", "221")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+    use crate::email::Mailbox;
+
+    // Accepts one connection, answers as a mail server would and returns
+    // everything the client sent.
+    fn stand_in_server(
+        listener: TcpListener,
+        rcpt_reply: &'static str,
+    ) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut transcript = String::new();
+            writer.write_all(b"220 stand-in ESMTP\r\n").unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                transcript += &line;
+                let reply = if in_data {
+                    if line != ".\r\n" {
+                        continue;
+                    }
+                    in_data = false;
+                    "250 queued"
+                } else if line.starts_with("EHLO") {
+                    "250-stand-in\r\n250 8BITMIME"
+                } else if line.starts_with("RCPT") {
+                    rcpt_reply
+                } else if line == "DATA\r\n" {
+                    in_data = true;
+                    "354 go ahead"
+                } else if line == "QUIT\r\n" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    "250 ok"
+                };
+                writer.write_all(format!("{reply}\r\n").as_bytes()).unwrap();
+            }
+            transcript
+        })
+    }
+
+    fn email() -> Email {
+        Email {
+            from: Mailbox {
+                name: None,
+                email: "box-office@bankside-players.example".to_string(),
+            },
+            to: Mailbox {
+                name: None,
+                email: "accounts@bigco.example".to_string(),
+            },
+            subject: "Statement for BigCo".to_string(),
+            date: 0,
+            message_id: "statement-bigco.0@bankside-players.example".to_string(),
+            text: ".hidden\n".to_string(),
+            html: String::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_sends_through_a_stand_in_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = SmtpSender::new(listener.local_addr().unwrap().to_string());
+        let server = stand_in_server(listener, "250 ok");
+        sender.send(&email()).unwrap();
+        let transcript = server.join().unwrap();
+        assert!(transcript.starts_with(
+            "EHLO localhost\r\n\
+             MAIL FROM:<box-office@bankside-players.example>\r\n\
+             RCPT TO:<accounts@bigco.example>\r\n\
+             DATA\r\n\
+             From: <box-office@bankside-players.example>\r\n"
+        ));
+        assert!(transcript.contains("\r\n\r\n..hidden\r\n"));
+        assert!(transcript.ends_with("--=_alternative--\r\n.\r\nQUIT\r\n"));
+    }
+
+    #[test]
+    fn test_rejected_recipient() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = SmtpSender::new(listener.local_addr().unwrap().to_string());
+        let server = stand_in_server(listener, "550 no such user");
+        let err = sender.send(&email()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "server rejected RCPT TO:<accounts@bigco.example>: 550 no such user"
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_silent_server_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = SmtpSender {
+            timeout: Duration::from_millis(100),
+            ..SmtpSender::new(listener.local_addr().unwrap().to_string())
+        };
+        // Accepts, then says nothing until the client has given up.
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = io::Read::read_to_end(&mut stream, &mut Vec::new());
+        });
+        match sender.send(&email()).unwrap_err() {
+            SendError::Io(err) => assert!(matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            )),
+            err => panic!("expected a timeout, got {err}"),
+        }
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use std::{fmt, io, path::PathBuf};
+
+use super::{date::Date, input::DataFormat, ledger::PostedCredits, money::Currency};
+
+/// Everything that can go wrong while producing a statement.
+///
+/// Errors raised for a performance carry the invoice customer and the index of
+/// the performance within the invoice; errors about the invoice as a whole
+/// carry only the customer. Loading errors happen before any invoice exists, so
+/// they carry the file path instead.
+#[derive(Debug)]
+pub(crate) enum StatementError {
+    MissingPlay {
+        customer: String,
+        performance: usize,
+        play_id: String,
+    },
+    UnknownPlayKind {
+        customer: String,
+        performance: usize,
+        kind: String,
+        registered: Vec<String>,
+    },
+    OverCapacity {
+        customer: String,
+        performance: usize,
+        audience: u32,
+        capacity: u32,
+    },
+    InconsistentBreakdown {
+        customer: String,
+        performance: usize,
+    },
+    Overflow {
+        customer: String,
+        performance: usize,
+    },
+    /// An invoice total, discount or conversion that does not fit.
+    TotalOverflow {
+        customer: String,
+    },
+    /// The customer's credit balance in the ledger does not fit.
+    CreditOverflow {
+        customer: String,
+    },
+    CurrencyMismatch {
+        customer: String,
+        performance: usize,
+        expected: Currency,
+        found: Currency,
+    },
+    InsufficientCredits {
+        customer: String,
+        requested: u32,
+        available: u32,
+    },
+    /// A rerun of an invoice the ledger has posted disagrees with the credits
+    /// it posted.
+    PostedCreditsDiffer {
+        customer: String,
+        invoice: String,
+        posted: PostedCredits,
+        found: PostedCredits,
+    },
+    UnknownJurisdiction {
+        customer: String,
+        jurisdiction: String,
+    },
+    MissingRate {
+        customer: String,
+        from: Currency,
+        to: Currency,
+        date: Option<Date>,
+    },
+    MissingContact {
+        customer: String,
+    },
+    /// A header of the statement email holds a line break or other control
+    /// character, which could add headers of its own.
+    InvalidHeader {
+        customer: String,
+        header: &'static str,
+    },
+    Template {
+        template: String,
+        line: usize,
+        message: String,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Write {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Json {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        source: serde_json::Error,
+    },
+    /// A YAML, TOML or CSV file that does not parse or has the wrong fields.
+    Parse {
+        path: PathBuf,
+        format: DataFormat,
+        message: String,
+    },
+}
+
+impl StatementError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        StatementError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub fn write(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        StatementError::Write {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub fn json(path: impl Into<PathBuf>, source: serde_json::Error) -> Self {
+        StatementError::Json {
+            path: path.into(),
+            line: source.line(),
+            column: source.column(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for StatementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatementError::MissingPlay {
+                customer,
+                performance,
+                play_id,
+            } => write!(
+                f,
+                "{customer}, performance #{performance}: play not found: {play_id}"
+            ),
+            StatementError::UnknownPlayKind {
+                customer,
+                performance,
+                kind,
+                registered,
+            } => write!(
+                f,
+                "{customer}, performance #{performance}: unknown type: {kind} (registered: {})",
+                registered.join(", ")
+            ),
+            StatementError::OverCapacity {
+                customer,
+                performance,
+                audience,
+                capacity,
+            } => write!(
+                f,
+                "{customer}, performance #{performance}: audience of {audience} exceeds venue capacity of {capacity}"
+            ),
+            StatementError::InconsistentBreakdown {
+                customer,
+                performance,
+            } => write!(
+                f,
+                "{customer}, performance #{performance}: price components do not add up to the amount"
+            ),
+            StatementError::Overflow {
+                customer,
+                performance,
+            } => write!(
+                f,
+                "{customer}, performance #{performance}: amount overflowed"
+            ),
+            StatementError::TotalOverflow { customer } => {
+                write!(f, "{customer}: invoice total overflowed")
+            }
+            StatementError::CreditOverflow { customer } => {
+                write!(f, "{customer}: credit balance overflowed")
+            }
+            StatementError::CurrencyMismatch {
+                customer,
+                performance,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{customer}, performance #{performance}: priced in {found}, statement is in {expected}"
+            ),
+            StatementError::InsufficientCredits {
+                customer,
+                requested,
+                available,
+            } => write!(
+                f,
+                "{customer}: cannot redeem {requested} credits, only {available} available"
+            ),
+            StatementError::PostedCreditsDiffer {
+                customer,
+                invoice,
+                posted,
+                found,
+            } => write!(
+                f,
+                "{customer}: invoice {invoice} was posted to the ledger with {posted}, \
+                 but now has {found}"
+            ),
+            StatementError::UnknownJurisdiction {
+                customer,
+                jurisdiction,
+            } => write!(f, "{customer}: unknown tax jurisdiction: {jurisdiction}"),
+            StatementError::MissingRate {
+                customer,
+                from,
+                to,
+                date,
+            } => match date {
+                Some(date) => write!(
+                    f,
+                    "{customer}: no {from} to {to} exchange rate on or before {date}"
+                ),
+                None => write!(f, "{customer}: no {from} to {to} exchange rate"),
+            },
+            StatementError::MissingContact { customer } => {
+                write!(f, "{customer}: no contact to email the statement to")
+            }
+            StatementError::InvalidHeader { customer, header } => write!(
+                f,
+                "{customer}: the email {header} contains a control character"
+            ),
+            StatementError::Template {
+                template,
+                line,
+                message,
+            } => write!(f, "template {template}, line {line}: {message}"),
+            StatementError::Io { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            StatementError::Write { path, source } => {
+                write!(f, "failed to write {}: {source}", path.display())
+            }
+            StatementError::Json {
+                path,
+                line,
+                column,
+                source,
+            } => write!(
+                f,
+                "failed to parse {} at line {line}, column {column}: {source}",
+                path.display()
+            ),
+            StatementError::Parse {
+                path,
+                format,
+                message,
+            } => write!(
+                f,
+                "failed to parse {} as {format}: {message}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StatementError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StatementError::Io { source, .. } | StatementError::Write { source, .. } => {
+                Some(source)
+            }
+            StatementError::Json { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use super::{
+    date::Date,
+    error::StatementError,
+    money::{Currency, Money, MoneyError},
+    read_json,
+};
+
+/// How a converted amount is rounded to the target currency's minor unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RoundingMode {
+    #[default]
+    HalfEven,
+    HalfUp,
+    /// Towards zero.
+    Down,
+    /// Away from zero.
+    Up,
+}
+
+impl RoundingMode {
+    /// Divides `numerator` by a positive `denominator`, rounding the quotient.
+    pub fn divide(self, numerator: i128, denominator: i128) -> i128 {
+        let quotient = numerator / denominator;
+        let remainder = numerator % denominator;
+        if remainder == 0 {
+            return quotient;
+        }
+        let away = quotient + numerator.signum();
+        let twice = 2 * remainder.abs();
+        match self {
+            RoundingMode::Down => quotient,
+            RoundingMode::Up => away,
+            RoundingMode::HalfUp if twice >= denominator => away,
+            RoundingMode::HalfEven if twice > denominator => away,
+            RoundingMode::HalfEven if twice == denominator && quotient % 2 != 0 => away,
+            RoundingMode::HalfUp | RoundingMode::HalfEven => quotient,
+        }
+    }
+}
+
+/// An exact positive decimal, written as a string such as `"0.9134"` so that
+/// it never passes through a float. Used for exchange and tax rates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rate {
+    /// The rate is `digits / 10^scale`.
+    pub digits: i128,
+    pub scale: u32,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid rate {s:?}");
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty() || !(whole.chars().chain(fraction.chars())).all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let digits: i128 = format!("{whole}{fraction}")
+            .parse()
+            .map_err(|_| invalid())?;
+        if digits == 0 || fraction.len() > 18 {
+            return Err(invalid());
+        }
+        Ok(Rate {
+            digits,
+            scale: fraction.len() as u32,
+        })
+    }
+}
+
+impl Rate {
+    /// `amount * rate / 100`, for rates given as percentages.
+    pub fn percent_of(&self, amount: Money, rounding: RoundingMode) -> Result<Money, MoneyError> {
+        let numerator = i128::from(amount.minor_units)
+            .checked_mul(self.digits)
+            .ok_or(MoneyError::Overflow)?;
+        let minor = rounding.divide(numerator, 100 * 10i128.pow(self.scale));
+        i64::try_from(minor)
+            .map(|minor| Money::new(minor, amount.currency))
+            .map_err(|_| MoneyError::Overflow)
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = 10i128.pow(self.scale);
+        if self.scale == 0 {
+            return write!(f, "{}", self.digits);
+        }
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.digits / scale,
+            self.digits % scale,
+            width = self.scale as usize
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// One unit of `from` buys `rate` units of `to`, from `date` onwards.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ExchangeRate {
+    pub date: Date,
+    pub from: Currency,
+    pub to: Currency,
+    pub rate: Rate,
+}
+
+/// A converted amount together with the rate that produced it.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Conversion {
+    pub amount: Money,
+    pub rate: Rate,
+    pub rate_date: Date,
+}
+
+impl Conversion {
+    /// `1 USD = 0.925 EUR on 2024-02-01`
+    pub fn describe_rate(&self, from: Currency) -> String {
+        format!(
+            "1 {from} = {} {} on {}",
+            self.rate, self.amount.currency, self.rate_date
+        )
+    }
+}
+
+/// The local exchange-rate table, as found in `rates.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct ExchangeRates {
+    #[serde(default)]
+    pub rounding: RoundingMode,
+    pub rates: Vec<ExchangeRate>,
+}
+
+impl ExchangeRates {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, StatementError> {
+        read_json(path)
+    }
+
+    /// The latest rate from `from` to `to` published on or before `date`, or
+    /// the latest rate overall when no date is given.
+    pub fn find(&self, from: Currency, to: Currency, date: Option<Date>) -> Option<&ExchangeRate> {
+        self.rates
+            .iter()
+            .filter(|r| r.from == from && r.to == to)
+            .filter(|r| date.is_none_or(|date| r.date <= date))
+            .max_by_key(|r| r.date)
+    }
+
+    /// Converts `amount` into `to` at the rate for `date`. Returns `None` when
+    /// the table has no suitable rate.
+    pub fn convert(
+        &self,
+        amount: Money,
+        to: Currency,
+        date: Option<Date>,
+    ) -> Option<Result<Conversion, MoneyError>> {
+        let rate = self.find(amount.currency, to, date)?;
+        // minor_to = minor_from * rate * 10^to_digits / 10^from_digits
+        let numerator = i128::from(amount.minor_units)
+            .checked_mul(rate.rate.digits)
+            .and_then(|n| n.checked_mul(10i128.pow(to.minor_units())));
+        let denominator = 10i128
+            .pow(rate.rate.scale)
+            .checked_mul(10i128.pow(amount.currency.minor_units()));
+        let converted = numerator
+            .zip(denominator)
+            .map(|(n, d)| self.rounding.divide(n, d))
+            .and_then(|minor| i64::try_from(minor).ok())
+            .map(|minor| Conversion {
+                amount: Money::new(minor, to),
+                rate: rate.rate,
+                rate_date: rate.date,
+            })
+            .ok_or(MoneyError::Overflow);
+        Some(converted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(rounding: RoundingMode) -> ExchangeRates {
+        let rate = |date: &str, to, rate: &str| ExchangeRate {
+            date: date.parse().unwrap(),
+            from: Currency::Usd,
+            to,
+            rate: rate.parse().unwrap(),
+        };
+        ExchangeRates {
+            rounding,
+            rates: vec![
+                rate("2024-01-01", Currency::Eur, "0.90"),
+                rate("2024-02-01", Currency::Eur, "0.925"),
+                rate("2024-01-01", Currency::Jpy, "148.5"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_uses_rate_for_invoice_date() {
+        let rates = rates(RoundingMode::HalfEven);
+        let amount = Money::new(173000, Currency::Usd);
+
+        let january = rates
+            .convert(amount, Currency::Eur, "2024-01-31".parse().ok())
+            .unwrap()
+            .unwrap();
+        assert_eq!(january.amount.to_string(), "€1557.00");
+
+        let march = rates
+            .convert(amount, Currency::Eur, "2024-03-01".parse().ok())
+            .unwrap()
+            .unwrap();
+        assert_eq!(march.amount.to_string(), "€1600.25");
+        assert_eq!(march.rate.to_string(), "0.925");
+
+        assert!(
+            rates
+                .convert(amount, Currency::Eur, "2023-12-31".parse().ok())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        // $0.01 at 0.925 is 0.925 euro cents
+        let cent = Money::new(1, Currency::Usd);
+        let convert = |mode| {
+            rates(mode)
+                .convert(cent, Currency::Eur, None)
+                .unwrap()
+                .unwrap()
+                .amount
+                .minor_units
+        };
+        assert_eq!(convert(RoundingMode::HalfEven), 1);
+        assert_eq!(convert(RoundingMode::Down), 0);
+
+        // $1.01 at 148.5 is 149.985 yen
+        let yen = rates(RoundingMode::HalfUp)
+            .convert(Money::new(101, Currency::Usd), Currency::Jpy, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(yen.amount.to_string(), "¥150");
+        assert_eq!(RoundingMode::HalfEven.divide(5, 2), 2);
+        assert_eq!(RoundingMode::HalfUp.divide(-5, 2), -3);
+    }
+}
@@ -0,0 +1,351 @@
+//! Reads the play catalogue and the invoices from JSON, YAML, TOML or CSV,
+//! and invoices from newline-delimited JSON too.
+//!
+//! Every format is read into a JSON value first and deserialized from there,
+//! so all of them produce the same plays and invoices, and converting between
+//! formats keeps every field as written.
+
+mod csv;
+
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::error::StatementError;
+
+/// A file format for plays and invoices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+    Csv,
+    /// One invoice per line; see `stream`.
+    Ndjson,
+}
+
+/// Which file is read, as CSV and TOML lay the two out differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Dataset {
+    Plays,
+    Invoices,
+}
+
+impl DataFormat {
+    /// The format given by `flag`, or else by the extension of `path`. Files
+    /// with any other extension are JSON, as they always were. An `ndjson`
+    /// flag is for the invoices; the plays still go by extension.
+    pub fn detect(path: &Path, flag: Option<DataFormat>, dataset: Dataset) -> Self {
+        let flag = flag.filter(|flag| *flag != DataFormat::Ndjson || dataset == Dataset::Invoices);
+        flag.or_else(|| path.extension()?.to_str()?.parse().ok())
+            .unwrap_or(DataFormat::Json)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            DataFormat::Json => "json",
+            DataFormat::Yaml => "yaml",
+            DataFormat::Toml => "toml",
+            DataFormat::Csv => "csv",
+            DataFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl FromStr for DataFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(DataFormat::Json),
+            "yaml" | "yml" => Ok(DataFormat::Yaml),
+            "toml" => Ok(DataFormat::Toml),
+            "csv" => Ok(DataFormat::Csv),
+            "ndjson" | "jsonl" => Ok(DataFormat::Ndjson),
+            _ => Err(format!(
+                "unknown data format {s:?}, expected json, yaml, toml, csv or ndjson"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+// TOML documents are tables, so invoices go in an `invoices` array of tables.
+const TOML_INVOICES: &str = "invoices";
+
+const NDJSON_PLAYS: &str = "NDJSON holds invoices only, not plays";
+
+// TOML dates and times become the text they were written as.
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(value) => Value::String(value),
+        toml::Value::Integer(value) => Value::from(value),
+        toml::Value::Float(value) => Value::from(value),
+        toml::Value::Boolean(value) => Value::Bool(value),
+        toml::Value::Datetime(value) => Value::String(value.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, from_toml(value)))
+                .collect(),
+        ),
+    }
+}
+
+// TOML has no null; a missing field means the same.
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.iter().map(without_nulls).collect()),
+        Value::Object(members) => Value::Object(
+            members
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), without_nulls(value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// Parses `text` in `format` into a JSON value.
+pub(crate) fn read_value(
+    text: &str,
+    format: DataFormat,
+    dataset: Dataset,
+) -> Result<Value, String> {
+    match (format, dataset) {
+        (DataFormat::Json, _) => serde_json::from_str(text).map_err(|err| err.to_string()),
+        (DataFormat::Yaml, _) => serde_yaml_ng::from_str(text).map_err(|err| err.to_string()),
+        (DataFormat::Toml, _) => {
+            let table: toml::Table = text
+                .parse()
+                .map_err(|err: toml::de::Error| err.to_string().trim_end().to_string())?;
+            let value = from_toml(toml::Value::Table(table));
+            match dataset {
+                Dataset::Plays => Ok(value),
+                Dataset::Invoices => match value {
+                    Value::Object(mut members) => Ok(members
+                        .remove(TOML_INVOICES)
+                        .unwrap_or_else(|| Value::Array(Vec::new()))),
+                    value => Ok(value),
+                },
+            }
+        }
+        (DataFormat::Csv, Dataset::Plays) => csv::read_plays(text),
+        (DataFormat::Csv, Dataset::Invoices) => csv::read_invoices(text),
+        (DataFormat::Ndjson, Dataset::Invoices) => text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str::<Value>(line)
+                    .map_err(|err| format!("line {}: {err}", index + 1))
+            })
+            .collect(),
+        (DataFormat::Ndjson, Dataset::Plays) => Err(NDJSON_PLAYS.to_string()),
+    }
+}
+
+/// Writes `value` as a document in `format`.
+pub(crate) fn write_value(
+    value: &Value,
+    format: DataFormat,
+    dataset: Dataset,
+) -> Result<String, String> {
+    match (format, dataset) {
+        (DataFormat::Json, _) => serde_json::to_string_pretty(value)
+            .map(|text| text + "\n")
+            .map_err(|err| err.to_string()),
+        (DataFormat::Yaml, _) => serde_yaml_ng::to_string(value).map_err(|err| err.to_string()),
+        (DataFormat::Toml, Dataset::Plays) => {
+            toml::to_string(&without_nulls(value)).map_err(|err| err.to_string())
+        }
+        (DataFormat::Toml, Dataset::Invoices) => {
+            let document = serde_json::json!({ TOML_INVOICES: without_nulls(value) });
+            toml::to_string(&document).map_err(|err| err.to_string())
+        }
+        (DataFormat::Csv, Dataset::Plays) => csv::write_plays(value),
+        (DataFormat::Csv, Dataset::Invoices) => csv::write_invoices(value),
+        (DataFormat::Ndjson, Dataset::Invoices) => match value {
+            Value::Array(invoices) => Ok(invoices
+                .iter()
+                .map(|invoice| invoice.to_string() + "\n")
+                .collect()),
+            _ => Err("expected an array of invoices".to_string()),
+        },
+        (DataFormat::Ndjson, Dataset::Plays) => Err(NDJSON_PLAYS.to_string()),
+    }
+}
+
+/// Parses `text`, read from `path`, as `T`.
+pub(crate) fn parse<T: DeserializeOwned>(
+    path: &Path,
+    text: &str,
+    format: DataFormat,
+    dataset: Dataset,
+) -> Result<T, StatementError> {
+    // serde_json reports where in the file a field is wrong.
+    if format == DataFormat::Json {
+        return serde_json::from_str(text).map_err(|err| StatementError::json(path, err));
+    }
+    let parse_error = |message: String| StatementError::Parse {
+        path: path.to_path_buf(),
+        format,
+        message,
+    };
+    let value = read_value(text, format, dataset).map_err(parse_error)?;
+    serde_json::from_value(value).map_err(|err| parse_error(err.to_string()))
+}
+
+/// Reads `path` as `T` in the format `flag` names or its extension implies.
+pub(crate) fn load<T: DeserializeOwned>(
+    path: impl AsRef<Path>,
+    flag: Option<DataFormat>,
+    dataset: Dataset,
+) -> Result<T, StatementError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|err| StatementError::io(path, err))?;
+    parse(
+        path,
+        &text,
+        DataFormat::detect(path, flag, dataset),
+        dataset,
+    )
+}
+
+/// Reads `path` and returns it written in `to`. It must load as `T`, so that
+/// nothing is converted that statements could not be made from.
+pub(crate) fn convert<T: DeserializeOwned>(
+    path: &Path,
+    flag: Option<DataFormat>,
+    to: DataFormat,
+    dataset: Dataset,
+) -> Result<String, StatementError> {
+    let text = fs::read_to_string(path).map_err(|err| StatementError::io(path, err))?;
+    let from = DataFormat::detect(path, flag, dataset);
+    parse::<T>(path, &text, from, dataset)?;
+    let parse_error = |format, message| StatementError::Parse {
+        path: path.to_path_buf(),
+        format,
+        message,
+    };
+    let value = read_value(&text, from, dataset).map_err(|message| parse_error(from, message))?;
+    write_value(&value, to, dataset).map_err(|message| parse_error(to, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Invoice, Play};
+
+    const FORMATS: [DataFormat; 4] = [
+        DataFormat::Json,
+        DataFormat::Yaml,
+        DataFormat::Toml,
+        DataFormat::Csv,
+    ];
+
+    fn invoices() -> Value {
+        json!([
+            {
+                "id": "2024-001",
+                "customer": "BigCo",
+                "date": "2024-05-01",
+                "currency": "EUR",
+                "credit_balance": 12,
+                "buyer": { "name": "BigCo Ltd", "country": "GB", "city": "London" },
+                "contact": { "name": "Zoë, Accounts", "email": "accounts@bigco.example" },
+                "performances": [
+                    { "play_id": "hamlet", "audience": 55, "date": "2024-04-02", "venue": "Globe" },
+                    { "play_id": "as-like", "audience": 35, "capacity": 40 }
+                ]
+            },
+            { "customer": "Acme \"Theatricals\"", "performances": [] }
+        ])
+    }
+
+    #[test]
+    fn test_formats_round_trip() {
+        let plays = serde_json::from_str(include_str!("../../plays.json")).unwrap();
+        for format in FORMATS {
+            for (dataset, value) in [(Dataset::Plays, &plays), (Dataset::Invoices, &invoices())] {
+                let text = write_value(value, format, dataset).unwrap();
+                assert_eq!(
+                    read_value(&text, format, dataset).as_ref(),
+                    Ok(value),
+                    "{format}:\n{text}"
+                );
+            }
+            let text = write_value(&invoices(), format, Dataset::Invoices).unwrap();
+            let loaded: Vec<Invoice> =
+                parse(Path::new("invoices"), &text, format, Dataset::Invoices).unwrap();
+            assert_eq!(loaded[0].performances[1].capacity, Some(40));
+        }
+    }
+
+    #[test]
+    fn test_format_detection_and_errors() {
+        let detect = |path: &str, flag| DataFormat::detect(Path::new(path), flag, Dataset::Plays);
+        assert_eq!(detect("plays.YML", None), DataFormat::Yaml);
+        assert_eq!(detect("plays.txt", None), DataFormat::Json);
+        assert_eq!(detect("season.jsonl", None), DataFormat::Ndjson);
+        assert_eq!(detect("plays.json", Some(DataFormat::Csv)), DataFormat::Csv);
+        assert_eq!(
+            detect("plays.json", Some(DataFormat::Ndjson)),
+            DataFormat::Json
+        );
+        assert!("xml".parse::<DataFormat>().is_err());
+
+        let plays: HashMap<String, Play> = parse(
+            Path::new("plays.yaml"),
+            "hamlet:\n  name: Hamlet\n  type: tragedy\n",
+            DataFormat::Yaml,
+            Dataset::Plays,
+        )
+        .unwrap();
+        assert_eq!(plays["hamlet"].kind, "tragedy");
+
+        let err = parse::<HashMap<String, Play>>(
+            Path::new("plays.toml"),
+            "[hamlet]\nname = \"Hamlet\"\n",
+            DataFormat::Toml,
+            Dataset::Plays,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to parse plays.toml as toml: missing field `type`"
+        );
+        let ndjson = write_value(&invoices(), DataFormat::Ndjson, Dataset::Invoices).unwrap();
+        assert_eq!(ndjson.lines().count(), 2);
+        assert_eq!(
+            read_value(&ndjson, DataFormat::Ndjson, Dataset::Invoices),
+            Ok(invoices())
+        );
+        assert_eq!(
+            read_value("[]\n{", DataFormat::Ndjson, Dataset::Invoices),
+            Err("line 2: EOF while parsing an object at line 1 column 1".to_string())
+        );
+        assert!(read_value("", DataFormat::Ndjson, Dataset::Plays).is_err());
+        assert!(matches!(
+            convert::<Vec<Invoice>>(
+                &PathBuf::from("missing.csv"),
+                None,
+                DataFormat::Json,
+                Dataset::Invoices
+            ),
+            Err(StatementError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,378 @@
+//! Plays and invoices as CSV, the way the box office exports them.
+//!
+//! Plays have one row per play: an `id` column and one column per field.
+//! Invoices have one row per performance. The performance fields have columns
+//! of their own, and the remaining columns describe the invoice, repeated on
+//! each of its rows. Rows with the same invoice columns belong to the same
+//! invoice, wherever they are in the file. An invoice without performances is
+//! a row with empty performance columns. Nested fields such as the buyer's
+//! address are flattened into dotted columns, e.g. `buyer.city`.
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use super::super::render::csv_field;
+
+/// Performance fields and their columns. The performance date has its own
+/// name so that it does not clash with the invoice date.
+const PERFORMANCE_COLUMNS: [(&str, &str); 5] = [
+    ("play_id", "play_id"),
+    ("audience", "audience"),
+    ("date", "performance_date"),
+    ("venue", "venue"),
+    ("capacity", "capacity"),
+];
+
+/// Columns holding whole numbers; every other column holds text.
+const NUMBER_COLUMNS: [&str; 4] = ["audience", "capacity", "credit_balance", "redeem_credits"];
+
+/// Records of fields, each with the line it starts on.
+type Records = Vec<(usize, Vec<String>)>;
+
+/// Splits CSV text into records (RFC 4180). Blank lines are skipped.
+fn records(text: &str) -> Result<Records, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|field| !field.is_empty()) || record.len() > 1 {
+                    records.push((start, std::mem::take(&mut record)));
+                } else {
+                    record.clear();
+                }
+                line += 1;
+                start = line;
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if quoted {
+        return Err(format!("line {start}: unterminated quoted field"));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+/// The scalar leaves of `value` under dotted keys, skipping nulls.
+fn flatten(prefix: &str, value: &Value, cells: &mut Vec<(String, String)>) -> Result<(), String> {
+    match value {
+        Value::Null => {}
+        Value::Bool(value) => cells.push((prefix.to_string(), value.to_string())),
+        Value::Number(value) => cells.push((prefix.to_string(), value.to_string())),
+        Value::String(value) => cells.push((prefix.to_string(), value.clone())),
+        Value::Array(_) => return Err(format!("{prefix} is a list, which CSV cannot hold")),
+        Value::Object(members) => {
+            for (key, value) in members {
+                let key = match prefix {
+                    "" => key.clone(),
+                    _ => format!("{prefix}.{key}"),
+                };
+                flatten(&key, value, cells)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets the dotted `key` of `object` to `value`, creating objects on the way.
+fn insert(object: &mut Map<String, Value>, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let child = object
+                .entry(head)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(child) = child {
+                insert(child, rest, value);
+            }
+        }
+        None => {
+            object.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn cell_value(line: usize, column: &str, cell: &str) -> Result<Value, String> {
+    if !NUMBER_COLUMNS.contains(&column) {
+        return Ok(Value::String(cell.to_string()));
+    }
+    cell.parse::<u64>()
+        .map(Value::from)
+        .map_err(|_| format!("line {line}: {column} must be a whole number, found {cell:?}"))
+}
+
+/// Adds the columns of `cells` to `columns` in order of first appearance.
+fn add_columns(columns: &mut Vec<String>, cells: &[(String, String)]) {
+    for (column, _) in cells {
+        if !columns.contains(column) {
+            columns.push(column.clone());
+        }
+    }
+}
+
+fn write_row(output: &mut String, columns: &[String], cells: &[(String, String)]) {
+    let row: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            cells
+                .iter()
+                .find(|(key, _)| key == column)
+                .map_or_else(String::new, |(_, cell)| csv_field(cell))
+        })
+        .collect();
+    *output += &row.join(",");
+    output.push('\n');
+}
+
+/// The header and the rows below it, which must be as wide.
+fn header(text: &str) -> Result<(Vec<String>, Records), String> {
+    let mut records = records(text)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Err("the file is empty, expected a header row".to_string());
+    };
+    let rows: Vec<_> = records.collect();
+    for (line, row) in &rows {
+        if row.len() != header.len() {
+            return Err(format!(
+                "line {line}: expected {} fields, found {}",
+                header.len(),
+                row.len()
+            ));
+        }
+    }
+    Ok((header, rows))
+}
+
+/// Reads plays keyed by the `id` column.
+pub(crate) fn read_plays(text: &str) -> Result<Value, String> {
+    let (header, rows) = header(text)?;
+    let Some(id_column) = header.iter().position(|column| column == "id") else {
+        return Err("no id column".to_string());
+    };
+    let mut plays = Map::new();
+    for (line, row) in rows {
+        let mut play = Map::new();
+        for (column, cell) in header.iter().zip(&row) {
+            if column != "id" && !cell.is_empty() {
+                insert(&mut play, column, cell_value(line, column, cell)?);
+            }
+        }
+        let id = &row[id_column];
+        if plays.insert(id.clone(), Value::Object(play)).is_some() {
+            return Err(format!("line {line}: duplicate play id {id:?}"));
+        }
+    }
+    Ok(Value::Object(plays))
+}
+
+pub(crate) fn write_plays(plays: &Value) -> Result<String, String> {
+    let Value::Object(plays) = plays else {
+        return Err("expected an object of plays by id".to_string());
+    };
+    let mut columns = vec!["id".to_string(), "name".to_string(), "type".to_string()];
+    let mut rows = Vec::new();
+    for (id, play) in plays {
+        let mut cells = vec![("id".to_string(), id.clone())];
+        flatten("", play, &mut cells)?;
+        add_columns(&mut columns, &cells);
+        rows.push(cells);
+    }
+    let mut output = columns.join(",") + "\n";
+    for cells in &rows {
+        write_row(&mut output, &columns, cells);
+    }
+    Ok(output)
+}
+
+/// The performance field of `column`, if it is a performance column.
+fn performance_field(column: &str) -> Option<&'static str> {
+    PERFORMANCE_COLUMNS
+        .iter()
+        .find(|(_, name)| *name == column)
+        .map(|(field, _)| *field)
+}
+
+/// Reads invoices, one row per performance.
+pub(crate) fn read_invoices(text: &str) -> Result<Value, String> {
+    let (header, rows) = header(text)?;
+    let mut invoices: Vec<Map<String, Value>> = Vec::new();
+    // Invoice cells to the index of their invoice.
+    let mut groups: HashMap<Vec<String>, usize> = HashMap::new();
+    for (line, row) in rows {
+        let mut key = Vec::new();
+        let mut invoice = Map::new();
+        let mut performance = Map::new();
+        for (column, cell) in header.iter().zip(&row) {
+            match performance_field(column) {
+                Some(field) => {
+                    if !cell.is_empty() {
+                        performance.insert(field.to_string(), cell_value(line, column, cell)?);
+                    }
+                }
+                None => {
+                    key.push(cell.clone());
+                    if !cell.is_empty() {
+                        insert(&mut invoice, column, cell_value(line, column, cell)?);
+                    }
+                }
+            }
+        }
+        let index = *groups.entry(key).or_insert_with(|| {
+            invoice.insert("performances".to_string(), Value::Array(Vec::new()));
+            invoices.push(invoice);
+            invoices.len() - 1
+        });
+        if !performance.is_empty()
+            && let Some(Value::Array(performances)) = invoices[index].get_mut("performances")
+        {
+            performances.push(Value::Object(performance));
+        }
+    }
+    Ok(Value::Array(
+        invoices.into_iter().map(Value::Object).collect(),
+    ))
+}
+
+pub(crate) fn write_invoices(invoices: &Value) -> Result<String, String> {
+    let Value::Array(invoices) = invoices else {
+        return Err("expected an array of invoices".to_string());
+    };
+    let mut invoice_columns = vec!["customer".to_string()];
+    let mut performance_columns = Vec::new();
+    let mut rows = Vec::new();
+    for invoice in invoices {
+        let mut head = invoice.clone();
+        let performances = match head.as_object_mut().and_then(|m| m.remove("performances")) {
+            Some(Value::Array(performances)) => performances,
+            _ => Vec::new(),
+        };
+        let mut cells = Vec::new();
+        flatten("", &head, &mut cells)?;
+        add_columns(&mut invoice_columns, &cells);
+        if performances.is_empty() {
+            rows.push(cells.clone());
+        }
+        for performance in &performances {
+            let mut fields = Vec::new();
+            flatten("", performance, &mut fields)?;
+            let mut row = cells.clone();
+            for (field, cell) in fields {
+                let Some((_, column)) = PERFORMANCE_COLUMNS.iter().find(|(name, _)| *name == field)
+                else {
+                    return Err(format!("performance field {field} has no CSV column"));
+                };
+                row.push((column.to_string(), cell));
+            }
+            add_columns(&mut performance_columns, &row[cells.len()..]);
+            rows.push(row);
+        }
+    }
+    // Performance columns in their fixed order, after the invoice columns.
+    performance_columns.sort_by_key(|column| {
+        PERFORMANCE_COLUMNS
+            .iter()
+            .position(|(_, name)| name == column)
+    });
+    let columns: Vec<String> = invoice_columns
+        .into_iter()
+        .chain(performance_columns)
+        .collect();
+    let mut output = columns.join(",") + "\n";
+    for cells in &rows {
+        write_row(&mut output, &columns, cells);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_invoice_rows_are_grouped() {
+        let text = "customer,id,buyer.city,play_id,audience\n\
+                    BigCo,1,\"London, UK\",hamlet,55\n\
+                    Acme,2,,othello,10\r\n\
+                    \n\
+                    BigCo,1,\"London, UK\",as-like,35\n\
+                    Empty,,,,\n";
+        let invoices = read_invoices(text).unwrap();
+        assert_eq!(
+            invoices,
+            json!([
+                {
+                    "customer": "BigCo",
+                    "id": "1",
+                    "buyer": { "city": "London, UK" },
+                    "performances": [
+                        { "play_id": "hamlet", "audience": 55 },
+                        { "play_id": "as-like", "audience": 35 }
+                    ]
+                },
+                {
+                    "customer": "Acme",
+                    "id": "2",
+                    "performances": [{ "play_id": "othello", "audience": 10 }]
+                },
+                { "customer": "Empty", "performances": [] }
+            ])
+        );
+        assert_eq!(
+            read_invoices(&write_invoices(&invoices).unwrap()),
+            Ok(invoices)
+        );
+    }
+
+    #[test]
+    fn test_plays_and_errors() {
+        let plays = json!({ "hamlet": { "name": "Hamlet, Prince", "type": "tragedy" } });
+        let text = write_plays(&plays).unwrap();
+        assert_eq!(text, "id,name,type\nhamlet,\"Hamlet, Prince\",tragedy\n");
+        assert_eq!(read_plays(&text), Ok(plays));
+
+        assert_eq!(
+            read_invoices("customer,audience\nBigCo,many\n"),
+            Err("line 2: audience must be a whole number, found \"many\"".to_string())
+        );
+        assert_eq!(
+            read_plays("id,name\nhamlet\n"),
+            Err("line 2: expected 2 fields, found 1".to_string())
+        );
+        assert_eq!(
+            read_plays("id,name\n\"hamlet,Hamlet\n"),
+            Err("line 2: unterminated quoted field".to_string())
+        );
+        assert_eq!(
+            write_invoices(&json!([{ "customer": "BigCo", "tags": ["a"] }])),
+            Err("tags is a list, which CSV cannot hold".to_string())
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    collections::VecDeque,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{create_statement_data::StatementData, date::Date, error::StatementError};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum EntryKind {
+    Earned,
+    Redeemed,
+}
+
+/// One movement of a customer's volume credits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LedgerEntry {
+    pub customer: String,
+    #[serde(default)]
+    pub invoice: Option<String>,
+    pub kind: EntryKind,
+    pub credits: u32,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// What became of a statement's credits when it was posted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Posting {
+    Posted,
+    /// An earlier run posted the invoice already.
+    AlreadyPosted,
+    /// Invoices without an id cannot be told apart, so nothing is posted.
+    NoInvoiceId,
+}
+
+/// The credits an invoice redeemed and earned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PostedCredits {
+    pub redeemed: u32,
+    pub earned: u32,
+}
+
+impl fmt::Display for PostedCredits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} credits redeemed and {} earned",
+            self.redeemed, self.earned
+        )
+    }
+}
+
+/// How long earned credits stay redeemable, read from `credits.json`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub(crate) struct ExpiryPolicy {
+    #[serde(default)]
+    pub expire_after_days: Option<u64>,
+}
+
+impl ExpiryPolicy {
+    /// Credits due to expire past the end of time never do.
+    fn is_expired(&self, earned_at: u64, now: u64) -> bool {
+        self.expire_after_days.is_some_and(|days| {
+            days.checked_mul(SECONDS_PER_DAY)
+                .and_then(|lifetime| earned_at.checked_add(lifetime))
+                .is_some_and(|expires_at| expires_at <= now)
+        })
+    }
+}
+
+/// A customer's credits over time, persisted as a JSON file.
+#[derive(Debug)]
+pub(crate) struct Ledger {
+    path: Option<PathBuf>,
+    entries: Vec<LedgerEntry>,
+    pub expiry: ExpiryPolicy,
+    /// Source of timestamps for new entries and balance queries.
+    pub clock: fn() -> u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LedgerFile {
+    entries: Vec<LedgerEntry>,
+}
+
+fn system_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Ledger {
+            path: None,
+            entries: Vec::new(),
+            expiry: ExpiryPolicy::default(),
+            clock: system_clock,
+        }
+    }
+}
+
+impl Ledger {
+    /// Opens the ledger at `path`, starting empty if the file does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StatementError> {
+        let path = path.as_ref();
+        let entries = match fs::read_to_string(path) {
+            Ok(data) => {
+                serde_json::from_str::<LedgerFile>(&data)
+                    .map_err(|err| StatementError::json(path, err))?
+                    .entries
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(StatementError::io(path, err)),
+        };
+        Ok(Ledger {
+            path: Some(path.to_path_buf()),
+            entries,
+            ..Default::default()
+        })
+    }
+
+    /// Writes the ledger back to the file it was opened from.
+    pub fn save(&self) -> Result<(), StatementError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = LedgerFile {
+            entries: self.entries.clone(),
+        };
+        let data =
+            serde_json::to_string_pretty(&file).map_err(|err| StatementError::json(path, err))?;
+        fs::write(path, data + "\n").map_err(|err| StatementError::write(path, err))
+    }
+
+    pub fn entries(&self, customer: &str) -> impl Iterator<Item = &LedgerEntry> {
+        self.entries.iter().filter(move |e| e.customer == customer)
+    }
+
+    /// Records the credits redeemed and earned on a statement that has been
+    /// written or sent. Each invoice is posted once, however often it is
+    /// run.
+    pub fn post_statement(&mut self, data: &StatementData) -> Posting {
+        let Some(invoice) = data.invoice_id.as_deref() else {
+            return Posting::NoInvoiceId;
+        };
+        if self.first_entry(&data.customer, invoice).is_some() {
+            return Posting::AlreadyPosted;
+        }
+        let timestamp = (self.clock)();
+        let mut post = |kind, credits| {
+            if credits > 0 {
+                self.entries.push(LedgerEntry {
+                    customer: data.customer.clone(),
+                    invoice: Some(invoice.to_string()),
+                    kind,
+                    credits,
+                    timestamp,
+                });
+            }
+        };
+        post(EntryKind::Redeemed, data.credits_redeemed);
+        post(EntryKind::Earned, data.total_volume_credits);
+        Posting::Posted
+    }
+
+    /// What was posted for the customer's `invoice`, if it has been.
+    pub fn posted(&self, customer: &str, invoice: &str) -> Option<PostedCredits> {
+        self.first_entry(customer, invoice)?;
+        let mut posted = PostedCredits::default();
+        for entry in self
+            .entries(customer)
+            .filter(|e| e.invoice.as_deref() == Some(invoice))
+        {
+            let total = match entry.kind {
+                EntryKind::Redeemed => &mut posted.redeemed,
+                EntryKind::Earned => &mut posted.earned,
+            };
+            *total = total.saturating_add(entry.credits);
+        }
+        Some(posted)
+    }
+
+    /// The index of the first entry posted for the customer's `invoice`.
+    fn first_entry(&self, customer: &str, invoice: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|e| e.customer == customer && e.invoice.as_deref() == Some(invoice))
+    }
+
+    /// Credits the customer can redeem now.
+    pub fn balance(&self, customer: &str) -> Result<u32, StatementError> {
+        self.balance_of(customer, &self.entries, (self.clock)())
+    }
+
+    /// Credits the customer can redeem on `invoice`: the balance now, or for
+    /// an invoice posted by an earlier run, the balance just before it was.
+    pub fn balance_for(
+        &self,
+        customer: &str,
+        invoice: Option<&str>,
+    ) -> Result<u32, StatementError> {
+        match invoice.and_then(|invoice| self.first_entry(customer, invoice)) {
+            Some(index) => self.balance_of(
+                customer,
+                &self.entries[..index],
+                self.entries[index].timestamp,
+            ),
+            None => self.balance(customer),
+        }
+    }
+
+    /// The balance after `entries`, at `now`. Redemptions use up the oldest
+    /// credits first; credits left unused past the expiry policy are lost.
+    fn balance_of(
+        &self,
+        customer: &str,
+        entries: &[LedgerEntry],
+        now: u64,
+    ) -> Result<u32, StatementError> {
+        let mut entries: Vec<&LedgerEntry> =
+            entries.iter().filter(|e| e.customer == customer).collect();
+        entries.sort_by_key(|e| e.timestamp);
+
+        let mut lots: VecDeque<(u64, u32)> = VecDeque::new();
+        for entry in entries {
+            lots.retain(|(earned_at, _)| !self.expiry.is_expired(*earned_at, entry.timestamp));
+            match entry.kind {
+                EntryKind::Earned => lots.push_back((entry.timestamp, entry.credits)),
+                EntryKind::Redeemed => {
+                    let mut owed = entry.credits;
+                    while owed > 0 {
+                        let Some(lot) = lots.front_mut() else { break };
+                        let used = lot.1.min(owed);
+                        lot.1 -= used;
+                        owed -= used;
+                        if lot.1 == 0 {
+                            lots.pop_front();
+                        }
+                    }
+                }
+            }
+        }
+        lots.iter()
+            .filter(|(earned_at, _)| !self.expiry.is_expired(*earned_at, now))
+            .try_fold(0u32, |total, (_, credits)| total.checked_add(*credits))
+            .ok_or_else(|| StatementError::CreditOverflow {
+                customer: customer.to_string(),
+            })
+    }
+
+    /// A per-customer statement of every credit movement and the balance.
+    pub fn report(&self, customer: &str) -> Result<String, StatementError> {
+        let mut result = format!("Ledger for {}\n", customer);
+        for entry in self.entries(customer) {
+            let kind = match entry.kind {
+                EntryKind::Earned => "earned",
+                EntryKind::Redeemed => "redeemed",
+            };
+            result += &format!(
+                " {} {} {} credits",
+                Date::from_unix_seconds(entry.timestamp),
+                kind,
+                entry.credits
+            );
+            if let Some(invoice) = &entry.invoice {
+                result += &format!(" (invoice {})", invoice);
+            }
+            result += "\n";
+        }
+        result += &format!("Balance is {} credits\n", self.balance(customer)?);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = SECONDS_PER_DAY;
+
+    fn entry(kind: EntryKind, credits: u32, day: u64) -> LedgerEntry {
+        LedgerEntry {
+            customer: "BigCo".to_string(),
+            invoice: None,
+            kind,
+            credits,
+            timestamp: day * DAY,
+        }
+    }
+
+    #[test]
+    fn test_unused_credits_expire_oldest_first() {
+        let mut ledger = Ledger {
+            entries: vec![
+                entry(EntryKind::Earned, 30, 0),
+                entry(EntryKind::Earned, 20, 200),
+                entry(EntryKind::Redeemed, 25, 300),
+            ],
+            expiry: ExpiryPolicy {
+                expire_after_days: Some(365),
+            },
+            ..Default::default()
+        };
+        ledger.clock = || 300 * DAY;
+        assert_eq!(ledger.balance("BigCo").unwrap(), 25);
+        // the 5 credits left from day 0 expire on day 365
+        ledger.clock = || 365 * DAY;
+        assert_eq!(ledger.balance("BigCo").unwrap(), 20);
+        ledger.clock = || 565 * DAY;
+        assert_eq!(ledger.balance("BigCo").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_overflow_is_an_error() {
+        let mut ledger = Ledger {
+            entries: vec![
+                entry(EntryKind::Earned, u32::MAX, 0),
+                entry(EntryKind::Earned, 1, 1),
+            ],
+            expiry: ExpiryPolicy {
+                expire_after_days: Some(u64::MAX),
+            },
+            ..Default::default()
+        };
+        ledger.clock = || u64::MAX;
+        assert!(matches!(
+            ledger.balance("BigCo"),
+            Err(StatementError::CreditOverflow { customer }) if customer == "BigCo"
+        ));
+        ledger.entries.pop();
+        assert_eq!(ledger.balance("BigCo").unwrap(), u32::MAX);
+    }
+
+    #[test]
+    fn test_invoices_are_posted_once() {
+        let mut ledger = Ledger {
+            entries: vec![entry(EntryKind::Earned, 30, 0)],
+            ..Default::default()
+        };
+        ledger.clock = || DAY;
+        let mut data = StatementData {
+            customer: "BigCo".to_string(),
+            invoice_id: Some("INV-1".to_string()),
+            total_volume_credits: 47,
+            credits_redeemed: 10,
+            ..Default::default()
+        };
+        assert_eq!(ledger.post_statement(&data), Posting::Posted);
+        assert_eq!(ledger.post_statement(&data), Posting::AlreadyPosted);
+        assert_eq!(ledger.balance("BigCo").unwrap(), 67);
+        // Run again, the invoice sees the balance from before it was posted.
+        assert_eq!(ledger.balance_for("BigCo", Some("INV-1")).unwrap(), 30);
+        assert_eq!(ledger.balance_for("BigCo", Some("INV-2")).unwrap(), 67);
+
+        data.invoice_id = None;
+        assert_eq!(ledger.post_statement(&data), Posting::NoInvoiceId);
+        assert_eq!(ledger.entries("BigCo").count(), 3);
+    }
+}
@@ -0,0 +1,269 @@
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use super::money::Money;
+
+mod fluent;
+
+use fluent::Catalogue;
+
+/// A language and region statements can be written for.
+///
+/// Statements without a locale keep the original English wording with
+/// ungrouped amounts such as `$1730.00`; `en-US` groups them as `$1,730.00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub(crate) enum Locale {
+    #[serde(rename = "en-US")]
+    EnUs,
+    #[serde(rename = "de-DE")]
+    DeDe,
+    #[serde(rename = "fr-FR")]
+    FrFr,
+    #[serde(rename = "ja-JP")]
+    JaJp,
+}
+
+/// A value passed to a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum MessageArg {
+    /// Printed with the locale's digit grouping; selects plural variants.
+    Number(i64),
+    Text(String),
+    /// Selects plural variants like a number but prints as the given text,
+    /// for counts wrapped in markup.
+    Styled(i64, String),
+}
+
+impl Locale {
+    pub const ALL: [Locale; 4] = [Locale::EnUs, Locale::DeDe, Locale::FrFr, Locale::JaJp];
+
+    /// The BCP 47 tag, e.g. `de-DE`.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::EnUs => "en-US",
+            Locale::DeDe => "de-DE",
+            Locale::FrFr => "fr-FR",
+            Locale::JaJp => "ja-JP",
+        }
+    }
+
+    /// The CLDR plural category of `n`, as used for variant keys.
+    pub fn plural(&self, n: i64) -> &'static str {
+        match self {
+            Locale::EnUs | Locale::DeDe if n == 1 => "one",
+            Locale::FrFr if n == 0 || n == 1 => "one",
+            _ => "other",
+        }
+    }
+
+    // The thousands separator and the decimal mark.
+    fn separators(&self) -> (&'static str, &'static str) {
+        match self {
+            Locale::EnUs | Locale::JaJp => (",", "."),
+            Locale::DeDe => (".", ","),
+            // A narrow no-break space, as CLDR has it.
+            Locale::FrFr => ("\u{202f}", ","),
+        }
+    }
+
+    fn group(&self, digits: &str) -> String {
+        let (separator, _) = self.separators();
+        let mut result = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                result.push_str(separator);
+            }
+            result.push(digit);
+        }
+        result
+    }
+
+    /// `1,730` in en-US, `1.730` in de-DE.
+    pub fn format_number(&self, n: i64) -> String {
+        let sign = if n < 0 { "-" } else { "" };
+        format!("{sign}{}", self.group(&n.unsigned_abs().to_string()))
+    }
+
+    /// `$1,730.00` in en-US, `1.730,00 €` in de-DE: the symbol or ISO code
+    /// goes before the amount in English and Japanese and after it, separated
+    /// by a no-break space, in German and French.
+    pub fn format_money(&self, money: Money) -> String {
+        let amount = money.format_amount();
+        let (sign, amount) = match amount.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", amount.as_str()),
+        };
+        let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+        let mut number = self.group(whole);
+        if !fraction.is_empty() {
+            number.push_str(self.separators().1);
+            number.push_str(fraction);
+        }
+        let currency = money.currency;
+        match (self, currency.symbol()) {
+            (Locale::EnUs | Locale::JaJp, Some(symbol)) => format!("{sign}{symbol}{number}"),
+            (Locale::EnUs | Locale::JaJp, None) => format!("{sign}{currency} {number}"),
+            (Locale::DeDe | Locale::FrFr, symbol) => {
+                format!("{sign}{number}\u{a0}{}", symbol.unwrap_or(currency.code()))
+            }
+        }
+    }
+
+    fn catalogue(&self) -> &'static Catalogue {
+        static CATALOGUES: OnceLock<Vec<Catalogue>> = OnceLock::new();
+        let catalogues = CATALOGUES.get_or_init(|| {
+            Locale::ALL
+                .iter()
+                .map(|locale| {
+                    Catalogue::parse(locale.source())
+                        .unwrap_or_else(|err| panic!("{}.ftl: {err}", locale.tag()))
+                })
+                .collect()
+        });
+        &catalogues[*self as usize]
+    }
+
+    fn source(&self) -> &'static str {
+        match self {
+            Locale::EnUs => include_str!("locale/en-US.ftl"),
+            Locale::DeDe => include_str!("locale/de-DE.ftl"),
+            Locale::FrFr => include_str!("locale/fr-FR.ftl"),
+            Locale::JaJp => include_str!("locale/ja-JP.ftl"),
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.tag().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let tags: Vec<&str> = Locale::ALL.iter().map(Locale::tag).collect();
+                format!("unknown locale {s:?}, expected one of: {}", tags.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.tag())
+    }
+}
+
+// Without a locale, statements use the English catalogue and the plain
+// number formats of `Money`'s `Display`.
+
+/// Formats `money` for `locale`, or as `$1730.00` without one.
+pub(crate) fn format_money(locale: Option<Locale>, money: Money) -> String {
+    match locale {
+        Some(locale) => locale.format_money(money),
+        None => money.to_string(),
+    }
+}
+
+/// Formats `n` for `locale`, or without grouping when there is none.
+pub(crate) fn format_number(locale: Option<Locale>, n: i64) -> String {
+    match locale {
+        Some(locale) => locale.format_number(n),
+        None => n.to_string(),
+    }
+}
+
+/// Whether message `id` exists; every catalogue has the same messages.
+pub(crate) fn has_message(id: &str) -> bool {
+    Locale::EnUs.catalogue().contains(id)
+}
+
+/// Formats message `id` from the catalogue of `locale`, or the English one.
+/// Without a locale plurals always take the `other` form, so that the plain
+/// statement keeps its original wording, e.g. `1 credits`.
+pub(crate) fn message(
+    locale: Option<Locale>,
+    id: &str,
+    args: &[(&str, MessageArg)],
+) -> Option<String> {
+    let language = locale.unwrap_or(Locale::EnUs);
+    language.catalogue().format(
+        id,
+        args,
+        |n| locale.map_or("other", |locale| locale.plural(n)),
+        |n| format_number(locale, n),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Currency;
+
+    #[test]
+    fn test_catalogues_have_the_same_messages() {
+        let english = Locale::EnUs.catalogue().ids();
+        for locale in Locale::ALL {
+            assert_eq!(locale.catalogue().ids(), english, "{locale}");
+        }
+    }
+
+    #[test]
+    fn test_money_formats() {
+        let amount = Money::new(-123456789, Currency::Eur);
+        let formatted: Vec<String> = Locale::ALL
+            .iter()
+            .map(|locale| locale.format_money(amount))
+            .collect();
+        assert_eq!(
+            formatted,
+            [
+                "-€1,234,567.89",
+                "-1.234.567,89\u{a0}€",
+                "-1\u{202f}234\u{202f}567,89\u{a0}€",
+                "-€1,234,567.89",
+            ]
+        );
+        assert_eq!(
+            Locale::JaJp.format_money(Money::new(1730, Currency::Jpy)),
+            "¥1,730"
+        );
+        assert_eq!(
+            Locale::DeDe.format_money(Money::new(1730, Currency::Kwd)),
+            "1,730\u{a0}KWD"
+        );
+        assert_eq!(
+            Locale::EnUs.format_money(Money::new(99, Currency::Chf)),
+            "CHF 0.99"
+        );
+        assert_eq!(
+            format_money(None, Money::new(173000, Currency::Usd)),
+            "$1730.00"
+        );
+    }
+
+    #[test]
+    fn test_plural_rules() {
+        let earned = |locale, credits| {
+            message(
+                locale,
+                "credits-earned",
+                &[("credits", MessageArg::Number(credits))],
+            )
+            .unwrap()
+        };
+        assert_eq!(earned(None, 1), "You earned 1 credits");
+        assert_eq!(earned(Some(Locale::EnUs), 1), "You earned 1 credit");
+        assert_eq!(earned(Some(Locale::EnUs), 1250), "You earned 1,250 credits");
+        assert_eq!(earned(Some(Locale::DeDe), 1), "Sie haben 1 Punkt gesammelt");
+        assert_eq!(
+            earned(Some(Locale::DeDe), 0),
+            "Sie haben 0 Punkte gesammelt"
+        );
+        assert_eq!(earned(Some(Locale::FrFr), 0), "Vous avez gagné 0 crédit");
+        assert_eq!(earned(Some(Locale::FrFr), 2), "Vous avez gagné 2 crédits");
+        assert_eq!(earned(Some(Locale::JaJp), 2), "2 ポイントを獲得しました");
+        assert_eq!("DE-de".parse(), Ok(Locale::DeDe));
+        assert!("pt-BR".parse::<Locale>().is_err());
+    }
+}
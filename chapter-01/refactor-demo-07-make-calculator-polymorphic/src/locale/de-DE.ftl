@@ -0,0 +1,36 @@
+# Statement wording. Every catalogue defines the same messages.
+
+statement-for = Abrechnung für { $customer }
+on-date = am { $date }
+at-venue = in { $venue }
+performance = { $amount } ({ $seats } { $seats ->
+    [one] Platz
+   *[other] Plätze
+})
+net-amount = Nettobetrag: { $amount }
+amount-owed = Zu zahlen: { $amount }
+discount = Rabatt für { $credits } { $credits ->
+    [one] Punkt
+   *[other] Punkte
+}: -{ $amount }
+remaining-balance = Verbleibender Betrag: { $amount }
+amount-owed-in = Zu zahlen in { $currency }: { $amount } (1 { $from } = { $rate } { $currency } am { $date })
+credits-earned = Sie haben { $credits } { $credits ->
+    [one] Punkt
+   *[other] Punkte
+} gesammelt
+credits-remaining = Sie haben noch { $credits } { $credits ->
+    [one] Punkt
+   *[other] Punkte
+}
+
+# Table headings
+column-date = Datum
+column-play = Stück
+column-seats = Plätze
+column-cost = Betrag
+subtotal = Zwischensumme
+
+# Chart titles
+chart-performances = Betrag je Vorstellung
+chart-kinds = Umsatz nach Stückart
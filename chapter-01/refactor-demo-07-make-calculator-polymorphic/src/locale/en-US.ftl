@@ -0,0 +1,36 @@
+# Statement wording. Every catalogue defines the same messages.
+
+statement-for = Statement for { $customer }
+on-date = on { $date }
+at-venue = at { $venue }
+performance = { $amount } ({ $seats } { $seats ->
+    [one] seat
+   *[other] seats
+})
+net-amount = Net amount is { $amount }
+amount-owed = Amount owed is { $amount }
+discount = Discount for { $credits } { $credits ->
+    [one] credit
+   *[other] credits
+} is -{ $amount }
+remaining-balance = Remaining balance is { $amount }
+amount-owed-in = Amount owed in { $currency } is { $amount } (1 { $from } = { $rate } { $currency } on { $date })
+credits-earned = You earned { $credits } { $credits ->
+    [one] credit
+   *[other] credits
+}
+credits-remaining = You have { $credits } { $credits ->
+    [one] credit
+   *[other] credits
+} remaining
+
+# Table headings
+column-date = date
+column-play = play
+column-seats = seats
+column-cost = cost
+subtotal = Subtotal
+
+# Chart titles
+chart-performances = Amount per performance
+chart-kinds = Revenue by play type
@@ -0,0 +1,266 @@
+//! The subset of Project Fluent that statement catalogues use: messages with
+//! `{ $variable }` placeables and select expressions on a variable, with
+//! plural categories or exact values as variant keys. Terms, attributes and
+//! functions are not supported.
+
+use std::collections::HashMap;
+
+use super::MessageArg;
+
+type Pattern = Vec<Element>;
+
+#[derive(Debug)]
+enum Element {
+    Text(String),
+    Variable(String),
+    Select {
+        variable: String,
+        variants: Vec<(String, Pattern)>,
+        default: usize,
+    },
+}
+
+/// The messages of one locale, by id.
+#[derive(Debug, Default)]
+pub(crate) struct Catalogue {
+    messages: HashMap<String, Pattern>,
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn parse_pattern(text: &str) -> Result<Pattern, String> {
+    let mut pattern = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            pattern.push(Element::Text(rest[..open].to_string()));
+        }
+        let (placeable, after) = parse_placeable(&rest[open + 1..])?;
+        pattern.push(placeable);
+        rest = after;
+    }
+    if !rest.is_empty() {
+        pattern.push(Element::Text(rest.to_string()));
+    }
+    Ok(pattern)
+}
+
+// Parses what follows a `{`, returning the element and the text after its `}`.
+fn parse_placeable(text: &str) -> Result<(Element, &str), String> {
+    let rest = text
+        .trim_start()
+        .strip_prefix('$')
+        .ok_or("expected a $variable after {")?;
+    let end = rest.find(|c| !is_identifier(c)).unwrap_or(rest.len());
+    let variable = rest[..end].to_string();
+    if variable.is_empty() {
+        return Err("expected a variable name after $".to_string());
+    }
+    let rest = rest[end..].trim_start();
+    if let Some(after) = rest.strip_prefix('}') {
+        return Ok((Element::Variable(variable), after));
+    }
+    let mut rest = rest
+        .strip_prefix("->")
+        .ok_or_else(|| format!("expected }} or -> after ${variable}"))?;
+    let mut variants = Vec::new();
+    let mut default = None;
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix('}') {
+            rest = after;
+            break;
+        }
+        if let Some(after) = rest.strip_prefix('*') {
+            if default.is_some() {
+                return Err(format!("more than one default variant for ${variable}"));
+            }
+            default = Some(variants.len());
+            rest = after;
+        }
+        let after = rest
+            .strip_prefix('[')
+            .ok_or_else(|| format!("expected a [variant] for ${variable}"))?;
+        let close = after.find(']').ok_or("unclosed [ in variant key")?;
+        let key = after[..close].trim().to_string();
+        // A variant's value runs to the end of its line.
+        let after = &after[close + 1..];
+        let end = after.find('\n').unwrap_or(after.len());
+        variants.push((key, parse_pattern(after[..end].trim())?));
+        rest = &after[end..];
+    }
+    let default = default.ok_or_else(|| format!("no default *[variant] for ${variable}"))?;
+    Ok((
+        Element::Select {
+            variable,
+            variants,
+            default,
+        },
+        rest,
+    ))
+}
+
+impl Catalogue {
+    /// Parses a catalogue; errors name the line the broken message starts on.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        // Each message with the line it starts on and its value, continuation
+        // lines included.
+        let mut entries: Vec<(String, usize, String)> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with([' ', '}']) {
+                let (_, _, value) = entries
+                    .last_mut()
+                    .ok_or_else(|| format!("line {number}: continuation without a message"))?;
+                value.push('\n');
+                value.push_str(line.trim());
+                continue;
+            }
+            let (id, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {number}: expected `id = value`"))?;
+            let id = id.trim();
+            if id.is_empty() || !id.chars().all(is_identifier) {
+                return Err(format!("line {number}: invalid message id {id:?}"));
+            }
+            entries.push((id.to_string(), number, value.trim().to_string()));
+        }
+
+        let mut messages = HashMap::new();
+        for (id, number, value) in entries {
+            let pattern =
+                parse_pattern(value.trim()).map_err(|err| format!("line {number}: {err}"))?;
+            if messages.insert(id.clone(), pattern).is_some() {
+                return Err(format!("line {number}: duplicate message {id}"));
+            }
+        }
+        Ok(Catalogue { messages })
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.messages.contains_key(id)
+    }
+
+    /// The sorted message ids.
+    #[cfg(test)]
+    pub fn ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.messages.keys().map(String::as_str).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Formats message `id`, choosing variants by `plural` category and
+    /// printing numbers with `number`. A missing variable prints as
+    /// `{$name}`, as Fluent does.
+    pub fn format(
+        &self,
+        id: &str,
+        args: &[(&str, MessageArg)],
+        plural: impl Fn(i64) -> &'static str,
+        number: impl Fn(i64) -> String,
+    ) -> Option<String> {
+        let mut out = String::new();
+        format_pattern(self.messages.get(id)?, args, &plural, &number, &mut out);
+        Some(out)
+    }
+}
+
+fn format_pattern(
+    pattern: &Pattern,
+    args: &[(&str, MessageArg)],
+    plural: &dyn Fn(i64) -> &'static str,
+    number: &dyn Fn(i64) -> String,
+    out: &mut String,
+) {
+    let arg = |name: &str| args.iter().find(|(n, _)| *n == name).map(|(_, a)| a);
+    for element in pattern {
+        match element {
+            Element::Text(text) => out.push_str(text),
+            Element::Variable(name) => match arg(name) {
+                Some(MessageArg::Number(n)) => out.push_str(&number(*n)),
+                Some(MessageArg::Text(text) | MessageArg::Styled(_, text)) => out.push_str(text),
+                None => out.push_str(&format!("{{${name}}}")),
+            },
+            Element::Select {
+                variable,
+                variants,
+                default,
+            } => {
+                let position = |wanted: &str| variants.iter().position(|(key, _)| key == wanted);
+                // Exact values win over plural categories.
+                let chosen = match arg(variable) {
+                    Some(MessageArg::Number(n) | MessageArg::Styled(n, _)) => {
+                        position(&n.to_string()).or_else(|| position(plural(*n)))
+                    }
+                    Some(MessageArg::Text(text)) => position(text),
+                    None => None,
+                };
+                let (_, value) = &variants[chosen.unwrap_or(*default)];
+                format_pattern(value, args, plural, number, out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english(n: i64) -> &'static str {
+        if n == 1 { "one" } else { "other" }
+    }
+
+    #[test]
+    fn test_select_expressions() {
+        let catalogue = Catalogue::parse(
+            "# Comment\n\
+             hello = Hello { $name }!\n\
+             seats = { $count ->\n    [0] no seats\n    [one] one seat\n   *[other] { $count } seats\n} left\n",
+        )
+        .unwrap();
+        let format = |id, args: &[(&str, MessageArg)]| {
+            catalogue
+                .format(id, args, english, |n| n.to_string())
+                .unwrap()
+        };
+        assert_eq!(
+            format("hello", &[("name", MessageArg::Text("Ada".into()))]),
+            "Hello Ada!"
+        );
+        assert_eq!(format("hello", &[]), "Hello {$name}!");
+        assert_eq!(
+            format("seats", &[("count", MessageArg::Number(0))]),
+            "no seats left"
+        );
+        assert_eq!(
+            format("seats", &[("count", MessageArg::Number(1))]),
+            "one seat left"
+        );
+        assert_eq!(
+            format("seats", &[("count", MessageArg::Styled(5, "*5*".into()))]),
+            "*5* seats left"
+        );
+        assert!(
+            catalogue
+                .format("missing", &[], english, |n| n.to_string())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_errors_name_the_line() {
+        assert_eq!(
+            Catalogue::parse("a = ok\nb = { $n ->\n  [one] x\n}\n").unwrap_err(),
+            "line 2: no default *[variant] for $n"
+        );
+        assert_eq!(
+            Catalogue::parse("a = ok\n\nnot a message\n").unwrap_err(),
+            "line 3: expected `id = value`"
+        );
+    }
+}
@@ -0,0 +1,36 @@
+# Statement wording. Every catalogue defines the same messages.
+
+statement-for = Relevé pour { $customer }
+on-date = le { $date }
+at-venue = à { $venue }
+performance = { $amount } ({ $seats } { $seats ->
+    [one] place
+   *[other] places
+})
+net-amount = Montant net : { $amount }
+amount-owed = Montant dû : { $amount }
+discount = Remise pour { $credits } { $credits ->
+    [one] crédit
+   *[other] crédits
+} : -{ $amount }
+remaining-balance = Solde restant : { $amount }
+amount-owed-in = Montant dû en { $currency } : { $amount } (1 { $from } = { $rate } { $currency } le { $date })
+credits-earned = Vous avez gagné { $credits } { $credits ->
+    [one] crédit
+   *[other] crédits
+}
+credits-remaining = Il vous reste { $credits } { $credits ->
+    [one] crédit
+   *[other] crédits
+}
+
+# Table headings
+column-date = date
+column-play = pièce
+column-seats = places
+column-cost = montant
+subtotal = Sous-total
+
+# Chart titles
+chart-performances = Montant par représentation
+chart-kinds = Recettes par genre de pièce
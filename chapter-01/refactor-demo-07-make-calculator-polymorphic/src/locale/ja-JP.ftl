@@ -0,0 +1,25 @@
+# Statement wording. Every catalogue defines the same messages.
+# Japanese has no plural forms, so counts need no select expressions.
+
+statement-for = { $customer } 様 ご利用明細
+on-date = { $date } 公演
+at-venue = 会場 { $venue }
+performance = { $amount }（{ $seats } 席）
+net-amount = 税抜金額: { $amount }
+amount-owed = ご請求金額: { $amount }
+discount = { $credits } ポイント利用による割引: -{ $amount }
+remaining-balance = お支払い残高: { $amount }
+amount-owed-in = { $currency } でのご請求金額: { $amount }（1 { $from } = { $rate } { $currency }、{ $date } 時点）
+credits-earned = { $credits } ポイントを獲得しました
+credits-remaining = ポイント残高: { $credits }
+
+# Table headings
+column-date = 日付
+column-play = 演目
+column-seats = 席数
+column-cost = 金額
+subtotal = 小計
+
+# Chart titles
+chart-performances = 公演ごとの金額
+chart-kinds = 演目の種類別の売上
@@ -1,10 +1,57 @@
-use std::{collections::HashMap, fs};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{Mutex, PoisonError},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+mod batch;
+mod calculator_registry;
+mod cli;
+mod company;
 mod create_statement_data;
-use create_statement_data::{StatementData, create_statement_data};
+mod credits;
+mod date;
+mod email;
+mod error;
+mod exchange;
+mod input;
+mod ledger;
+mod locale;
+mod money;
+mod party;
+mod pricing;
+mod render;
+mod report;
+mod stream;
+mod tax;
+mod validate;
+use batch::BatchStats;
+use calculator_registry::CalculatorRegistry;
+use cli::{Cli, CliError, Command};
+use company::Company;
+use create_statement_data::{StatementContext, StatementData, create_statement_data};
+use credits::RedemptionPolicy;
+use date::Date;
+use email::{EmailOptions, MailSender, Mailbox, SmtpSender};
+use error::StatementError;
+use exchange::ExchangeRates;
+use input::{DataFormat, Dataset};
+use ledger::Ledger;
+use locale::Locale;
+use money::Currency;
+use party::Party;
+use pricing::PricingRules;
+use render::{RenderOptions, RendererRegistry, StatementRenderer, load_templates};
+use report::Report;
+use stream::{ErrorLog, InvoiceLines, LineError};
+use tax::TaxEngine;
+use validate::{Diagnostic, Source};
 
-#[derive(Debug, Deserialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub(crate) struct Play {
     name: String,
     #[serde(rename = "type")]
@@ -15,101 +62,722 @@ pub(crate) struct Play {
 pub(crate) struct Performance {
     play_id: String,
     audience: u32,
+    #[serde(default)]
+    date: Option<Date>,
+    #[serde(default)]
+    venue: Option<String>,
+    /// Seats available at the venue; a larger audience is rejected.
+    #[serde(default)]
+    capacity: Option<u32>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 pub(crate) struct Invoice {
+    /// Identifies the invoice in the loyalty ledger.
+    #[serde(default)]
+    id: Option<String>,
     customer: String,
     performances: Vec<Performance>,
+    #[serde(default)]
+    date: Option<Date>,
+    /// The currency the customer is billed in, if not the pricing currency.
+    #[serde(default)]
+    currency: Option<Currency>,
+    /// Where the performances take place, for sales tax or VAT.
+    #[serde(default)]
+    jurisdiction: Option<String>,
+    /// Volume credits the customer held before this invoice. Ignored when a
+    /// ledger keeps track of balances.
+    #[serde(default)]
+    credit_balance: u32,
+    /// Volume credits to redeem as a discount on this invoice.
+    #[serde(default)]
+    redeem_credits: u32,
+    /// The customer's legal details, needed for e-invoices.
+    #[serde(default)]
+    buyer: Option<Party>,
+    /// The language and number formats of the statement, e.g. `de-DE`.
+    #[serde(default)]
+    locale: Option<Locale>,
+    /// Who receives the statement by email.
+    #[serde(default)]
+    contact: Option<Mailbox>,
 }
 
-fn usd(amount: u32) -> String {
-    format!("${:.2}", amount as f64 / 100.0)
+pub(crate) fn read_json<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, StatementError> {
+    let path = path.as_ref();
+    let data = fs::read_to_string(path).map_err(|err| StatementError::io(path, err))?;
+    serde_json::from_str(&data).map_err(|err| StatementError::json(path, err))
 }
 
-fn statement(invoice: &Invoice, plays: &HashMap<String, Play>) -> String {
-    render_plain_text(&create_statement_data::create_statement_data(
-        invoice, plays,
-    ))
+/// Writes the statement as an email into `dir` as `stem.eml`, and sends it too
+/// if there is a `sender`.
+fn email_statement(
+    data: &StatementData,
+    stem: &str,
+    options: &EmailOptions,
+    dir: &Path,
+    sender: Option<&dyn MailSender>,
+    now: u64,
+) -> Result<PathBuf, CliError> {
+    let email = email::compose(data, stem, options, now)?;
+    let path = email::write_eml(dir, stem, &email)?;
+    if let Some(sender) = sender {
+        sender
+            .send(&email)
+            .map_err(|err| CliError::Io(format!("{}: {err}", data.customer)))?;
+    }
+    Ok(path)
 }
 
-fn render_plain_text(statement_data: &StatementData) -> String {
-    let mut result = format!("Statement for {}\n", statement_data.customer);
-    for perf in &statement_data.performances {
-        // Print line for this performance
-        result += &format!(
-            " {}: {} ({} seats)\n",
-            perf.play.name,
-            usd(perf.amount),
-            perf.audience
-        );
+/// Writes `contents` to `dir/name`, creating `dir` if needed.
+fn write_output(dir: &Path, name: &str, contents: &str) -> Result<PathBuf, CliError> {
+    let path = dir.join(name);
+    fs::create_dir_all(dir)
+        .and_then(|()| fs::write(&path, contents))
+        .map_err(|err| StatementError::write(&path, err))?;
+    Ok(path)
+}
+
+/// The failures of a command that handles invoices one by one, which are
+/// reported as they happen and summed up at the end.
+#[derive(Debug, Default)]
+struct Failures {
+    count: usize,
+    io: bool,
+}
+
+impl Failures {
+    fn add(&mut self, err: &CliError) {
+        self.count += 1;
+        self.io |= matches!(err, CliError::Io(_));
     }
 
-    result += &format!("Amount owed is {}\n", usd(statement_data.total_amount));
-    result += &format!(
-        "You earned {} credits\n",
-        statement_data.total_volume_credits
-    );
-    result
+    fn finish(&self, total: usize) -> Result<(), CliError> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        let summary = format!("{} of {} invoices failed", self.count, total);
+        // An I/O failure wins, as fixing the data would not help.
+        if self.io {
+            Err(CliError::Io(summary))
+        } else {
+            Err(CliError::Data(summary))
+        }
+    }
 }
-#[allow(unused)]
-pub(crate) fn html_statement(invoice: &Invoice, plays: &HashMap<String, Play>) -> String {
-    render_html(&create_statement_data(invoice, plays))
+
+/// The revenue report over `invoices` as text, CSV, JSON or SVG. Invoices that
+/// fail are reported, added to `failures` and left out; nothing is posted to
+/// the ledger.
+fn report(
+    invoices: &[&Invoice],
+    context: &StatementContext,
+    format: &str,
+    failures: &mut Failures,
+) -> Result<String, CliError> {
+    let statements: Vec<_> = invoices
+        .iter()
+        .filter_map(|invoice| match create_statement_data(invoice, context) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                let err = CliError::from(err);
+                eprintln!("error: {}", err);
+                failures.add(&err);
+                None
+            }
+        })
+        .collect();
+    let report = Report::from_statements(&statements)
+        .map_err(|err| CliError::Data(format!("cannot total the report: {err}")))?;
+    match format {
+        "text" => Ok(report.to_text()),
+        "csv" => Ok(report.to_csv()),
+        "json" => Ok(report.to_json()),
+        "svg" => Ok(report.to_svg()),
+        _ => Err(CliError::Usage(format!(
+            "unknown report format {format:?}, expected one of: csv, json, svg, text"
+        ))),
+    }
 }
-#[allow(unused)]
-fn render_html(data: &StatementData) -> String {
-    let mut result = String::new();
 
-    result.push_str(&format!("<h1>Statement for {}</h1>\n", data.customer));
-    result.push_str("<table>\n");
-    result.push_str("<tr><th>play</th><th>seats</th><th>cost</th></tr>");
+/// The play catalogue sorted by id, as text, CSV or JSON.
+fn list_plays(plays: &HashMap<String, Play>, format: &str) -> Result<String, CliError> {
+    let mut ids: Vec<&String> = plays.keys().collect();
+    ids.sort_unstable();
+    match format {
+        "text" => Ok(ids
+            .iter()
+            .map(|id| format!("{}: {} ({})\n", id, plays[*id].name, plays[*id].kind))
+            .collect()),
+        "csv" => Ok(ids.iter().fold(String::from("id,name,type\n"), |csv, id| {
+            csv + &format!(
+                "{},{},{}\n",
+                render::csv_field(id),
+                render::csv_field(&plays[*id].name),
+                render::csv_field(&plays[*id].kind)
+            )
+        })),
+        "json" => {
+            let list: Vec<serde_json::Value> = ids
+                .iter()
+                .map(|id| {
+                    serde_json::json!({
+                        "id": id,
+                        "name": plays[*id].name,
+                        "type": plays[*id].kind,
+                    })
+                })
+                .collect();
+            // Plain strings always serialize.
+            Ok(serde_json::to_string_pretty(&list).expect("plays serialize") + "\n")
+        }
+        _ => Err(CliError::Usage(format!(
+            "unknown play list format {format:?}, expected one of: csv, json, text"
+        ))),
+    }
+}
 
-    for perf in &data.performances {
-        result.push_str(&format!(
-            " <tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
-            perf.play.name,
-            perf.audience,
-            usd(perf.amount),
+/// The invoices picked by `--customer` and `--invoice`, all of them without,
+/// each with its position in the input from 1.
+fn select_invoices<'a>(
+    cli: &Cli,
+    invoices: &'a [Invoice],
+) -> Result<Vec<(usize, &'a Invoice)>, CliError> {
+    let selected: Vec<(usize, &Invoice)> = (1..)
+        .zip(invoices)
+        .filter(|(_, invoice)| cli.selects(invoice))
+        .collect();
+    if selected.is_empty() && (cli.customer.is_some() || cli.invoice.is_some()) {
+        return Err(CliError::Usage(
+            "no invoice matches the selection".to_string(),
         ));
     }
+    Ok(selected)
+}
 
-    result.push_str("</table>\n");
-    result.push_str(&format!(
-        "<p>Amount owed is <em>{}</em></p>\n",
-        usd(data.total_amount)
-    ));
-    result.push_str(&format!(
-        "<p>You earned <em>{}</em> credits</p>\n",
-        data.total_volume_credits
-    ));
+/// What became of a statement, reported in invoice order once it is done.
+struct Outcome {
+    /// Peppol rule violations of UBL statements.
+    warnings: Vec<String>,
+    /// The statement when printed, or the file it was written to.
+    written: Result<String, PathBuf>,
+}
 
-    result
+impl Outcome {
+    fn report(&self) {
+        for warning in &self.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        match &self.written {
+            Ok(statement) => println!("{}", statement),
+            Err(path) => println!("Wrote {}", path.display()),
+        }
+    }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Read JSON files
-    let plays_data =
-        fs::read_to_string("chapter-01/plays.json").expect("Failed to read plays.json");
-    let invoices_data =
-        fs::read_to_string("chapter-01/invoices.json").expect("Failed to read invoices.json");
+/// How statements are turned into output, set up once per run.
+struct StatementOutput {
+    format: String,
+    renderer: Box<dyn StatementRenderer>,
+    /// Where `--email` writes `.eml` files, and how they are composed.
+    email: Option<(PathBuf, EmailOptions)>,
+    smtp: Option<SmtpSender>,
+    /// The sending time of emails, in seconds since the Unix epoch.
+    now: u64,
+}
 
-    let plays: HashMap<String, Play> = serde_json::from_str(&plays_data)?;
-    let invoices: Vec<Invoice> = serde_json::from_str(&invoices_data)?;
+impl StatementOutput {
+    fn new(cli: &Cli) -> Result<Self, CliError> {
+        // `--template NAME` picks NAME from the templates directory and implies
+        // `--format template`.
+        let template = match &cli.template {
+            Some(name) => {
+                let dir = cli.templates_dir();
+                let mut templates = load_templates(&dir)?;
+                let Some(template) = templates.remove(name) else {
+                    let mut names: Vec<String> = templates.into_keys().collect();
+                    names.sort_unstable();
+                    return Err(CliError::Usage(format!(
+                        "no template {name:?} in {}, found: {}",
+                        dir.display(),
+                        names.join(", ")
+                    )));
+                };
+                Some(template)
+            }
+            None => None,
+        };
+        let format = cli.format.as_deref().unwrap_or(if template.is_some() {
+            "template"
+        } else {
+            "text"
+        });
+        let options = RenderOptions {
+            detailed: cli.detailed,
+            company: Some(Company::from_file(cli.data_file("company.json"))?),
+            template,
+            charts: cli.charts,
+            // Tables fall back to ASCII outside UTF-8 locales.
+            ascii: cli.ascii || !render::unicode_enabled(),
+            // Files get no escape codes.
+            color: cli.out_dir.is_none() && render::color_enabled(),
+        };
+        // `--email DIR` writes each statement as an `.eml` file instead of
+        // printing it; `--smtp HOST:PORT` sends it as well.
+        let email = match &cli.email {
+            Some(dir) => {
+                let company = options.company.clone().unwrap_or_default();
+                let Some(from) = Mailbox::from_company(&company) else {
+                    return Err(CliError::Data(
+                        "company.json has no email address to send statements from".to_string(),
+                    ));
+                };
+                let email_options = EmailOptions {
+                    from,
+                    subject: cli.subject.clone(),
+                    attach_pdf: cli.attach_pdf,
+                    detailed: options.detailed,
+                    letterhead: Some(company),
+                };
+                Some((dir.clone(), email_options))
+            }
+            None => None,
+        };
+        let renderers = RendererRegistry::builtin();
+        let Some(renderer) = renderers.create(format, &options) else {
+            let formats = renderers.formats().join(", ");
+            return Err(CliError::Usage(format!(
+                "unknown format {format:?}, expected one of: {formats}"
+            )));
+        };
+        Ok(StatementOutput {
+            format: format.to_string(),
+            renderer,
+            email,
+            smtp: cli.smtp.as_deref().map(|address| {
+                let mut sender = SmtpSender::new(address);
+                if let Some(timeout) = cli.smtp_timeout {
+                    sender.timeout = timeout;
+                }
+                sender
+            }),
+            now: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        })
+    }
 
-    // Print statements
-    for invoice in &invoices {
-        let output = statement(invoice, &plays);
-        println!("{}", output);
+    /// Renders, writes or emails the statement for `invoice`, the
+    /// `position`th in the input, leaving the printing to the caller. Its
+    /// credits are posted to the ledger only once it is written or sent.
+    fn write(
+        &self,
+        cli: &Cli,
+        position: usize,
+        invoice: &Invoice,
+        context: &StatementContext,
+    ) -> Result<Outcome, CliError> {
+        let data = create_statement_data(invoice, context)?;
+        let stem = render::file_stem(invoice.id.as_deref(), &invoice.customer, position);
+        let mut warnings = Vec::new();
+        let written = match &self.email {
+            Some((dir, email_options)) => {
+                let sender = self.smtp.as_ref().map(|smtp| smtp as &dyn MailSender);
+                Err(email_statement(
+                    &data,
+                    &stem,
+                    email_options,
+                    dir,
+                    sender,
+                    self.now,
+                )?)
+            }
+            None => {
+                let output = self.renderer.render(&data)?;
+                // Peppol rules can only be checked on the finished document.
+                if self.format == "ubl" {
+                    for violation in render::validate(&output) {
+                        warnings.push(format!("{}: {}", invoice.customer, violation));
+                    }
+                }
+                match &cli.out_dir {
+                    Some(dir) => {
+                        let name = format!("{stem}.{}", render::extension(&self.format));
+                        Err(write_output(dir, &name, &output)?)
+                    }
+                    None => Ok(output),
+                }
+            }
+        };
+        warnings.extend(context.post(&data));
+        Ok(Outcome { warnings, written })
     }
+}
 
+/// The credit ledger at `path` with its expiry rules, shared by the statements
+/// of a run.
+fn open_ledger(cli: &Cli, path: &Path) -> Result<Mutex<Ledger>, CliError> {
+    let mut ledger = Ledger::open(path)?;
+    ledger.expiry = read_json(cli.data_file("credits.json"))?;
+    Ok(Mutex::new(ledger))
+}
+
+/// Renders, writes or emails the selected statements and posts their credits
+/// to the ledger if `--ledger` is given.
+fn run_statements(
+    cli: &Cli,
+    invoices: &[(usize, &Invoice)],
+    context: StatementContext,
+) -> Result<(), CliError> {
+    let output = StatementOutput::new(cli)?;
+    let ledger = match &cli.ledger {
+        Some(path) => Some(open_ledger(cli, path)?),
+        None => None,
+    };
+    let context = match &ledger {
+        Some(ledger) => context.with_ledger(ledger),
+        None => context,
+    };
+
+    // Handle each statement, reporting bad invoices without stopping the run
+    let started = Instant::now();
+    let results = match cli.jobs {
+        Some(jobs) => write_in_parallel(cli, invoices, &output, &context, jobs),
+        None => invoices
+            .iter()
+            .map(|(position, invoice)| output.write(cli, *position, invoice, &context))
+            .collect(),
+    };
+    let mut failures = Failures::default();
+    for result in results {
+        match result {
+            Ok(outcome) => outcome.report(),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                failures.add(&err);
+            }
+        }
+    }
+
+    // The balances and stats go to stderr, so that stdout holds only the
+    // statements.
+    if let Some(ledger) = ledger {
+        let ledger = ledger.into_inner().unwrap_or_else(PoisonError::into_inner);
+        ledger.save()?;
+        let mut customers: Vec<&str> = invoices.iter().map(|(_, i)| i.customer.as_str()).collect();
+        customers.sort_unstable();
+        customers.dedup();
+        for customer in customers {
+            eprintln!("{}", ledger.report(customer)?);
+        }
+    }
+    if let Some(jobs) = cli.jobs {
+        let stats = BatchStats {
+            jobs,
+            total: invoices.len(),
+            failed: failures.count,
+            elapsed: started.elapsed(),
+        };
+        eprintln!("{}", stats);
+    }
+    failures.finish(invoices.len())
+}
+
+/// The statements for `invoices` made on `jobs` threads, in invoice order.
+/// Each customer's invoices are priced in turn on one thread, as credits
+/// earned on one invoice can be redeemed on the next; customers run in
+/// parallel.
+fn write_in_parallel(
+    cli: &Cli,
+    invoices: &[(usize, &Invoice)],
+    output: &StatementOutput,
+    context: &StatementContext,
+    jobs: usize,
+) -> Vec<Result<Outcome, CliError>> {
+    let mut customers: Vec<Vec<(usize, &Invoice)>> = Vec::new();
+    let mut by_customer: HashMap<&str, usize> = HashMap::new();
+    for &(position, invoice) in invoices {
+        let group = *by_customer
+            .entry(invoice.customer.as_str())
+            .or_insert_with(|| {
+                customers.push(Vec::new());
+                customers.len() - 1
+            });
+        customers[group].push((position, invoice));
+    }
+    let mut results: Vec<_> = batch::run(&customers, jobs, |invoices| {
+        invoices
+            .iter()
+            .map(|(position, invoice)| (*position, output.write(cli, *position, invoice, context)))
+            .collect::<Vec<_>>()
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+    // Positions grow through the input, so they give the invoice order.
+    results.sort_unstable_by_key(|(position, _)| *position);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Streams statements for NDJSON invoices, reading, pricing and writing one
+/// invoice at a time, so memory does not grow with the number of invoices.
+/// Invoices that fail are logged to the `--errors` side file as they happen.
+///
+/// Only run without `--ledger`: the ledger's journal grows with every invoice
+/// and is replayed for every balance, so nothing is posted to it. Credits
+/// come from each invoice's `credit_balance` instead.
+fn run_stream(cli: &Cli, context: StatementContext) -> Result<(), CliError> {
+    let path = cli.invoices_path();
+    let file = fs::File::open(&path).map_err(|err| StatementError::io(&path, err))?;
+    let output = StatementOutput::new(cli)?;
+    let mut errors = ErrorLog::create(
+        cli.errors
+            .clone()
+            .unwrap_or_else(|| ErrorLog::path_for(&path)),
+    )?;
+
+    let mut total = 0;
+    let mut failures = Failures::default();
+    // Every non-blank line is an invoice, parsed or not, so that positions are
+    // those of a JSON array of the same invoices.
+    for ((line, invoice), position) in InvoiceLines::new(io::BufReader::new(file)).zip(1..) {
+        let (customer, result) = match invoice {
+            Ok(invoice) => {
+                if !cli.selects(&invoice) {
+                    continue;
+                }
+                let result = output
+                    .write(cli, position, &invoice, &context)
+                    .map(|outcome| outcome.report());
+                (Some(invoice.customer), result)
+            }
+            Err(LineError::Io(err)) => (None, Err(StatementError::io(&path, err).into())),
+            Err(err) => (None, Err(CliError::Data(err.to_string()))),
+        };
+        total += 1;
+        if let Err(err) = result {
+            errors.record(line, customer.as_deref(), &err)?;
+            failures.add(&err);
+        }
+    }
+
+    let log = errors.path.clone();
+    errors.close()?;
+    failures.finish(total).map_err(|err| match err {
+        CliError::Io(summary) => CliError::Io(format!("{summary}, see {}", log.display())),
+        CliError::Data(summary) => CliError::Data(format!("{summary}, see {}", log.display())),
+        err => err,
+    })
+}
+
+/// The pricing inputs shared by every invoice.
+struct Pricing {
+    registry: CalculatorRegistry,
+    rates: ExchangeRates,
+    taxes: TaxEngine,
+    redemption: RedemptionPolicy,
+}
+
+impl Pricing {
+    fn load(cli: &Cli) -> Result<Self, CliError> {
+        Ok(Pricing {
+            registry: CalculatorRegistry::from_rules(&PricingRules::from_file(
+                cli.data_file("pricing.json"),
+            )?),
+            rates: ExchangeRates::from_file(cli.data_file("rates.json"))?,
+            taxes: TaxEngine::from_file(cli.data_file("taxes.json"))?,
+            redemption: RedemptionPolicy::from_file(cli.data_file("credits.json"))?,
+        })
+    }
+
+    fn context<'a>(&'a self, plays: &'a HashMap<String, Play>, cli: &Cli) -> StatementContext<'a> {
+        let context = StatementContext::new(plays, &self.registry)
+            .with_rates(&self.rates)
+            .with_taxes(&self.taxes)
+            .with_redemption(&self.redemption);
+        // Invoices that name a locale keep it; `--locale` covers the rest.
+        match cli.locale {
+            Some(locale) => context.with_locale(locale),
+            None => context,
+        }
+    }
+}
+
+/// Checks the whole play catalogue and all invoices, then prices the invoices
+/// if the files hold together, and prints every problem found.
+fn run_validate(cli: &Cli) -> Result<(), CliError> {
+    let format = cli.format.as_deref().unwrap_or("text");
+    if !matches!(format, "text" | "json") {
+        return Err(CliError::Usage(format!(
+            "unknown validate format {format:?}, expected text or json"
+        )));
+    }
+    let plays_source = Source::read(cli.plays_path(), cli.input_format, Dataset::Plays)?;
+    let invoices_source = Source::read(cli.invoices_path(), cli.input_format, Dataset::Invoices)?;
+    let pricing = Pricing::load(cli)?;
+    let mut diagnostics =
+        validate::check(&plays_source, &invoices_source, &pricing.registry.kinds());
+    // Pricing finds what the files cannot show, such as unknown currencies
+    // or too few credits, but only makes sense on files that load.
+    if validate::error_count(&diagnostics) == 0 {
+        let plays: HashMap<String, Play> = plays_source.deserialize()?;
+        let invoices: Vec<Invoice> = invoices_source.deserialize()?;
+        let context = pricing.context(&plays, cli);
+        let positions = validate::invoice_positions(&invoices_source);
+        for (index, invoice) in invoices.iter().enumerate() {
+            if let Err(err) = create_statement_data(invoice, &context) {
+                diagnostics.push(Diagnostic::error(
+                    &invoices_source.path,
+                    positions.get(index).copied().unwrap_or_default(),
+                    err.to_string(),
+                ));
+            }
+        }
+    }
+    match format {
+        "json" => println!(
+            "{}",
+            validate::to_json(&diagnostics).map_err(|err| CliError::Io(err.to_string()))?
+        ),
+        _ => print!("{}", validate::to_text(&diagnostics)),
+    }
+    match validate::error_count(&diagnostics) {
+        0 => Ok(()),
+        1 => Err(CliError::Data("validation found 1 error".to_string())),
+        errors => Err(CliError::Data(format!("validation found {errors} errors"))),
+    }
+}
+
+/// Writes the plays and invoices into `--out` in the `--to` format.
+fn run_convert(cli: &Cli) -> Result<(), CliError> {
+    let (Some(to), Some(dir)) = (cli.to, &cli.out_dir) else {
+        return Err(CliError::Usage(
+            "convert needs --to FORMAT and --out DIR".to_string(),
+        ));
+    };
+    let plays = input::convert::<HashMap<String, Play>>(
+        &cli.plays_path(),
+        cli.input_format,
+        to,
+        Dataset::Plays,
+    )?;
+    let invoices = input::convert::<Vec<Invoice>>(
+        &cli.invoices_path(),
+        cli.input_format,
+        to,
+        Dataset::Invoices,
+    )?;
+    for (name, contents) in [("plays", plays), ("invoices", invoices)] {
+        let path = write_output(dir, &format!("{name}.{}", to.extension()), &contents)?;
+        println!("Wrote {}", path.display());
+    }
     Ok(())
 }
 
+fn run(cli: &Cli) -> Result<(), CliError> {
+    match cli.command {
+        Command::Validate => return run_validate(cli),
+        Command::Convert => return run_convert(cli),
+        _ => {}
+    }
+    let plays: HashMap<String, Play> =
+        input::load(cli.plays_path(), cli.input_format, Dataset::Plays)?;
+    if cli.command == Command::ListPlays {
+        print!(
+            "{}",
+            list_plays(&plays, cli.format.as_deref().unwrap_or("text"))?
+        );
+        return Ok(());
+    }
+    let pricing = Pricing::load(cli)?;
+    let context = pricing.context(&plays, cli);
+    let invoices_path = cli.invoices_path();
+    if cli.command == Command::Statement
+        && DataFormat::detect(&invoices_path, cli.input_format, Dataset::Invoices)
+            == DataFormat::Ndjson
+    {
+        if cli.ledger.is_some() {
+            return Err(CliError::Usage(
+                "ndjson invoices are streamed without the credit ledger; \
+                 leave out --ledger to make their statements"
+                    .to_string(),
+            ));
+        }
+        return run_stream(cli, context);
+    }
+    let invoices: Vec<Invoice> = input::load(invoices_path, cli.input_format, Dataset::Invoices)?;
+    let invoices = select_invoices(cli, &invoices)?;
+
+    match cli.command {
+        Command::Statement => run_statements(cli, &invoices, context),
+        Command::Report => {
+            let format = cli.format.as_deref().unwrap_or("text");
+            // The report covers the invoices that priced; the others fail
+            // the run once it is out.
+            let mut failures = Failures::default();
+            let selected: Vec<&Invoice> = invoices.iter().map(|(_, invoice)| *invoice).collect();
+            let output = report(&selected, &context, format, &mut failures)?;
+            match &cli.out_dir {
+                Some(dir) => {
+                    let name = format!("report.{}", render::extension(format));
+                    let path = write_output(dir, &name, &output)?;
+                    println!("Wrote {}", path.display());
+                }
+                None => print!("{}", output),
+            }
+            failures.finish(invoices.len())
+        }
+        Command::Validate | Command::Convert | Command::ListPlays | Command::Help => Ok(()),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = match Cli::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            return ExitCode::from(err.exit_code());
+        }
+    };
+    if cli.command == Command::Help {
+        print!("{}", cli::USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+/// The chapter's sample plays and invoices, built in so that tests do not
+/// depend on the working directory.
+#[cfg(test)]
+fn load_fixtures() -> (HashMap<String, Play>, Vec<Invoice>) {
+    (
+        serde_json::from_str(include_str!("../../plays.json")).expect("Failed to parse plays.json"),
+        serde_json::from_str(include_str!("../../invoices.json"))
+            .expect("Failed to parse invoices.json"),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
 
     use super::*;
+    use crate::render::{HtmlRenderer, TextRenderer};
+
+    fn statement(
+        invoice: &Invoice,
+        context: &StatementContext,
+        renderer: &dyn StatementRenderer,
+    ) -> Result<String, StatementError> {
+        renderer.render(&create_statement_data(invoice, context)?)
+    }
 
     #[test]
     fn test_statement_output_from_files() {
@@ -125,10 +793,456 @@ mod tests {
 
         // Test the first invoice
         let invoice = &invoices[0];
-        let result = statement(invoice, &plays);
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        let context = StatementContext::new(&plays, &registry);
+        let result = statement(invoice, &context, &TextRenderer::default())
+            .expect("Failed to render statement");
 
         let expected_output = "Statement for BigCo\n Hamlet: $650.00 (55 seats)\n As You Like It: $580.00 (35 seats)\n Othello: $500.00 (40 seats)\nAmount owed is $1730.00\nYou earned 47 credits\n";
 
         assert_eq!(result, expected_output);
     }
+
+    #[test]
+    fn test_pricing_file_matches_default_rules() {
+        let (plays, invoices) = load_fixtures();
+        let rules =
+            PricingRules::from_file("../pricing.json").expect("Failed to read pricing.json");
+
+        let from_file = CalculatorRegistry::from_rules(&rules);
+        let default = CalculatorRegistry::from_rules(&PricingRules::default());
+
+        assert_eq!(
+            statement(
+                &invoices[0],
+                &StatementContext::new(&plays, &from_file),
+                &TextRenderer::default()
+            )
+            .unwrap(),
+            statement(
+                &invoices[0],
+                &StatementContext::new(&plays, &default),
+                &TextRenderer::default()
+            )
+            .unwrap()
+        );
+    }
+
+    struct MusicalCalculator {
+        base: create_statement_data::PerformanceCalculatorBase,
+    }
+    impl create_statement_data::PerformanceCalculator for MusicalCalculator {
+        fn audience(&self) -> u32 {
+            self.base.performance.audience
+        }
+        fn get_amount(&self) -> Result<money::Money, money::MoneyError> {
+            Ok(money::Money::new(50000, money::Currency::Usd))
+        }
+        fn get_play(&self) -> &Play {
+            &self.base.play
+        }
+    }
+
+    #[test]
+    fn test_registered_kind_is_used() {
+        let mut plays = HashMap::new();
+        plays.insert(
+            "cats".to_string(),
+            Play {
+                name: "Cats".to_string(),
+                kind: "musical".to_string(),
+            },
+        );
+        let invoice = Invoice {
+            customer: "Acme".to_string(),
+            performances: vec![Performance {
+                play_id: "cats".to_string(),
+                audience: 40,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        registry.register("musical", |perf, play| {
+            Box::new(MusicalCalculator {
+                base: create_statement_data::PerformanceCalculatorBase {
+                    performance: perf.clone(),
+                    play,
+                },
+            })
+        });
+
+        assert_eq!(registry.kinds(), ["comedy", "musical", "tragedy"]);
+        assert_eq!(
+            statement(
+                &invoice,
+                &StatementContext::new(&plays, &registry),
+                &TextRenderer::default()
+            )
+            .unwrap(),
+            "Statement for Acme\n Cats: $500.00 (40 seats)\nAmount owed is $500.00\nYou earned 10 credits\n"
+        );
+    }
+
+    #[test]
+    fn test_bad_performance_is_reported_with_context() {
+        let (plays, mut invoices) = load_fixtures();
+        invoices[0].performances[1].play_id = "macbeth".to_string();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+
+        match statement(
+            &invoices[0],
+            &StatementContext::new(&plays, &registry),
+            &TextRenderer::default(),
+        ) {
+            Err(StatementError::MissingPlay {
+                customer,
+                performance,
+                play_id,
+            }) => {
+                assert_eq!(customer, "BigCo");
+                assert_eq!(performance, 1);
+                assert_eq!(play_id, "macbeth");
+            }
+            other => panic!("expected MissingPlay, got {other:?}"),
+        }
+
+        // Credits overflow before the comedy's amount does.
+        invoices[0].performances[1].play_id = "as-like".to_string();
+        invoices[0].performances[1].audience = u32::MAX;
+        assert!(matches!(
+            statement(
+                &invoices[0],
+                &StatementContext::new(&plays, &registry),
+                &TextRenderer::default()
+            ),
+            Err(StatementError::Overflow { performance: 1, .. })
+        ));
+
+        let rules: PricingRules = serde_json::from_str(&format!(
+            r#"{{"tragedy": {{"base_amount": 0, "threshold": 0, "per_seat": {},
+                "credits": {{"threshold": 30}}}}}}"#,
+            u32::MAX
+        ))
+        .unwrap();
+        invoices[0].performances[1].play_id = "hamlet".to_string();
+        invoices[0].performances[1].audience = u32::MAX;
+        let registry = CalculatorRegistry::from_rules(&rules);
+        assert!(matches!(
+            statement(
+                &invoices[0],
+                &StatementContext::new(&plays, &registry),
+                &TextRenderer::default()
+            ),
+            Err(StatementError::Overflow { performance: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_statement_in_billing_currency() {
+        let (plays, mut invoices) = load_fixtures();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        let rates = ExchangeRates::from_file("../rates.json").expect("Failed to read rates.json");
+        let context = StatementContext::new(&plays, &registry).with_rates(&rates);
+        invoices[0].currency = Some(Currency::Eur);
+        invoices[0].date = "2024-03-15".parse().ok();
+
+        assert_eq!(
+            statement(&invoices[0], &context, &TextRenderer::default()).unwrap(),
+            "Statement for BigCo\n Hamlet: $650.00 (55 seats)\n As You Like It: $580.00 (35 seats)\n Othello: $500.00 (40 seats)\nAmount owed is $1730.00\nAmount owed in EUR is €1589.87 (1 USD = 0.919 EUR on 2024-03-01)\nYou earned 47 credits\n"
+        );
+
+        invoices[0].date = "2023-01-01".parse().ok();
+        assert!(matches!(
+            statement(&invoices[0], &context, &TextRenderer::default()),
+            Err(StatementError::MissingRate { .. })
+        ));
+
+        // A conversion that does not fit is the invoice's, not a performance's.
+        let rates: ExchangeRates = serde_json::from_str(
+            r#"{"rates": [{"date": "2024-01-02", "from": "USD", "to": "JPY",
+                "rate": "100000000000000000"}]}"#,
+        )
+        .unwrap();
+        let context = StatementContext::new(&plays, &registry).with_rates(&rates);
+        invoices[0].currency = Some(Currency::Jpy);
+        invoices[0].date = None;
+        let err = statement(&invoices[0], &context, &TextRenderer::default()).unwrap_err();
+        assert_eq!(err.to_string(), "BigCo: invoice total overflowed");
+    }
+
+    #[test]
+    fn test_statement_with_taxes() {
+        let (plays, mut invoices) = load_fixtures();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        let taxes = TaxEngine::from_file("../taxes.json").expect("Failed to read taxes.json");
+        let context = StatementContext::new(&plays, &registry).with_taxes(&taxes);
+        invoices[0].jurisdiction = Some("DE".to_string());
+
+        let data = create_statement_data(&invoices[0], &context).unwrap();
+        assert_eq!(data.performances[0].tax_lines[0].label(), "VAT 7%");
+        assert_eq!(
+            TextRenderer::default().render(&data).unwrap(),
+            "Statement for BigCo\n Hamlet: $650.00 (55 seats)\n As You Like It: $580.00 (35 seats)\n Othello: $500.00 (40 seats)\nNet amount is $1616.83\n VAT 7%: $113.17\nAmount owed is $1730.00\nYou earned 47 credits\n"
+        );
+
+        invoices[0].jurisdiction = Some("US-NY".to_string());
+        let data = create_statement_data(&invoices[0], &context).unwrap();
+        assert_eq!(data.total_net.to_string(), "$1730.00");
+        assert_eq!(data.total_tax.to_string(), "$153.55");
+        assert_eq!(data.total_gross.to_string(), "$1883.55");
+        assert!(
+            HtmlRenderer::default()
+                .render(&data)
+                .unwrap()
+                .contains("<p>City sales tax 4.5%: <em>$77.85</em></p>\n")
+        );
+    }
+
+    #[test]
+    fn test_redeemed_credits_are_discounted() {
+        let (plays, mut invoices) = load_fixtures();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        let policy =
+            RedemptionPolicy::from_file("../credits.json").expect("Failed to read credits.json");
+        let context = StatementContext::new(&plays, &registry).with_redemption(&policy);
+        invoices[0].credit_balance = 30;
+        invoices[0].redeem_credits = 25;
+
+        assert_eq!(
+            statement(&invoices[0], &context, &TextRenderer::default()).unwrap(),
+            "Statement for BigCo\n Hamlet: $650.00 (55 seats)\n As You Like It: $580.00 (35 seats)\n Othello: $500.00 (40 seats)\nAmount owed is $1730.00\nDiscount for 25 credits is -$25.00\nRemaining balance is $1705.00\nYou earned 47 credits\nYou have 52 credits remaining\n"
+        );
+
+        invoices[0].redeem_credits = 31;
+        assert!(matches!(
+            statement(&invoices[0], &context, &TextRenderer::default()),
+            Err(StatementError::InsufficientCredits {
+                requested: 31,
+                available: 30,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_statements_are_localised() {
+        let (plays, mut invoices) = load_fixtures();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        let policy =
+            RedemptionPolicy::from_file("../credits.json").expect("Failed to read credits.json");
+        let context = StatementContext::new(&plays, &registry)
+            .with_redemption(&policy)
+            .with_locale(Locale::FrFr);
+        invoices[0].credit_balance = 30;
+        invoices[0].redeem_credits = 1;
+        invoices[0].performances[1].audience = 1;
+        invoices[0].locale = Some(Locale::DeDe);
+
+        assert_eq!(
+            statement(&invoices[0], &context, &TextRenderer::default()).unwrap(),
+            "Abrechnung für BigCo\n Hamlet: 650,00\u{a0}$ (55 Plätze)\n As You Like It: 303,00\u{a0}$ (1 Platz)\n Othello: 500,00\u{a0}$ (40 Plätze)\nZu zahlen: 1.453,00\u{a0}$\nRabatt für 1 Punkt: -1,00\u{a0}$\nVerbleibender Betrag: 1.452,00\u{a0}$\nSie haben 35 Punkte gesammelt\nSie haben noch 64 Punkte\n"
+        );
+
+        // Invoices without a locale fall back to the one from the context.
+        invoices[0].locale = None;
+        let html = statement(&invoices[0], &context, &HtmlRenderer::default()).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>\n<html lang=\"fr-FR\">"));
+        assert!(html.contains("<p>Montant dû : <em>1\u{202f}453,00\u{a0}$</em></p>\n"));
+        assert!(html.contains("<p>Il vous reste <em>64</em> crédits</p>\n"));
+    }
+
+    #[test]
+    fn test_ledger_carries_credits_across_invoices() {
+        let (plays, mut invoices) = load_fixtures();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        let mut ledger = Ledger::default();
+        ledger.clock = || 1_709_251_200; // 2024-03-01
+        let ledger = Mutex::new(ledger);
+        let context = StatementContext::new(&plays, &registry).with_ledger(&ledger);
+        invoices[0].id = Some("INV-1".to_string());
+        let data = create_statement_data(&invoices[0], &context).unwrap();
+        // Nothing is posted until the statement is out.
+        assert_eq!(ledger.lock().unwrap().balance("BigCo").unwrap(), 0);
+        assert_eq!(context.post(&data), None);
+
+        invoices[0].id = Some("INV-2".to_string());
+        invoices[0].redeem_credits = 40;
+        let data = create_statement_data(&invoices[0], &context).unwrap();
+        assert_eq!(data.credits_redeemed, 40);
+        assert_eq!(data.credits_remaining, 54);
+        assert_eq!(context.post(&data), None);
+        // A second run redeems against the balance before INV-2 again and
+        // posts nothing new.
+        let rerun = create_statement_data(&invoices[0], &context).unwrap();
+        assert_eq!(rerun.credits_remaining, 54);
+        assert_eq!(context.post(&rerun), None);
+        // A rerun that redeems other credits would disagree with the books.
+        invoices[0].redeem_credits = 10;
+        let err = create_statement_data(&invoices[0], &context).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "BigCo: invoice INV-2 was posted to the ledger with 40 credits redeemed and 47 \
+             earned, but now has 10 credits redeemed and 47 earned"
+        );
+
+        invoices[0].id = Some("INV-3".to_string());
+        invoices[0].redeem_credits = 55;
+        assert!(matches!(
+            create_statement_data(&invoices[0], &context),
+            Err(StatementError::InsufficientCredits { available: 54, .. })
+        ));
+        invoices[0].id = None;
+        invoices[0].redeem_credits = 0;
+        let data = create_statement_data(&invoices[0], &context).unwrap();
+        assert_eq!(
+            context.post(&data).unwrap(),
+            "BigCo: the invoice has no id, so its credits are not posted to the ledger"
+        );
+        assert_eq!(
+            ledger.lock().unwrap().report("BigCo").unwrap(),
+            "Ledger for BigCo\n 2024-03-01 earned 47 credits (invoice INV-1)\n 2024-03-01 redeemed 40 credits (invoice INV-2)\n 2024-03-01 earned 47 credits (invoice INV-2)\nBalance is 54 credits\n"
+        );
+    }
+
+    #[test]
+    fn test_dated_performances_are_priced_and_ordered() {
+        let (plays, mut invoices) = load_fixtures();
+        let rules: PricingRules = serde_json::from_str(
+            r#"{
+                "tragedy": {
+                    "base_amount": 40000, "threshold": 30, "per_seat_over_threshold": 1000,
+                    "credits": { "threshold": 30 },
+                    "calendar": {
+                        "weekend_surcharge_percent": "10",
+                        "off_season_months": [1, 2], "off_season_discount_percent": "20"
+                    }
+                },
+                "comedy": {
+                    "base_amount": 30000, "threshold": 20, "threshold_fee": 10000,
+                    "per_seat_over_threshold": 500, "per_seat": 300,
+                    "credits": { "threshold": 30, "bonus_divisor": 5 }
+                }
+            }"#,
+        )
+        .unwrap();
+        let registry = CalculatorRegistry::from_rules(&rules);
+        let context = StatementContext::new(&plays, &registry);
+        let performances = &mut invoices[0].performances;
+        performances[0].date = "2024-03-02".parse().ok(); // Saturday
+        performances[0].venue = Some("globe".to_string());
+        performances[1].date = "2024-03-01".parse().ok();
+        performances[2].date = "2024-02-13".parse().ok(); // off-season Tuesday
+
+        assert_eq!(
+            statement(&invoices[0], &context, &TextRenderer::default()).unwrap(),
+            "Statement for BigCo\n Othello on 2024-02-13: $400.00 (40 seats)\n As You Like It on 2024-03-01: $580.00 (35 seats)\n Hamlet on 2024-03-02 at globe: $715.00 (55 seats)\nAmount owed is $1695.00\nYou earned 47 credits\n"
+        );
+
+        let data = create_statement_data(&invoices[0], &context).unwrap();
+        assert!(
+            TextRenderer { detailed: true }
+                .render(&data)
+                .unwrap()
+                .contains(
+                    " Othello on 2024-02-13: $400.00 (40 seats)\n   base fee: $400.00\n   \
+             10 seats over 30 × $10.00: $100.00\n   off-season discount 20%: -$100.00\n"
+                )
+        );
+        let hamlet = &data.performances[2];
+        assert_eq!(hamlet.components[2].label, "weekend surcharge 10%");
+        assert_eq!(hamlet.components[2].amount.to_string(), "$65.00");
+        assert!(HtmlRenderer {
+            detailed: true,
+            ..Default::default()
+        }
+        .render(&data)
+        .unwrap()
+        .contains(
+            " <tr class=\"component\"><td colspan=\"3\">off-season discount 20%</td><td>-$100.00</td></tr>\n"
+        ));
+
+        invoices[0].performances[0].capacity = Some(50);
+        assert!(matches!(
+            statement(&invoices[0], &context, &TextRenderer::default()),
+            Err(StatementError::OverCapacity {
+                performance: 0,
+                audience: 55,
+                capacity: 50,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_errors_give_the_index_in_the_invoice() {
+        let (plays, mut invoices) = load_fixtures();
+        let rules: PricingRules = serde_json::from_str(
+            r#"{
+                "tragedy": { "base_amount": 40000, "threshold": 30, "credits": { "threshold": 30 } },
+                "comedy": { "currency": "EUR", "base_amount": 30000, "threshold": 20,
+                            "credits": { "threshold": 30 } }
+            }"#,
+        )
+        .unwrap();
+        let registry = CalculatorRegistry::from_rules(&rules);
+        // The comedy is second in the invoice but last by date.
+        let performances = &mut invoices[0].performances;
+        performances[0].date = "2024-03-01".parse().ok();
+        performances[1].date = "2024-03-03".parse().ok();
+        performances[2].date = "2024-03-02".parse().ok();
+
+        assert!(matches!(
+            create_statement_data(&invoices[0], &StatementContext::new(&plays, &registry)),
+            Err(StatementError::CurrencyMismatch { performance: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_command_helpers() {
+        let (plays, invoices) = load_fixtures();
+        assert_eq!(
+            list_plays(&plays, "text").unwrap(),
+            "as-like: As You Like It (comedy)\nhamlet: Hamlet (tragedy)\nothello: Othello (tragedy)\n"
+        );
+        assert!(
+            list_plays(&plays, "csv")
+                .unwrap()
+                .starts_with("id,name,type\nas-like,As You Like It,comedy\n")
+        );
+        assert_eq!(list_plays(&plays, "xml").unwrap_err().exit_code(), 64);
+
+        let mut cli = Cli::parse(["--customer".to_string(), "BigCo".to_string()]).unwrap();
+        let selected = select_invoices(&cli, &invoices).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].0, 1);
+        cli.customer = Some("Nobody".to_string());
+        assert_eq!(
+            select_invoices(&cli, &invoices).unwrap_err().exit_code(),
+            64
+        );
+
+        let mut failures = Failures::default();
+        assert_eq!(failures.finish(3), Ok(()));
+        failures.add(&CliError::Data("BigCo: bad".to_string()));
+        assert_eq!(
+            failures.finish(3),
+            Err(CliError::Data("1 of 3 invoices failed".to_string()))
+        );
+        failures.add(&CliError::Io("disk full".to_string()));
+        assert_eq!(failures.finish(3).unwrap_err().exit_code(), 74);
+
+        // A report leaves out the invoices that fail and counts them.
+        let mut broken = invoices[0].clone();
+        broken.performances[0].play_id = "hamlt".to_string();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        let context = StatementContext::new(&plays, &registry);
+        let mut failures = Failures::default();
+        let output = report(&[&invoices[0], &broken], &context, "csv", &mut failures).unwrap();
+        assert_eq!(output.matches("BigCo").count(), 1);
+        assert_eq!(
+            failures.finish(2),
+            Err(CliError::Data("1 of 2 invoices failed".to_string()))
+        );
+    }
 }
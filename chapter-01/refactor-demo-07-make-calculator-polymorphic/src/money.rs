@@ -0,0 +1,194 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// ISO 4217 currencies the company bills in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum Currency {
+    #[default]
+    Usd,
+    Eur,
+    Gbp,
+    Chf,
+    Jpy,
+    Kwd,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Chf => "CHF",
+            Currency::Jpy => "JPY",
+            Currency::Kwd => "KWD",
+        }
+    }
+
+    /// Number of decimal places of the minor unit, as defined by ISO 4217.
+    pub fn minor_units(&self) -> u32 {
+        match self {
+            Currency::Jpy => 0,
+            Currency::Kwd => 3,
+            _ => 2,
+        }
+    }
+
+    pub fn symbol(&self) -> Option<&'static str> {
+        match self {
+            Currency::Usd => Some("$"),
+            Currency::Eur => Some("€"),
+            Currency::Gbp => Some("£"),
+            Currency::Jpy => Some("¥"),
+            Currency::Chf | Currency::Kwd => None,
+        }
+    }
+}
+
+impl FromStr for Currency {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "USD" => Ok(Currency::Usd),
+            "EUR" => Ok(Currency::Eur),
+            "GBP" => Ok(Currency::Gbp),
+            "CHF" => Ok(Currency::Chf),
+            "JPY" => Ok(Currency::Jpy),
+            "KWD" => Ok(Currency::Kwd),
+            _ => Err(MoneyError::UnknownCurrency(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum MoneyError {
+    Overflow,
+    CurrencyMismatch(Currency, Currency),
+    UnknownCurrency(String),
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::Overflow => write!(f, "amount overflowed"),
+            MoneyError::CurrencyMismatch(a, b) => write!(f, "cannot combine {a} with {b}"),
+            MoneyError::UnknownCurrency(code) => write!(f, "unknown currency: {code}"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// An amount in the minor unit of its currency (cents for USD, yen for JPY).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub(crate) struct Money {
+    pub minor_units: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(minor_units: i64, currency: Currency) -> Self {
+        Money {
+            minor_units,
+            currency,
+        }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Money::new(0, currency)
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch(self.currency, other.currency));
+        }
+        self.minor_units
+            .checked_add(other.minor_units)
+            .map(|minor_units| Money::new(minor_units, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        let negated = other
+            .minor_units
+            .checked_neg()
+            .ok_or(MoneyError::Overflow)?;
+        self.checked_add(Money::new(negated, other.currency))
+    }
+
+    pub fn checked_mul(self, factor: i64) -> Result<Money, MoneyError> {
+        self.minor_units
+            .checked_mul(factor)
+            .map(|minor_units| Money::new(minor_units, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// The amount without symbol, e.g. `1730.00` for USD or `1730` for JPY.
+    pub fn format_amount(&self) -> String {
+        let digits = self.currency.minor_units();
+        let sign = if self.minor_units < 0 { "-" } else { "" };
+        let abs = self.minor_units.unsigned_abs();
+        if digits == 0 {
+            return format!("{sign}{abs}");
+        }
+        let scale = 10u64.pow(digits);
+        format!(
+            "{sign}{}.{:0width$}",
+            abs / scale,
+            abs % scale,
+            width = digits as usize
+        )
+    }
+}
+
+// `$1730.00`, `¥1730`, `KWD 1.730`: the symbol when the currency has one,
+// otherwise the ISO code.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let amount = self.format_amount();
+        let (sign, amount) = match amount.strip_prefix('-') {
+            Some(rest) => ("-", rest.to_string()),
+            None => ("", amount),
+        };
+        match self.currency.symbol() {
+            Some(symbol) => write!(f, "{sign}{symbol}{amount}"),
+            None => write!(f, "{sign}{} {amount}", self.currency.code()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_respects_minor_units() {
+        assert_eq!(Money::new(173000, Currency::Usd).to_string(), "$1730.00");
+        assert_eq!(Money::new(1730, Currency::Jpy).to_string(), "¥1730");
+        assert_eq!(Money::new(1730, Currency::Kwd).to_string(), "KWD 1.730");
+        assert_eq!(Money::new(-505, Currency::Eur).to_string(), "-€5.05");
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let ten = Money::new(1000, Currency::Usd);
+        assert_eq!(ten.checked_mul(3), Ok(Money::new(3000, Currency::Usd)));
+        assert_eq!(
+            Money::new(i64::MAX, Currency::Usd).checked_add(ten),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(
+            ten.checked_add(Money::new(1000, Currency::Eur)),
+            Err(MoneyError::CurrencyMismatch(Currency::Usd, Currency::Eur))
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/// A seller or buyer as identified on an e-invoice.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub(crate) struct Party {
+    /// The registered legal name.
+    pub name: String,
+    #[serde(default)]
+    pub street: Option<String>,
+    #[serde(default)]
+    pub city: Option<String>,
+    #[serde(default)]
+    pub postal_code: Option<String>,
+    /// ISO 3166-1 alpha-2 code, e.g. `GB`.
+    pub country: String,
+    /// VAT identifier including its country prefix, e.g. `GB123456789`.
+    #[serde(default)]
+    pub vat_id: Option<String>,
+    #[serde(default)]
+    pub endpoint: Option<Endpoint>,
+}
+
+impl Party {
+    /// The name, postal address and VAT identifier, one line each.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![self.name.clone()];
+        lines.extend(self.street.clone());
+        let town: Vec<&str> = [self.postal_code.as_deref(), self.city.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        if !town.is_empty() {
+            lines.push(town.join(" "));
+        }
+        lines.push(self.country.clone());
+        lines.extend(self.vat_id.as_ref().map(|vat_id| format!("VAT {vat_id}")));
+        lines
+    }
+}
+
+/// An electronic address invoices can be delivered to, such as a Peppol
+/// participant identifier.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct Endpoint {
+    /// The ISO 6523 scheme code, e.g. `0088` for GLN or `9932` for UK VAT.
+    pub scheme: String,
+    pub id: String,
+}
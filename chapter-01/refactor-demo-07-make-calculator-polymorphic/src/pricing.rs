@@ -0,0 +1,236 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    date::Date,
+    error::StatementError,
+    exchange::{Rate, RoundingMode},
+    money::{Currency, Money, MoneyError},
+    read_json,
+};
+
+/// Pricing rules for one kind of play. All amounts are in the minor unit of
+/// `currency`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub(crate) struct PricingRule {
+    #[serde(default)]
+    pub currency: Currency,
+    pub base_amount: u32,
+    /// Audience size above which the surcharges apply.
+    pub threshold: u32,
+    /// Flat fee added once the audience exceeds the threshold.
+    #[serde(default)]
+    pub threshold_fee: u32,
+    #[serde(default)]
+    pub per_seat_over_threshold: u32,
+    /// Charged for every seat, regardless of the threshold.
+    #[serde(default)]
+    pub per_seat: u32,
+    pub credits: CreditRule,
+    #[serde(default)]
+    pub calendar: CalendarRule,
+}
+
+/// Adjustments for when a performance takes place. Percentages are applied to
+/// the audience-based amount; a holiday surcharge replaces the weekend one.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub(crate) struct CalendarRule {
+    #[serde(default)]
+    pub weekend_surcharge_percent: Option<Rate>,
+    #[serde(default)]
+    pub holiday_surcharge_percent: Option<Rate>,
+    #[serde(default)]
+    pub holidays: Vec<Date>,
+    /// Months (1-12) in which the off-season discount applies.
+    #[serde(default)]
+    pub off_season_months: Vec<u32>,
+    #[serde(default)]
+    pub off_season_discount_percent: Option<Rate>,
+}
+
+impl CalendarRule {
+    /// Surcharges and discounts for a performance on `date` whose
+    /// audience-based price is `subtotal`.
+    pub fn components(
+        &self,
+        subtotal: Money,
+        date: Option<Date>,
+    ) -> Result<Vec<PriceComponent>, MoneyError> {
+        let mut result = Vec::new();
+        let Some(date) = date else {
+            return Ok(result);
+        };
+        let rounding = RoundingMode::default();
+        let surcharge = if self.holidays.contains(&date) {
+            self.holiday_surcharge_percent
+                .map(|percent| ("holiday surcharge", percent))
+        } else if date.is_weekend() {
+            self.weekend_surcharge_percent
+                .map(|percent| ("weekend surcharge", percent))
+        } else {
+            None
+        };
+        let mut total = subtotal;
+        if let Some((label, percent)) = surcharge {
+            let amount = percent.percent_of(subtotal, rounding)?;
+            total = total.checked_add(amount)?;
+            result.push(PriceComponent::new(format!("{label} {percent}%"), amount));
+        }
+        if let Some(percent) = self.off_season_discount_percent
+            && self.off_season_months.contains(&date.month())
+        {
+            let amount =
+                Money::zero(total.currency).checked_sub(percent.percent_of(total, rounding)?)?;
+            result.push(PriceComponent::new(
+                format!("off-season discount {percent}%"),
+                amount,
+            ));
+        }
+        Ok(result)
+    }
+}
+
+/// A named part of a performance's price, such as the base fee or a weekend
+/// surcharge. The components of a performance add up to its amount.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct PriceComponent {
+    pub label: String,
+    pub amount: Money,
+}
+
+impl PriceComponent {
+    pub fn new(label: impl Into<String>, amount: Money) -> Self {
+        PriceComponent {
+            label: label.into(),
+            amount,
+        }
+    }
+}
+
+pub(crate) fn sum_components(
+    components: &[PriceComponent],
+    currency: Currency,
+) -> Result<Money, MoneyError> {
+    components
+        .iter()
+        .try_fold(Money::zero(currency), |sum, c| sum.checked_add(c.amount))
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub(crate) struct CreditRule {
+    /// One credit is earned for every seat over this threshold.
+    pub threshold: u32,
+    /// One extra credit for every `bonus_divisor` seats, if set.
+    #[serde(default)]
+    pub bonus_divisor: Option<u32>,
+}
+
+impl PricingRule {
+    /// The amount for `audience` on `date`, the sum of its components.
+    pub fn amount_on(&self, audience: u32, date: Option<Date>) -> Result<Money, MoneyError> {
+        sum_components(&self.components(audience, date)?, self.currency)
+    }
+
+    pub fn components(
+        &self,
+        audience: u32,
+        date: Option<Date>,
+    ) -> Result<Vec<PriceComponent>, MoneyError> {
+        let mut result = vec![PriceComponent::new(
+            "base fee",
+            self.price(self.base_amount),
+        )];
+        if audience > self.threshold {
+            if self.threshold_fee > 0 {
+                result.push(PriceComponent::new(
+                    format!("fee for more than {} seats", self.threshold),
+                    self.price(self.threshold_fee),
+                ));
+            }
+            if self.per_seat_over_threshold > 0 {
+                let seats = audience - self.threshold;
+                let unit = self.price(self.per_seat_over_threshold);
+                result.push(PriceComponent::new(
+                    format!("{} seats over {} × {}", seats, self.threshold, unit),
+                    unit.checked_mul(seats.into())?,
+                ));
+            }
+        }
+        if self.per_seat > 0 {
+            let unit = self.price(self.per_seat);
+            result.push(PriceComponent::new(
+                format!("{} seats × {}", audience, unit),
+                unit.checked_mul(audience.into())?,
+            ));
+        }
+        let subtotal = sum_components(&result, self.currency)?;
+        result.extend(self.calendar.components(subtotal, date)?);
+        Ok(result)
+    }
+
+    fn price(&self, minor_units: u32) -> Money {
+        Money::new(minor_units.into(), self.currency)
+    }
+
+    /// The credits earned for `audience`, or `None` if they overflow.
+    pub fn volume_credits(&self, audience: u32) -> Option<u32> {
+        let result = audience.saturating_sub(self.credits.threshold);
+        match self.credits.bonus_divisor.filter(|d| *d > 0) {
+            Some(divisor) => result.checked_add(audience / divisor),
+            None => Some(result),
+        }
+    }
+}
+
+/// Pricing rules keyed by play kind, as found in `pricing.json`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(transparent)]
+pub(crate) struct PricingRules {
+    rules: HashMap<String, PricingRule>,
+}
+
+impl PricingRules {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, StatementError> {
+        read_json(path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PricingRule)> {
+        self.rules.iter().map(|(kind, rule)| (kind.as_str(), rule))
+    }
+}
+
+// The rates the company has always charged, kept as the fallback rule set.
+impl Default for PricingRules {
+    fn default() -> Self {
+        let tragedy = PricingRule {
+            base_amount: 40000,
+            threshold: 30,
+            per_seat_over_threshold: 1000,
+            credits: CreditRule {
+                threshold: 30,
+                bonus_divisor: None,
+            },
+            ..Default::default()
+        };
+        let comedy = PricingRule {
+            currency: Currency::Usd,
+            base_amount: 30000,
+            threshold: 20,
+            threshold_fee: 10000,
+            per_seat_over_threshold: 500,
+            per_seat: 300,
+            credits: CreditRule {
+                threshold: 30,
+                bonus_divisor: Some(5),
+            },
+            calendar: CalendarRule::default(),
+        };
+        PricingRules {
+            rules: HashMap::from([
+                ("tragedy".to_string(), tragedy),
+                ("comedy".to_string(), comedy),
+            ]),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use super::{company::Company, create_statement_data::StatementData, error::StatementError};
+
+mod chart;
+mod csv;
+mod html;
+mod json;
+mod markdown;
+mod pdf;
+mod table;
+mod template;
+mod text;
+mod ubl;
+
+pub(crate) use chart::{Datum, SvgRenderer, bar_chart, pie_chart, stack};
+pub(crate) use csv::{CsvRenderer, field as csv_field};
+pub(crate) use html::HtmlRenderer;
+pub(crate) use json::JsonRenderer;
+pub(crate) use markdown::MarkdownRenderer;
+pub(crate) use pdf::PdfRenderer;
+pub(crate) use table::{TableRenderer, color_enabled, unicode_enabled};
+pub(crate) use template::{Template, TemplateRenderer, load_templates};
+pub(crate) use text::TextRenderer;
+pub(crate) use ubl::{UblRenderer, validate};
+
+/// Turns computed statement data into one output format. Renderers are
+/// shared by the threads of a batch.
+pub(crate) trait StatementRenderer: Send + Sync {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError>;
+}
+
+/// Settings shared by all renderers; each uses the ones that apply to it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RenderOptions {
+    /// Show the price components under each performance.
+    pub detailed: bool,
+    /// The issuing company, for letterheads and seller details.
+    pub company: Option<Company>,
+    /// The template for the "template" format; the built-in one if unset.
+    pub template: Option<Template>,
+    /// Embed SVG charts where the format allows, as HTML does.
+    pub charts: bool,
+    /// Draw tables with ASCII characters only.
+    pub ascii: bool,
+    /// Use ANSI colours in terminal output.
+    pub color: bool,
+}
+
+/// A name for a statement's files: `statement-` and the invoice id, or the
+/// customer and the invoice's position in the input if it has none, reduced to
+/// lowercase letters, digits and dashes.
+pub(crate) fn file_stem(invoice_id: Option<&str>, customer: &str, position: usize) -> String {
+    let name = match invoice_id {
+        Some(id) => id.to_string(),
+        // A customer's invoices would otherwise overwrite each other.
+        None => format!("{customer} {position}"),
+    };
+    let mut stem = String::from("statement");
+    for word in name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        stem.push('-');
+        stem.push_str(&word.to_ascii_lowercase());
+    }
+    stem
+}
+
+/// The file extension for output in `format`.
+pub(crate) fn extension(format: &str) -> &str {
+    match format {
+        "text" | "table" | "template" => "txt",
+        "markdown" => "md",
+        "ubl" => "xml",
+        other => other,
+    }
+}
+
+pub(crate) type RendererFactory =
+    Box<dyn Fn(&RenderOptions) -> Box<dyn StatementRenderer> + Send + Sync>;
+
+/// Maps format names such as "text" or "json" to their renderers.
+#[derive(Default)]
+pub(crate) struct RendererRegistry {
+    factories: HashMap<String, RendererFactory>,
+}
+
+impl RendererRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The text, table, HTML, Markdown, PDF, CSV, JSON, SVG, UBL and
+    /// template renderers.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register("text", |options| {
+            Box::new(TextRenderer {
+                detailed: options.detailed,
+            })
+        });
+        registry.register("html", |options| {
+            Box::new(HtmlRenderer {
+                detailed: options.detailed,
+                letterhead: options.company.clone(),
+                charts: options.charts,
+            })
+        });
+        registry.register("markdown", |options| {
+            Box::new(MarkdownRenderer {
+                detailed: options.detailed,
+            })
+        });
+        registry.register("pdf", |options| {
+            Box::new(PdfRenderer {
+                detailed: options.detailed,
+                letterhead: options.company.clone(),
+            })
+        });
+        registry.register("csv", |_| Box::new(CsvRenderer));
+        registry.register("json", |_| Box::new(JsonRenderer));
+        registry.register("svg", |_| Box::new(SvgRenderer));
+        registry.register("ubl", |options| {
+            Box::new(UblRenderer {
+                seller: options.company.as_ref().and_then(|c| c.party.clone()),
+            })
+        });
+        registry.register("table", |options| {
+            Box::new(TableRenderer {
+                detailed: options.detailed,
+                ascii: options.ascii,
+                color: options.color,
+            })
+        });
+        registry.register("template", |options| {
+            Box::new(TemplateRenderer {
+                template: options
+                    .template
+                    .clone()
+                    .unwrap_or_else(|| Template::builtin().clone()),
+                detailed: options.detailed,
+            })
+        });
+        registry
+    }
+
+    /// Registers `factory` for `format`, replacing any previous factory.
+    pub fn register<F>(&mut self, format: impl Into<String>, factory: F)
+    where
+        F: Fn(&RenderOptions) -> Box<dyn StatementRenderer> + Send + Sync + 'static,
+    {
+        self.factories.insert(format.into(), Box::new(factory));
+    }
+
+    pub fn create(
+        &self,
+        format: &str,
+        options: &RenderOptions,
+    ) -> Option<Box<dyn StatementRenderer>> {
+        self.factories.get(format).map(|factory| factory(options))
+    }
+
+    /// The registered formats, sorted for stable diagnostics.
+    pub fn formats(&self) -> Vec<&str> {
+        let mut formats: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+        formats.sort_unstable();
+        formats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        calculator_registry::CalculatorRegistry,
+        create_statement_data::{StatementContext, create_statement_data},
+        load_fixtures,
+        pricing::PricingRules,
+    };
+
+    fn big_co() -> StatementData {
+        let (plays, invoices) = load_fixtures();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        create_statement_data(&invoices[0], &StatementContext::new(&plays, &registry)).unwrap()
+    }
+
+    fn render(format: &str) -> String {
+        RendererRegistry::builtin()
+            .create(format, &RenderOptions::default())
+            .unwrap()
+            .render(&big_co())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_formats_are_looked_up_by_name() {
+        let registry = RendererRegistry::builtin();
+        assert_eq!(
+            registry.formats(),
+            [
+                "csv", "html", "json", "markdown", "pdf", "svg", "table", "template", "text", "ubl"
+            ]
+        );
+        assert!(registry.create("docx", &RenderOptions::default()).is_none());
+        assert_eq!(file_stem(Some("INV 42"), "BigCo", 1), "statement-inv-42");
+        assert_eq!(file_stem(None, "Acme, Inc.", 2), "statement-acme-inc-2");
+        assert_eq!(extension("markdown"), "md");
+
+        assert_eq!(
+            render("markdown"),
+            "# Statement for BigCo\n\n| Play | Seats | Amount |\n| --- | ---: | ---: |\n| Hamlet | 55 | $650.00 |\n| As You Like It | 35 | $580.00 |\n| Othello | 40 | $500.00 |\n\n- Amount owed is **$1730.00**\n- You earned **47** credits\n"
+        );
+        assert_eq!(
+            render("csv"),
+            "customer,play,date,venue,seats,amount,currency,credits\nBigCo,Hamlet,,,55,650.00,USD,25\nBigCo,As You Like It,,,35,580.00,USD,12\nBigCo,Othello,,,40,500.00,USD,10\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&render("json")).unwrap();
+        assert_eq!(json["customer"], "BigCo");
+        assert_eq!(json["performances"][0]["play"]["type"], "tragedy");
+        assert_eq!(json["total_amount"]["minor_units"], 173000);
+        assert_eq!(json["total_amount"]["currency"], "USD");
+    }
+
+    #[test]
+    fn test_sample_letter_template() {
+        let template = load_templates("../templates")
+            .unwrap()
+            .remove("letter")
+            .unwrap();
+        let options = RenderOptions {
+            template: Some(template),
+            ..Default::default()
+        };
+        let letter = RendererRegistry::builtin()
+            .create("template", &options)
+            .unwrap()
+            .render(&big_co())
+            .unwrap();
+        assert!(letter.starts_with(
+            "Dear BigCo,
+
+Thank you for your custom."
+        ));
+        assert!(letter.contains(
+            "  - Hamlet, 55 seats: $650.00
+"
+        ));
+        assert!(letter.contains(
+            "You earned 47 loyalty credits with this booking.
+"
+        ));
+        assert_eq!(render("template"), render("text"));
+    }
+}
@@ -0,0 +1,396 @@
+//! Bar and pie charts as SVG, laid out by hand. Text widths are estimated,
+//! since no fonts are available to measure with.
+
+use super::{StatementRenderer, html::escape};
+use crate::{
+    create_statement_data::StatementData,
+    error::StatementError,
+    locale::{self, Locale},
+    money::Money,
+};
+
+const COLORS: [&str; 6] = [
+    "#4e79a7", "#f28e2b", "#59a14f", "#e15759", "#76b7b2", "#b07aa1",
+];
+const FONT_SIZE: f64 = 12.0;
+const TITLE_HEIGHT: f64 = 30.0;
+
+/// Both charts of a statement as one SVG document, the bar chart above the
+/// pie chart.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SvgRenderer;
+
+impl StatementRenderer for SvgRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        let bars = performance_chart(data);
+        let pie = kind_chart(data);
+        Ok(stack(&[bars, pie]))
+    }
+}
+
+/// One bar or slice of a chart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Datum {
+    pub label: String,
+    pub value: Money,
+}
+
+/// An SVG element with its size, so charts can be stacked.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Chart {
+    pub width: f64,
+    pub height: f64,
+    /// The `<svg>` element, without an XML declaration so that it can be
+    /// inlined in HTML.
+    pub svg: String,
+}
+
+/// The amount of each performance, as listed on the statement.
+pub(crate) fn performance_chart(data: &StatementData) -> Chart {
+    let bars: Vec<Datum> = data
+        .performances
+        .iter()
+        .map(|perf| Datum {
+            label: perf.play.name.clone(),
+            value: perf.amount,
+        })
+        .collect();
+    bar_chart(&chart_title(data, "chart-performances"), &bars, data.locale)
+}
+
+/// Net revenue by play kind, in order of first appearance.
+pub(crate) fn kind_chart(data: &StatementData) -> Chart {
+    let mut slices: Vec<Datum> = Vec::new();
+    for perf in &data.performances {
+        match slices
+            .iter_mut()
+            .find(|slice| slice.label == perf.play.kind)
+        {
+            // Amounts on one statement share its currency.
+            Some(slice) => {
+                slice.value.minor_units = slice
+                    .value
+                    .minor_units
+                    .saturating_add(perf.net_amount.minor_units)
+            }
+            None => slices.push(Datum {
+                label: perf.play.kind.clone(),
+                value: perf.net_amount,
+            }),
+        }
+    }
+    pie_chart(&chart_title(data, "chart-kinds"), &slices, data.locale)
+}
+
+fn chart_title(data: &StatementData, id: &str) -> String {
+    locale::message(data.locale, id, &[]).expect("catalogues define every chart title")
+}
+
+// Estimates how wide `text` is, counting wide scripts such as Japanese as a
+// full em and everything else as about half of one.
+fn text_width(text: &str) -> f64 {
+    text.chars()
+        .map(|c| if (c as u32) >= 0x1100 { 1.0 } else { 0.6 })
+        .sum::<f64>()
+        * FONT_SIZE
+}
+
+// A coordinate with at most one decimal, without trailing zeros.
+fn px(value: f64) -> String {
+    let rounded = (value * 10.0).round() / 10.0;
+    if rounded.fract() == 0.0 {
+        format!("{}", rounded as i64)
+    } else {
+        format!("{rounded:.1}")
+    }
+}
+
+fn open_svg(width: f64, height: f64, title: &str) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+         font-family=\"sans-serif\" font-size=\"{}\" role=\"img\">\n\
+         <title>{title}</title>\n\
+         <text x=\"{}\" y=\"20\" text-anchor=\"middle\" font-size=\"14\" font-weight=\"bold\">{title}</text>\n",
+        FONT_SIZE,
+        px(width / 2.0),
+        w = px(width),
+        h = px(height),
+        title = escape(title),
+    )
+}
+
+// The smallest step of 1, 2 or 5 times a power of ten that divides `max`
+// into at most four intervals.
+fn tick_step(max: i64) -> i64 {
+    let target = (max + 3) / 4;
+    let mut magnitude = 1;
+    while magnitude * 10 <= target {
+        magnitude *= 10;
+    }
+    [1, 2, 5, 10]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= target)
+        .unwrap_or(10 * magnitude)
+        .max(1)
+}
+
+/// A vertical bar chart with a money axis. Negative values are drawn as
+/// empty bars.
+pub(crate) fn bar_chart(title: &str, bars: &[Datum], locale: Option<Locale>) -> Chart {
+    const PLOT_HEIGHT: f64 = 160.0;
+    const SLOT: f64 = 60.0;
+    let currency = bars
+        .first()
+        .map(|bar| bar.value.currency)
+        .unwrap_or_default();
+    let max = bars
+        .iter()
+        .map(|bar| bar.value.minor_units)
+        .max()
+        .unwrap_or(0)
+        .max(0);
+    // Whole units of the currency make for round axis labels.
+    let unit = 10_i64.pow(currency.minor_units());
+    let step = tick_step((max + unit - 1) / unit) * unit;
+    let ticks = ((max + step - 1) / step).max(1);
+    let top = step * ticks;
+    let labels: Vec<String> = (0..=ticks)
+        .map(|tick| locale::format_money(locale, Money::new(tick * step, currency)))
+        .collect();
+    let axis_x = labels
+        .iter()
+        .map(|label| text_width(label))
+        .fold(0.0, f64::max)
+        + 16.0;
+    // Play names are written at 45 degrees below the bars.
+    let label_depth = bars
+        .iter()
+        .map(|bar| text_width(&bar.label))
+        .fold(0.0, f64::max)
+        * std::f64::consts::FRAC_1_SQRT_2
+        + 20.0;
+    let width = (axis_x + SLOT * bars.len() as f64 + 20.0).max(240.0);
+    let height = TITLE_HEIGHT + 10.0 + PLOT_HEIGHT + label_depth;
+    let base = TITLE_HEIGHT + 10.0 + PLOT_HEIGHT;
+    let y = |minor_units: i64| base - PLOT_HEIGHT * minor_units.max(0) as f64 / top as f64;
+
+    let mut svg = open_svg(width, height, title);
+    for (tick, label) in labels.iter().enumerate() {
+        let tick_y = px(y(step * tick as i64));
+        svg += &format!(
+            "<line x1=\"{}\" y1=\"{tick_y}\" x2=\"{}\" y2=\"{tick_y}\" stroke=\"#ccc\"/>\n\
+             <text x=\"{}\" y=\"{tick_y}\" text-anchor=\"end\" dominant-baseline=\"middle\">{}</text>\n",
+            px(axis_x),
+            px(width - 10.0),
+            px(axis_x - 6.0),
+            escape(label)
+        );
+    }
+    for (index, bar) in bars.iter().enumerate() {
+        let x = axis_x + SLOT * index as f64 + SLOT * 0.15;
+        let bar_y = y(bar.value.minor_units);
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"><title>{}: {}</title></rect>\n",
+            px(x),
+            px(bar_y),
+            px(SLOT * 0.7),
+            px(base - bar_y),
+            COLORS[index % COLORS.len()],
+            escape(&bar.label),
+            escape(&locale::format_money(locale, bar.value))
+        );
+        let label_x = px(x + SLOT * 0.35);
+        let label_y = px(base + 14.0);
+        svg += &format!(
+            "<text x=\"{label_x}\" y=\"{label_y}\" text-anchor=\"end\" transform=\"rotate(-45 {label_x} {label_y})\">{}</text>\n",
+            escape(&bar.label)
+        );
+    }
+    svg += &format!(
+        "<line x1=\"{x}\" y1=\"{}\" x2=\"{x}\" y2=\"{}\" stroke=\"#222\"/>\n\
+         <line x1=\"{x}\" y1=\"{b}\" x2=\"{}\" y2=\"{b}\" stroke=\"#222\"/>\n</svg>\n",
+        px(TITLE_HEIGHT + 10.0),
+        px(base),
+        px(width - 10.0),
+        x = px(axis_x),
+        b = px(base),
+    );
+    Chart { width, height, svg }
+}
+
+/// A pie chart with a legend giving each slice's amount and share. Slices
+/// that are not positive are left out.
+pub(crate) fn pie_chart(title: &str, slices: &[Datum], locale: Option<Locale>) -> Chart {
+    const RADIUS: f64 = 80.0;
+    const LEGEND_X: f64 = 2.0 * RADIUS + 40.0;
+    let slices: Vec<&Datum> = slices
+        .iter()
+        .filter(|slice| slice.value.minor_units > 0)
+        .collect();
+    let total: i64 = slices.iter().map(|slice| slice.value.minor_units).sum();
+    let legend: Vec<String> = slices
+        .iter()
+        .map(|slice| {
+            let percent = (slice.value.minor_units as f64 * 100.0 / total as f64).round();
+            format!(
+                "{}: {} ({}%)",
+                slice.label,
+                locale::format_money(locale, slice.value),
+                percent
+            )
+        })
+        .collect();
+    let legend_width = legend
+        .iter()
+        .map(|line| text_width(line))
+        .fold(0.0, f64::max);
+    let width = LEGEND_X + 18.0 + legend_width + 10.0;
+    let height =
+        (TITLE_HEIGHT + 2.0 * RADIUS + 20.0).max(TITLE_HEIGHT + 20.0 * legend.len() as f64 + 20.0);
+    let (cx, cy) = (RADIUS + 20.0, TITLE_HEIGHT + 10.0 + RADIUS);
+
+    let mut svg = open_svg(width, height, title);
+    let mut angle = 0.0_f64;
+    for (index, slice) in slices.iter().enumerate() {
+        let color = COLORS[index % COLORS.len()];
+        let share = slice.value.minor_units as f64 / total as f64;
+        let tooltip = format!("<title>{}</title>", escape(&legend[index]));
+        if slices.len() == 1 {
+            // An arc cannot end where it starts.
+            svg += &format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{color}\">{tooltip}</circle>\n",
+                px(cx),
+                px(cy),
+                px(RADIUS)
+            );
+        } else {
+            // Clockwise from twelve o'clock.
+            let point = |turns: f64| {
+                let radians = turns * std::f64::consts::TAU;
+                (
+                    px(cx + RADIUS * radians.sin()),
+                    px(cy - RADIUS * radians.cos()),
+                )
+            };
+            let (x0, y0) = point(angle);
+            angle += share;
+            let (x1, y1) = point(angle);
+            svg += &format!(
+                "<path d=\"M{} {} L{x0} {y0} A{r} {r} 0 {} 1 {x1} {y1} Z\" fill=\"{color}\" stroke=\"#fff\">{tooltip}</path>\n",
+                px(cx),
+                px(cy),
+                u8::from(share > 0.5),
+                r = px(RADIUS),
+            );
+        }
+        let row_y = TITLE_HEIGHT + 20.0 + 20.0 * index as f64;
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"12\" height=\"12\" fill=\"{color}\"/>\n\
+             <text x=\"{}\" y=\"{}\">{}</text>\n",
+            px(LEGEND_X),
+            px(row_y - 10.0),
+            px(LEGEND_X + 18.0),
+            px(row_y),
+            escape(&legend[index])
+        );
+    }
+    svg += "</svg>\n";
+    Chart { width, height, svg }
+}
+
+/// Charts one above the other in a single SVG document.
+pub(crate) fn stack(charts: &[Chart]) -> String {
+    let width = charts.iter().map(|chart| chart.width).fold(0.0, f64::max);
+    let height: f64 = charts.iter().map(|chart| chart.height).sum();
+    let mut result = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = px(width),
+        h = px(height)
+    );
+    let mut y = 0.0;
+    for chart in charts {
+        // Nested `<svg>` elements position like `<g>` but keep their size.
+        result += &chart
+            .svg
+            .replacen("<svg ", &format!("<svg x=\"0\" y=\"{}\" ", px(y)), 1);
+        y += chart.height;
+    }
+    result + "</svg>\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Currency;
+
+    fn datum(label: &str, cents: i64) -> Datum {
+        Datum {
+            label: label.to_string(),
+            value: Money::new(cents, Currency::Usd),
+        }
+    }
+
+    #[test]
+    fn test_axis_steps_are_round() {
+        assert_eq!(tick_step(1730), 500);
+        assert_eq!(tick_step(650), 200);
+        assert_eq!(tick_step(4), 1);
+        assert_eq!(tick_step(0), 1);
+        assert_eq!(px(12.04), "12");
+        assert_eq!(px(-0.25), "-0.3");
+    }
+
+    #[test]
+    fn test_bar_chart_labels_use_money_formats() {
+        let bars = [datum("Hamlet", 65000), datum("Romeo & Juliet", 58000)];
+        let chart = bar_chart("Amount per performance", &bars, None);
+        assert!(
+            chart
+                .svg
+                .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\"")
+        );
+        assert!(chart.svg.contains("<title>Amount per performance</title>"));
+        // Four ticks of $200.00 reach above $650.00.
+        for label in ["$0.00", "$200.00", "$600.00", "$800.00"] {
+            assert!(chart.svg.contains(&format!(">{label}</text>")), "{label}");
+        }
+        assert!(
+            chart
+                .svg
+                .contains("<title>Romeo &amp; Juliet: $580.00</title>")
+        );
+        assert_eq!(chart.svg.matches("<rect ").count(), 2);
+
+        let german = bar_chart("Betrag", &bars, Some(Locale::DeDe));
+        assert!(german.svg.contains(">800,00\u{a0}$</text>"));
+    }
+
+    #[test]
+    fn test_pie_chart_shares() {
+        let chart = pie_chart(
+            "Revenue by play type",
+            &[
+                datum("tragedy", 115000),
+                datum("comedy", 58000),
+                datum("refund", -100),
+            ],
+            Some(Locale::EnUs),
+        );
+        assert!(chart.svg.contains(">tragedy: $1,150.00 (66%)</text>"));
+        assert!(chart.svg.contains(">comedy: $580.00 (34%)</text>"));
+        assert!(!chart.svg.contains("refund"));
+        // The larger slice takes the long way round.
+        assert!(chart.svg.contains(" A80 80 0 1 1 "));
+        assert!(chart.svg.contains(" A80 80 0 0 1 "));
+
+        let whole = pie_chart("Revenue", &[datum("tragedy", 100)], None);
+        assert!(whole.svg.contains("<circle "));
+
+        let stacked = stack(&[chart, whole]);
+        assert!(stacked.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg "));
+        assert_eq!(stacked.matches("<svg ").count(), 3);
+        assert!(stacked.ends_with("</svg>\n</svg>\n"));
+    }
+}
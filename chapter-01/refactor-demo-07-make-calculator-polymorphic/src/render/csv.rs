@@ -0,0 +1,37 @@
+use super::StatementRenderer;
+use crate::{create_statement_data::StatementData, error::StatementError};
+
+/// One row per performance, for spreadsheets. Amounts are plain decimals in
+/// the minor-unit precision of the currency column.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct CsvRenderer;
+
+// Quotes a field when it contains a separator, quote or line break (RFC 4180).
+pub(crate) fn field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+impl StatementRenderer for CsvRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        let mut result = String::from("customer,play,date,venue,seats,amount,currency,credits\n");
+        for perf in &data.performances {
+            let date = perf.date.map(|d| d.to_string()).unwrap_or_default();
+            result += &format!(
+                "{},{},{},{},{},{},{},{}\n",
+                field(&data.customer),
+                field(&perf.play.name),
+                date,
+                field(perf.venue.as_deref().unwrap_or_default()),
+                perf.audience,
+                perf.amount.format_amount(),
+                perf.amount.currency,
+                perf.total_credits
+            );
+        }
+        Ok(result)
+    }
+}
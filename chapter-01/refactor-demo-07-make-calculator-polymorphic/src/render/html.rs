@@ -0,0 +1,306 @@
+use super::{StatementRenderer, chart};
+use crate::{
+    company::Company,
+    create_statement_data::StatementData,
+    error::StatementError,
+    locale::{self, MessageArg},
+};
+
+/// A standalone HTML5 document with the performances in a table, styled for
+/// screen and print.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HtmlRenderer {
+    /// Follow each performance with a `component` row per price component.
+    pub detailed: bool,
+    /// Shown in a `letterhead` block above the statement heading.
+    pub letterhead: Option<Company>,
+    /// Show the amounts as SVG charts below the table.
+    pub charts: bool,
+}
+
+const STYLE: &str = "\
+body { font-family: Georgia, 'Times New Roman', serif; color: #222; max-width: 40em; margin: 2em auto; padding: 0 1em; }
+.letterhead { border-bottom: 2px solid #222; margin-bottom: 2em; }
+.letterhead p { margin: 0; }
+.letterhead .company { font-size: 1.4em; font-weight: bold; }
+table { border-collapse: collapse; width: 100%; }
+th, td { padding: 0.3em 0.6em; border-bottom: 1px solid #ccc; text-align: left; }
+th:last-child, td:last-child { text-align: right; font-variant-numeric: tabular-nums; white-space: nowrap; }
+tr.component td { color: #666; font-size: 0.9em; padding-left: 1.8em; border-bottom: none; }
+tfoot th, tfoot td { border-top: 2px solid #222; border-bottom: none; font-weight: bold; }
+em { font-style: normal; font-weight: bold; }
+.charts { margin: 1.5em 0; }
+.charts svg { max-width: 100%; height: auto; }
+@media print {
+  @page { margin: 2cm; }
+  body { margin: 0; max-width: none; font-size: 11pt; }
+  tr { break-inside: avoid; }
+}
+";
+
+/// Escapes text for use in HTML content and quoted attribute values.
+pub(super) fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+// A message from the statement's catalogue. Arguments are inserted as given,
+// so text in them must already be escaped.
+fn message(data: &StatementData, id: &str, args: &[(&str, MessageArg)]) -> String {
+    locale::message(data.locale, id, args).expect("catalogues define every statement message")
+}
+
+fn paragraph(data: &StatementData, id: &str, args: &[(&str, MessageArg)]) -> String {
+    format!("<p>{}</p>\n", message(data, id, args))
+}
+
+// An amount or count emphasized in running text.
+fn em(text: &str) -> String {
+    format!("<em>{}</em>", escape(text))
+}
+
+fn em_count(data: &StatementData, count: u32) -> MessageArg {
+    let count = i64::from(count);
+    MessageArg::Styled(count, em(&locale::format_number(data.locale, count)))
+}
+
+impl StatementRenderer for HtmlRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        let money = |amount| locale::format_money(data.locale, amount);
+        let text = |text: &str| MessageArg::Text(escape(text));
+        let title = message(data, "statement-for", &[("customer", text(&data.customer))]);
+        let lang = data.locale.map_or("en", |locale| locale.tag());
+        let mut result = format!("<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n", lang);
+        result.push_str("<meta charset=\"utf-8\">\n");
+        result.push_str(&format!("<title>{}</title>\n", title));
+        result.push_str(&format!("<style>\n{}</style>\n", STYLE));
+        result.push_str("</head>\n<body>\n");
+
+        if let Some(company) = &self.letterhead {
+            result.push_str("<header class=\"letterhead\">\n");
+            for (index, line) in company.lines().into_iter().enumerate() {
+                let class = if index == 0 { " class=\"company\"" } else { "" };
+                result.push_str(&format!("<p{}>{}</p>\n", class, escape(line)));
+            }
+            result.push_str("</header>\n");
+        }
+
+        result.push_str(&format!("<h1>{}</h1>\n", title));
+        result.push_str("<table>\n<thead>\n<tr>");
+        let dated = data.performances.iter().any(|perf| perf.date.is_some());
+        let mut columns = vec!["column-play", "column-seats", "column-cost"];
+        if dated {
+            columns.insert(0, "column-date");
+        }
+        for column in columns {
+            result.push_str(&format!("<th>{}</th>", escape(&message(data, column, &[]))));
+        }
+        result.push_str("</tr>\n</thead>\n");
+        // Every column but the amount
+        let span = if dated { 3 } else { 2 };
+
+        result.push_str("<tbody>\n");
+        for perf in &data.performances {
+            let date_cell = match (dated, perf.date) {
+                (true, Some(date)) => format!("<td>{}</td>", date),
+                (true, None) => "<td></td>".to_string(),
+                (false, _) => String::new(),
+            };
+            let venue = perf
+                .venue
+                .as_ref()
+                .map(|v| format!(" {}", message(data, "at-venue", &[("venue", text(v))])))
+                .unwrap_or_default();
+            result.push_str(&format!(
+                " <tr>{}<td>{}{}</td><td>{}</td><td>{}</td></tr>\n",
+                date_cell,
+                escape(&perf.play.name),
+                venue,
+                locale::format_number(data.locale, i64::from(perf.audience)),
+                money(perf.amount),
+            ));
+            if self.detailed {
+                for component in &perf.components {
+                    result.push_str(&format!(
+                        " <tr class=\"component\"><td colspan=\"{}\">{}</td><td>{}</td></tr>\n",
+                        span,
+                        escape(&component.label),
+                        money(component.amount),
+                    ));
+                }
+            }
+        }
+        result.push_str("</tbody>\n");
+        result.push_str(&format!(
+            "<tfoot>\n<tr><th colspan=\"{}\" scope=\"row\">{}</th><td>{}</td></tr>\n</tfoot>\n",
+            span,
+            escape(&message(data, "subtotal", &[])),
+            money(data.total_amount)
+        ));
+        result.push_str("</table>\n");
+        if self.charts {
+            result.push_str("<figure class=\"charts\">\n");
+            result.push_str(&chart::performance_chart(data).svg);
+            result.push_str(&chart::kind_chart(data).svg);
+            result.push_str("</figure>\n");
+        }
+
+        let emphasized = |amount| MessageArg::Text(em(&money(amount)));
+        if !data.tax_totals.is_empty() {
+            result.push_str(&paragraph(
+                data,
+                "net-amount",
+                &[("amount", emphasized(data.total_net))],
+            ));
+            for tax in &data.tax_totals {
+                // Tax names come from the tax tables and are not translated.
+                result.push_str(&format!(
+                    "<p>{}: {}</p>\n",
+                    escape(&tax.label()),
+                    em(&money(tax.amount))
+                ));
+            }
+        }
+        result.push_str(&paragraph(
+            data,
+            "amount-owed",
+            &[("amount", emphasized(data.total_gross))],
+        ));
+        if data.credits_redeemed > 0 {
+            result.push_str(&paragraph(
+                data,
+                "discount",
+                &[
+                    (
+                        "credits",
+                        MessageArg::Number(i64::from(data.credits_redeemed)),
+                    ),
+                    ("amount", emphasized(data.discount)),
+                ],
+            ));
+            result.push_str(&paragraph(
+                data,
+                "remaining-balance",
+                &[("amount", emphasized(data.balance_due))],
+            ));
+        }
+        if let Some(converted) = &data.converted_total {
+            result.push_str(&paragraph(
+                data,
+                "amount-owed-in",
+                &[
+                    ("currency", text(converted.amount.currency.code())),
+                    ("amount", emphasized(converted.amount)),
+                    ("from", text(data.total_gross.currency.code())),
+                    ("rate", text(&converted.rate.to_string())),
+                    ("date", text(&converted.rate_date.to_string())),
+                ],
+            ));
+        }
+        result.push_str(&paragraph(
+            data,
+            "credits-earned",
+            &[("credits", em_count(data, data.total_volume_credits))],
+        ));
+        if data.credits_redeemed > 0 {
+            result.push_str(&paragraph(
+                data,
+                "credits-remaining",
+                &[("credits", em_count(data, data.credits_remaining))],
+            ));
+        }
+
+        result.push_str("</body>\n</html>\n");
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Play,
+        create_statement_data::PerformanceData,
+        money::{Currency, Money},
+    };
+
+    fn statement(customer: &str, play: &str) -> StatementData {
+        let amount = Money::new(65000, Currency::Usd);
+        StatementData {
+            customer: customer.to_string(),
+            performances: vec![PerformanceData {
+                play: Play {
+                    name: play.to_string(),
+                    kind: "tragedy".to_string(),
+                },
+                audience: 55,
+                amount,
+                ..Default::default()
+            }],
+            total_amount: amount,
+            total_gross: amount,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_document_is_complete_and_escaped() {
+        let renderer = HtmlRenderer {
+            letterhead: Some(Company {
+                name: "Smith & Sons".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let html = renderer
+            .render(&statement(
+                "<script>alert('owned')</script>",
+                "Romeo \"&\" Juliet",
+            ))
+            .unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n"));
+        assert!(html.ends_with("</body>\n</html>\n"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains(
+            "<h1>Statement for &lt;script&gt;alert(&#39;owned&#39;)&lt;/script&gt;</h1>\n"
+        ));
+        assert!(html.contains("<p class=\"company\">Smith &amp; Sons</p>\n"));
+        assert!(html.contains(
+            "<thead>\n<tr><th>play</th><th>seats</th><th>cost</th></tr>\n</thead>\n<tbody>\n \
+             <tr><td>Romeo &quot;&amp;&quot; Juliet</td><td>55</td><td>$650.00</td></tr>\n</tbody>\n"
+        ));
+        assert!(html.contains(
+            "<tfoot>\n<tr><th colspan=\"2\" scope=\"row\">Subtotal</th><td>$650.00</td></tr>\n</tfoot>\n"
+        ));
+    }
+
+    #[test]
+    fn test_charts_are_inlined_after_the_table() {
+        let mut data = statement("BigCo", "Hamlet");
+        data.performances[0].net_amount = data.performances[0].amount;
+        let plain = HtmlRenderer::default().render(&data).unwrap();
+        assert!(!plain.contains("<svg"));
+
+        let html = HtmlRenderer {
+            charts: true,
+            ..Default::default()
+        }
+        .render(&data)
+        .unwrap();
+        assert!(html.contains("</table>\n<figure class=\"charts\">\n<svg xmlns="));
+        assert!(html.contains("<title>Amount per performance</title>"));
+        assert!(html.contains(">tragedy: $650.00 (100%)</text>"));
+        assert!(!html.contains("<?xml"));
+    }
+}
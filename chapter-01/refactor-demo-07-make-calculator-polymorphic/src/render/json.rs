@@ -0,0 +1,14 @@
+use super::StatementRenderer;
+use crate::{create_statement_data::StatementData, error::StatementError};
+
+/// The whole statement data as pretty-printed JSON, price components
+/// included. Amounts are in minor units alongside their currency.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct JsonRenderer;
+
+impl StatementRenderer for JsonRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        // Statement data has only string keys, so serializing cannot fail.
+        Ok(serde_json::to_string_pretty(data).expect("statement data serializes") + "\n")
+    }
+}
@@ -0,0 +1,133 @@
+use super::StatementRenderer;
+use crate::{create_statement_data::StatementData, error::StatementError};
+
+/// A Markdown document with the performances in a pipe table.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct MarkdownRenderer {
+    /// Add an indented row for each price component under its performance.
+    pub detailed: bool,
+}
+
+/// Backslash-escapes the characters that Markdown would read as markup,
+/// including pipes, which would end a table cell early.
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~' | '&' | '!'
+        ) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+impl StatementRenderer for MarkdownRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        let mut result = format!("# Statement for {}\n\n", escape(&data.customer));
+        let dated = data.performances.iter().any(|perf| perf.date.is_some());
+        if dated {
+            result += "| Date | Play | Seats | Amount |\n| --- | --- | ---: | ---: |\n";
+        } else {
+            result += "| Play | Seats | Amount |\n| --- | ---: | ---: |\n";
+        }
+        for perf in &data.performances {
+            let date_cell = match (dated, perf.date) {
+                (true, Some(date)) => format!("| {} ", date),
+                (true, None) => "| ".to_string(),
+                (false, _) => String::new(),
+            };
+            let venue = perf
+                .venue
+                .as_ref()
+                .map(|v| format!(" at {}", escape(v)))
+                .unwrap_or_default();
+            result += &format!(
+                "{}| {}{} | {} | {} |\n",
+                date_cell,
+                escape(&perf.play.name),
+                venue,
+                perf.audience,
+                perf.amount
+            );
+            if self.detailed {
+                let padding = if dated { "| " } else { "" };
+                for component in &perf.components {
+                    result += &format!(
+                        "{}| &nbsp;&nbsp;*{}* | | {} |\n",
+                        padding,
+                        escape(&component.label),
+                        component.amount
+                    );
+                }
+            }
+        }
+
+        result += "\n";
+        if !data.tax_totals.is_empty() {
+            result += &format!("- Net amount is {}\n", data.total_net);
+            for tax in &data.tax_totals {
+                result += &format!("  - {}: {}\n", tax.label(), tax.amount);
+            }
+        }
+        result += &format!("- Amount owed is **{}**\n", data.total_gross);
+        if data.credits_redeemed > 0 {
+            result += &format!(
+                "- Discount for {} credits is -{}\n",
+                data.credits_redeemed, data.discount
+            );
+            result += &format!("- Remaining balance is **{}**\n", data.balance_due);
+        }
+        if let Some(converted) = &data.converted_total {
+            result += &format!(
+                "- Amount owed in {} is **{}** ({})\n",
+                converted.amount.currency,
+                converted.amount,
+                converted.describe_rate(data.total_gross.currency)
+            );
+        }
+        result += &format!("- You earned **{}** credits\n", data.total_volume_credits);
+        if data.credits_redeemed > 0 {
+            result += &format!(
+                "- You have **{}** credits remaining\n",
+                data.credits_remaining
+            );
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Play,
+        create_statement_data::PerformanceData,
+        money::{Currency, Money},
+    };
+
+    #[test]
+    fn test_names_are_escaped() {
+        let data = StatementData {
+            customer: "*Big* Co_".to_string(),
+            performances: vec![PerformanceData {
+                play: Play {
+                    name: "Hamlet | Othello".to_string(),
+                    kind: "tragedy".to_string(),
+                },
+                venue: Some("[The Globe](https://example.com)".to_string()),
+                audience: 55,
+                amount: Money::new(65000, Currency::Usd),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let markdown = MarkdownRenderer::default().render(&data).unwrap();
+        assert!(markdown.starts_with("# Statement for \\*Big\\* Co\\_\n"));
+        assert!(markdown.contains(
+            "| Hamlet \\| Othello at \\[The Globe\\](https://example.com) | 55 | $650.00 |\n"
+        ));
+    }
+}
@@ -0,0 +1,604 @@
+use super::StatementRenderer;
+use crate::{
+    company::Company, create_statement_data::StatementData, error::StatementError, party::Party,
+};
+
+/// A4 PDF built from the standard Helvetica fonts, so no font files or
+/// external tools are needed. The output is plain ASCII: content streams are
+/// uncompressed and non-ASCII text is written as WinAnsi octal escapes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PdfRenderer {
+    /// List the price components in smaller type, indented under each
+    /// performance.
+    pub detailed: bool,
+    /// Drawn at the top of the first page, above the title.
+    pub letterhead: Option<Company>,
+}
+
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const LEFT: f64 = 50.0;
+const RIGHT: f64 = PAGE_WIDTH - 50.0;
+const TOP: f64 = PAGE_HEIGHT - 60.0;
+/// Content stops here; the page number sits below.
+const BOTTOM: f64 = 70.0;
+const ROW: f64 = 16.0;
+const SEATS_RIGHT: f64 = 440.0;
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+// Advance widths of Helvetica for ' ' to '~', in 1/1000 em. Helvetica-Bold
+// differs for letters but not for the digits and signs in amounts, which are
+// the only right-aligned text.
+const WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+fn text_width(text: &str, size: f64) -> f64 {
+    let units: u32 = text
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => u32::from(WIDTHS[c as usize - 32]),
+            _ => 556,
+        })
+        .sum();
+    f64::from(units) * size / 1000.0
+}
+
+/// The WinAnsiEncoding byte for `c`, or `?` when it has none.
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        'ƒ' => 0x83,
+        '„' => 0x84,
+        '…' => 0x85,
+        '†' => 0x86,
+        '‡' => 0x87,
+        'ˆ' => 0x88,
+        '‰' => 0x89,
+        'Š' => 0x8a,
+        '‹' => 0x8b,
+        'Œ' => 0x8c,
+        'Ž' => 0x8e,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '˜' => 0x98,
+        '™' => 0x99,
+        'š' => 0x9a,
+        '›' => 0x9b,
+        'œ' => 0x9c,
+        'ž' => 0x9e,
+        'Ÿ' => 0x9f,
+        _ => b'?',
+    }
+}
+
+/// A PDF literal string, e.g. `(Hamlet)`.
+fn pdf_string(text: &str) -> String {
+    let mut result = String::from("(");
+    for byte in text.chars().map(win_ansi) {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                result.push('\\');
+                result.push(byte as char);
+            }
+            0x20..=0x7e => result.push(byte as char),
+            _ => result.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    result.push(')');
+    result
+}
+
+/// Lays content out top to bottom, starting a new page when one fills up.
+struct Pages {
+    streams: Vec<String>,
+    y: f64,
+}
+
+impl Pages {
+    fn new() -> Self {
+        Pages {
+            streams: vec![String::new()],
+            y: TOP,
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.streams.push(String::new());
+        self.y = TOP;
+    }
+
+    /// Starts a new page unless `height` still fits on this one; returns
+    /// whether it did.
+    fn reserve(&mut self, height: f64) -> bool {
+        if self.y - height < BOTTOM {
+            self.new_page();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn text(&mut self, font: Font, size: f64, x: f64, text: &str) {
+        let stream = self.streams.last_mut().expect("there is always a page");
+        *stream += &format!(
+            "BT /{} {} Tf {:.2} {:.2} Td {} Tj ET\n",
+            font.resource(),
+            size,
+            x,
+            self.y,
+            pdf_string(text)
+        );
+    }
+
+    fn text_right(&mut self, font: Font, size: f64, right: f64, text: &str) {
+        self.text(font, size, right - text_width(text, size), text);
+    }
+
+    /// A horizontal rule just below the current line.
+    fn rule(&mut self) {
+        let y = self.y - 4.0;
+        let stream = self.streams.last_mut().expect("there is always a page");
+        *stream += &format!("0.5 w {LEFT:.2} {y:.2} m {RIGHT:.2} {y:.2} l S\n");
+    }
+
+    fn advance(&mut self, height: f64) {
+        self.y -= height;
+    }
+}
+
+struct Table {
+    dated: bool,
+}
+
+impl Table {
+    fn play_x(&self) -> f64 {
+        if self.dated { LEFT + 80.0 } else { LEFT }
+    }
+
+    fn header(&self, pages: &mut Pages) {
+        if self.dated {
+            pages.text(Font::Bold, 10.0, LEFT, "Date");
+        }
+        pages.text(Font::Bold, 10.0, self.play_x(), "Play");
+        pages.text_right(Font::Bold, 10.0, SEATS_RIGHT, "Seats");
+        pages.text_right(Font::Bold, 10.0, RIGHT, "Amount");
+        pages.rule();
+        pages.advance(ROW + 2.0);
+    }
+}
+
+/// A label on the left and an amount on the right of the summary.
+fn summary_line(pages: &mut Pages, font: Font, label: &str, value: &str) {
+    pages.reserve(ROW);
+    pages.text(font, 10.0, LEFT, label);
+    pages.text_right(font, 10.0, RIGHT, value);
+    pages.advance(ROW);
+}
+
+impl PdfRenderer {
+    /// The customer's legal details and contact, if the invoice has either.
+    fn buyer_lines(data: &StatementData) -> Option<Vec<String>> {
+        let mut lines = data.buyer.as_ref().map(Party::lines).unwrap_or_default();
+        if let Some(contact) = &data.contact {
+            lines.push(match &contact.name {
+                Some(name) => format!("{name} <{}>", contact.email),
+                None => contact.email.clone(),
+            });
+        }
+        (!lines.is_empty()).then_some(lines)
+    }
+
+    fn layout(&self, data: &StatementData) -> Vec<String> {
+        let mut pages = Pages::new();
+        if let Some(company) = &self.letterhead {
+            for (index, line) in company.lines().into_iter().enumerate() {
+                let (font, size) = if index == 0 {
+                    (Font::Bold, 14.0)
+                } else {
+                    (Font::Regular, 9.0)
+                };
+                pages.reserve(ROW);
+                pages.text(font, size, LEFT, line);
+                pages.advance(size + 3.0);
+            }
+            pages.rule();
+            pages.advance(24.0);
+        }
+        pages.reserve(ROW);
+        pages.text(
+            Font::Bold,
+            18.0,
+            LEFT,
+            &format!("Statement for {}", data.customer),
+        );
+        pages.advance(32.0);
+        if let Some(buyer) = Self::buyer_lines(data) {
+            for line in buyer {
+                pages.reserve(ROW);
+                pages.text(Font::Regular, 9.0, LEFT, &line);
+                pages.advance(12.0);
+            }
+            pages.advance(14.0);
+        }
+
+        let table = Table {
+            dated: data.performances.iter().any(|perf| perf.date.is_some()),
+        };
+        // Keep the header together with the first row.
+        pages.reserve(2.0 * ROW + 2.0);
+        table.header(&mut pages);
+        for perf in &data.performances {
+            if pages.reserve(ROW) {
+                table.header(&mut pages);
+            }
+            if let Some(date) = perf.date {
+                pages.text(Font::Regular, 10.0, LEFT, &date.to_string());
+            }
+            let venue = perf
+                .venue
+                .as_ref()
+                .map(|v| format!(" at {}", v))
+                .unwrap_or_default();
+            pages.text(
+                Font::Regular,
+                10.0,
+                table.play_x(),
+                &format!("{}{}", perf.play.name, venue),
+            );
+            pages.text_right(Font::Regular, 10.0, SEATS_RIGHT, &perf.audience.to_string());
+            pages.text_right(Font::Regular, 10.0, RIGHT, &perf.amount.to_string());
+            pages.advance(ROW);
+            if self.detailed {
+                for component in &perf.components {
+                    if pages.reserve(ROW) {
+                        table.header(&mut pages);
+                    }
+                    pages.text(Font::Regular, 8.0, table.play_x() + 12.0, &component.label);
+                    pages.text_right(Font::Regular, 8.0, RIGHT, &component.amount.to_string());
+                    pages.advance(ROW - 4.0);
+                }
+            }
+        }
+        pages.rule();
+        pages.advance(ROW + 4.0);
+
+        if !data.tax_totals.is_empty() {
+            summary_line(
+                &mut pages,
+                Font::Regular,
+                "Net amount",
+                &data.total_net.to_string(),
+            );
+            for tax in &data.tax_totals {
+                summary_line(
+                    &mut pages,
+                    Font::Regular,
+                    &tax.label(),
+                    &tax.amount.to_string(),
+                );
+            }
+        }
+        summary_line(
+            &mut pages,
+            Font::Bold,
+            "Amount owed",
+            &data.total_gross.to_string(),
+        );
+        if data.credits_redeemed > 0 {
+            summary_line(
+                &mut pages,
+                Font::Regular,
+                &format!("Discount for {} credits", data.credits_redeemed),
+                &format!("-{}", data.discount),
+            );
+            summary_line(
+                &mut pages,
+                Font::Bold,
+                "Remaining balance",
+                &data.balance_due.to_string(),
+            );
+        }
+        if let Some(converted) = &data.converted_total {
+            summary_line(
+                &mut pages,
+                Font::Regular,
+                &format!(
+                    "Amount owed in {} ({})",
+                    converted.amount.currency,
+                    converted.describe_rate(data.total_gross.currency)
+                ),
+                &converted.amount.to_string(),
+            );
+        }
+        pages.advance(ROW / 2.0);
+        summary_line(
+            &mut pages,
+            Font::Regular,
+            "Credits earned",
+            &data.total_volume_credits.to_string(),
+        );
+        if data.credits_redeemed > 0 {
+            summary_line(
+                &mut pages,
+                Font::Regular,
+                "Credits remaining",
+                &data.credits_remaining.to_string(),
+            );
+        }
+
+        let count = pages.streams.len();
+        for (index, stream) in pages.streams.iter_mut().enumerate() {
+            let label = format!("Page {} of {}", index + 1, count);
+            let x = (PAGE_WIDTH - text_width(&label, 8.0)) / 2.0;
+            *stream += &format!(
+                "BT /F1 8 Tf {:.2} 40.00 Td {} Tj ET\n",
+                x,
+                pdf_string(&label)
+            );
+        }
+        pages.streams
+    }
+}
+
+impl StatementRenderer for PdfRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        let streams = self.layout(data);
+
+        // 1 catalog, 2 page tree, 3-4 fonts, 5 info, then a page and its
+        // content stream for every page.
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            String::new(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+            format!(
+                "<< /Title {} >>",
+                pdf_string(&format!("Statement for {}", data.customer))
+            ),
+        ];
+        let mut kids = Vec::new();
+        for stream in &streams {
+            let page = objects.len() + 1;
+            kids.push(format!("{} 0 R", page));
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                page + 1
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                stream.len(),
+                stream
+            ));
+        }
+        objects[1] = format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            streams.len()
+        );
+
+        let mut result = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(result.len());
+            result += &format!("{} 0 obj\n{}\nendobj\n", index + 1, object);
+        }
+        let xref = result.len();
+        result += &format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            result += &format!("{:010} 00000 n \n", offset);
+        }
+        result += &format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info 5 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        );
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Play,
+        create_statement_data::PerformanceData,
+        email::Mailbox,
+        money::{Currency, Money},
+    };
+
+    /// The text drawn on each page, in drawing order.
+    fn page_texts(pdf: &str) -> Vec<Vec<String>> {
+        let mut pages = Vec::new();
+        for stream in pdf.split(">>\nstream\n").skip(1) {
+            let stream = &stream[..stream.find("endstream").unwrap()];
+            let mut texts = Vec::new();
+            let mut rest = stream;
+            while let Some(start) = rest.find(" Td (") {
+                let mut text = Vec::new();
+                let mut bytes = rest[start + 5..].bytes();
+                while let Some(byte) = bytes.next() {
+                    match byte {
+                        b')' => break,
+                        b'\\' => {
+                            let next = bytes.next().unwrap();
+                            if next.is_ascii_digit() {
+                                let octal = [next, bytes.next().unwrap(), bytes.next().unwrap()];
+                                text.push(
+                                    u8::from_str_radix(std::str::from_utf8(&octal).unwrap(), 8)
+                                        .unwrap(),
+                                );
+                            } else {
+                                text.push(next);
+                            }
+                        }
+                        _ => text.push(byte),
+                    }
+                }
+                texts.push(
+                    text.iter()
+                        .map(|&b| if b == 0x80 { '€' } else { b as char })
+                        .collect(),
+                );
+                rest = &rest[start + 5..];
+            }
+            pages.push(texts);
+        }
+        pages
+    }
+
+    fn statement(performances: usize) -> StatementData {
+        let amount = Money::new(65000, Currency::Eur);
+        StatementData {
+            customer: "Théâtre (Paris)".to_string(),
+            performances: (0..performances)
+                .map(|_| PerformanceData {
+                    play: Play {
+                        name: "Hamlet".to_string(),
+                        kind: "tragedy".to_string(),
+                    },
+                    audience: 55,
+                    amount,
+                    ..Default::default()
+                })
+                .collect(),
+            total_gross: amount.checked_mul(performances as i64).unwrap(),
+            total_volume_credits: 25 * performances as u32,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pdf_text_can_be_read_back() {
+        let pdf = PdfRenderer::default().render(&statement(1)).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        assert!(pdf.is_ascii());
+
+        // every cross-reference entry points at its object
+        let xref = &pdf[pdf.find("xref\n").unwrap()..];
+        for (index, entry) in xref.lines().skip(3).take(7).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj\n", index + 1)));
+        }
+
+        let pages = page_texts(&pdf);
+        assert_eq!(
+            pages,
+            [[
+                "Statement for Théâtre (Paris)",
+                "Play",
+                "Seats",
+                "Amount",
+                "Hamlet",
+                "55",
+                "€650.00",
+                "Amount owed",
+                "€650.00",
+                "Credits earned",
+                "25",
+                "Page 1 of 1",
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_buyer_details_follow_the_title() {
+        let mut data = statement(1);
+        data.buyer = Some(Party {
+            name: "Théâtre de Paris SA".to_string(),
+            street: Some("15 rue Blanche".to_string()),
+            city: Some("Paris".to_string()),
+            postal_code: Some("75009".to_string()),
+            country: "FR".to_string(),
+            vat_id: Some("FR12345678901".to_string()),
+            endpoint: None,
+        });
+        data.contact = Some(Mailbox {
+            name: Some("Comptabilité".to_string()),
+            email: "compta@theatre.example".to_string(),
+        });
+        let pages = page_texts(&PdfRenderer::default().render(&data).unwrap());
+        assert_eq!(
+            pages[0][..8],
+            [
+                "Statement for Théâtre (Paris)",
+                "Théâtre de Paris SA",
+                "15 rue Blanche",
+                "75009 Paris",
+                "FR",
+                "VAT FR12345678901",
+                "Comptabilité <compta@theatre.example>",
+                "Play",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_statements_break_across_pages() {
+        let pages = page_texts(&PdfRenderer::default().render(&statement(100)).unwrap());
+        assert_eq!(pages.len(), 3);
+        for (index, page) in pages.iter().enumerate() {
+            assert_eq!(page.last().unwrap(), &format!("Page {} of 3", index + 1));
+        }
+        // the table header is repeated on every page
+        assert_eq!(pages[1][..3], ["Play", "Seats", "Amount"]);
+        let rows = pages.iter().flatten().filter(|t| *t == "Hamlet").count();
+        assert_eq!(rows, 100);
+        assert!(pages[2].contains(&"€65000.00".to_string()));
+    }
+
+    #[test]
+    fn test_long_letterheads_break_across_pages() {
+        let renderer = PdfRenderer {
+            letterhead: Some(Company {
+                name: "The Globe".to_string(),
+                address: (1..=80).map(|line| format!("Line {line}")).collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let pdf = renderer.render(&statement(1)).unwrap();
+        // nothing but the page number is drawn below the bottom margin
+        for line in pdf.lines().filter(|line| line.contains(" Td (")) {
+            let y: f64 = line.split(' ').nth(5).unwrap().parse().unwrap();
+            assert!(y >= BOTTOM || line.contains("(Page "), "{line}");
+        }
+        let pages = page_texts(&pdf);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0][0], "The Globe");
+        let next = pages[0].len() - 1;
+        assert_eq!(pages[1][0], format!("Line {next}"));
+        assert!(pages[1].contains(&"Statement for Théâtre (Paris)".to_string()));
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use super::StatementRenderer;
+use crate::{
+    create_statement_data::StatementData,
+    error::StatementError,
+    locale::{self, MessageArg},
+    money::Money,
+};
+
+/// The statement as a table with aligned columns, for reading in a terminal.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TableRenderer {
+    /// Give each price component an indented row under its performance,
+    /// dimmed when `color` is set.
+    pub detailed: bool,
+    /// Draw the borders with `+`, `-` and `|` instead of box-drawing
+    /// characters.
+    pub ascii: bool,
+    /// Highlight headings and totals with ANSI escape codes.
+    pub color: bool,
+}
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether to colour output written to stdout: only for a terminal, and not
+/// when `NO_COLOR` is set to anything but the empty string.
+pub(crate) fn color_enabled() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    io::stdout().is_terminal() && !no_color
+}
+
+/// Whether the terminal's locale is UTF-8, going by the first of `LC_ALL`,
+/// `LC_CTYPE` and `LANG` that is set.
+pub(crate) fn unicode_enabled() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|value| {
+            let value = value.to_ascii_lowercase();
+            value.contains("utf-8") || value.contains("utf8")
+        })
+}
+
+/// How many terminal columns `c` takes: two for East Asian wide and
+/// fullwidth characters, none for combining marks.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036f | 0x200b..=0x200f | 0xfe00..=0xfe0f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Plain,
+    Heading,
+    Component,
+    Total,
+}
+
+struct Row {
+    cells: Vec<String>,
+    style: Style,
+    /// Whether the amount in the last cell is negative, to show it in red.
+    negative: bool,
+}
+
+// The corners, joints and lines of a table, top to bottom and left to right.
+struct Borders {
+    top: [&'static str; 3],
+    middle: [&'static str; 3],
+    bottom: [&'static str; 3],
+    horizontal: &'static str,
+    vertical: &'static str,
+}
+
+const UNICODE: Borders = Borders {
+    top: ["┌", "┬", "┐"],
+    middle: ["├", "┼", "┤"],
+    bottom: ["└", "┴", "┘"],
+    horizontal: "─",
+    vertical: "│",
+};
+
+const ASCII: Borders = Borders {
+    top: ["+", "+", "+"],
+    middle: ["+", "+", "+"],
+    bottom: ["+", "+", "+"],
+    horizontal: "-",
+    vertical: "|",
+};
+
+impl TableRenderer {
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{style}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn rule(&self, borders: &Borders, [left, joint, right]: [&str; 3], widths: &[usize]) -> String {
+        let lines: Vec<String> = widths
+            .iter()
+            .map(|width| borders.horizontal.repeat(width + 2))
+            .collect();
+        self.paint(DIM, &format!("{left}{}{right}", lines.join(joint))) + "\n"
+    }
+
+    fn row(&self, borders: &Borders, row: &Row, widths: &[usize], aligns: &[Align]) -> String {
+        let vertical = self.paint(DIM, borders.vertical);
+        let mut line = vertical.clone();
+        let last = row.cells.len() - 1;
+        for (index, cell) in row.cells.iter().enumerate() {
+            let padding = " ".repeat(widths[index] - width(cell));
+            let padded = match aligns[index] {
+                Align::Left => format!("{cell}{padding}"),
+                Align::Right => format!("{padding}{cell}"),
+            };
+            let painted = match row.style {
+                _ if row.negative && index == last => self.paint(RED, &padded),
+                Style::Heading | Style::Total => self.paint(BOLD, &padded),
+                Style::Component => self.paint(DIM, &padded),
+                Style::Plain => padded,
+            };
+            line += &format!(" {painted} {vertical}");
+        }
+        line + "\n"
+    }
+}
+
+impl StatementRenderer for TableRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        let message = |id: &str, args: &[(&str, MessageArg)]| {
+            locale::message(data.locale, id, args)
+                .expect("catalogues define every statement message")
+        };
+        let money = |amount: Money| locale::format_money(data.locale, amount);
+        let text = |text: &str| MessageArg::Text(text.to_string());
+        let count = |n: u32| MessageArg::Number(i64::from(n));
+
+        let dated = data.performances.iter().any(|perf| perf.date.is_some());
+        let mut aligns = vec![Align::Left, Align::Right, Align::Right];
+        let mut headings = vec!["column-play", "column-seats", "column-cost"];
+        if dated {
+            aligns.insert(0, Align::Left);
+            headings.insert(0, "column-date");
+        }
+        let row = |cells, style, amount: Option<Money>| Row {
+            cells,
+            style,
+            negative: amount.is_some_and(|amount| amount.minor_units < 0),
+        };
+
+        let mut rows = vec![row(
+            headings.iter().map(|id| message(id, &[])).collect(),
+            Style::Heading,
+            None,
+        )];
+        for perf in &data.performances {
+            let mut play = perf.play.name.clone();
+            if let Some(venue) = &perf.venue {
+                play = format!("{play} {}", message("at-venue", &[("venue", text(venue))]));
+            }
+            let mut cells = vec![
+                play,
+                locale::format_number(data.locale, i64::from(perf.audience)),
+                money(perf.amount),
+            ];
+            if dated {
+                cells.insert(0, perf.date.map(|d| d.to_string()).unwrap_or_default());
+            }
+            rows.push(row(cells, Style::Plain, Some(perf.amount)));
+            if self.detailed {
+                for component in &perf.components {
+                    let mut cells = vec![
+                        format!("  {}", component.label),
+                        String::new(),
+                        money(component.amount),
+                    ];
+                    if dated {
+                        cells.insert(0, String::new());
+                    }
+                    rows.push(row(cells, Style::Component, Some(component.amount)));
+                }
+            }
+        }
+        let audience: u32 = data.performances.iter().map(|perf| perf.audience).sum();
+        let mut cells = vec![
+            message("subtotal", &[]),
+            locale::format_number(data.locale, i64::from(audience)),
+            money(data.total_amount),
+        ];
+        if dated {
+            cells.insert(0, String::new());
+        }
+        rows.push(row(cells, Style::Total, Some(data.total_amount)));
+
+        let mut widths = vec![0; aligns.len()];
+        for row in &rows {
+            for (index, cell) in row.cells.iter().enumerate() {
+                widths[index] = widths[index].max(width(cell));
+            }
+        }
+        let borders = if self.ascii { &ASCII } else { &UNICODE };
+        let last = rows.len() - 1;
+        let mut result = self.paint(
+            BOLD,
+            &message("statement-for", &[("customer", text(&data.customer))]),
+        ) + "\n";
+        result += &self.rule(borders, borders.top, &widths);
+        for (index, row) in rows.iter().enumerate() {
+            if index == last {
+                result += &self.rule(borders, borders.middle, &widths);
+            }
+            result += &self.row(borders, row, &widths, &aligns);
+            if index == 0 {
+                result += &self.rule(borders, borders.middle, &widths);
+            }
+        }
+        result += &self.rule(borders, borders.bottom, &widths);
+
+        let mut line = |text: String| {
+            result += &text;
+            result.push('\n');
+        };
+        if !data.tax_totals.is_empty() {
+            line(message(
+                "net-amount",
+                &[("amount", text(&money(data.total_net)))],
+            ));
+            for tax in &data.tax_totals {
+                line(format!(" {}: {}", tax.label(), money(tax.amount)));
+            }
+        }
+        let owed = message("amount-owed", &[("amount", text(&money(data.total_gross)))]);
+        line(self.paint(BOLD, &owed));
+        if data.credits_redeemed > 0 {
+            line(message(
+                "discount",
+                &[
+                    ("credits", count(data.credits_redeemed)),
+                    ("amount", text(&money(data.discount))),
+                ],
+            ));
+            let balance = message(
+                "remaining-balance",
+                &[("amount", text(&money(data.balance_due)))],
+            );
+            line(self.paint(BOLD, &balance));
+        }
+        if let Some(converted) = &data.converted_total {
+            line(message(
+                "amount-owed-in",
+                &[
+                    ("currency", text(converted.amount.currency.code())),
+                    ("amount", text(&money(converted.amount))),
+                    ("from", text(data.total_gross.currency.code())),
+                    ("rate", text(&converted.rate.to_string())),
+                    ("date", text(&converted.rate_date.to_string())),
+                ],
+            ));
+        }
+        line(message(
+            "credits-earned",
+            &[("credits", count(data.total_volume_credits))],
+        ));
+        if data.credits_redeemed > 0 {
+            line(message(
+                "credits-remaining",
+                &[("credits", count(data.credits_remaining))],
+            ));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Play, create_statement_data::PerformanceData, locale::Locale, money::Currency};
+
+    fn statement(plays: &[(&str, u32, i64)]) -> StatementData {
+        let performances: Vec<PerformanceData> = plays
+            .iter()
+            .map(|&(name, audience, cents)| PerformanceData {
+                play: Play {
+                    name: name.to_string(),
+                    kind: "tragedy".to_string(),
+                },
+                audience,
+                amount: Money::new(cents, Currency::Usd),
+                ..Default::default()
+            })
+            .collect();
+        let total = performances
+            .iter()
+            .map(|perf| perf.amount.minor_units)
+            .sum();
+        StatementData {
+            customer: "BigCo".to_string(),
+            performances,
+            total_amount: Money::new(total, Currency::Usd),
+            total_gross: Money::new(total, Currency::Usd),
+            total_volume_credits: 47,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_columns_are_aligned() {
+        let data = statement(&[("Hamlet", 55, 65000), ("As You Like It", 35, 58000)]);
+        assert_eq!(
+            TableRenderer::default().render(&data).unwrap(),
+            "Statement for BigCo\n\
+             ┌────────────────┬───────┬──────────┐\n\
+             │ play           │ seats │     cost │\n\
+             ├────────────────┼───────┼──────────┤\n\
+             │ Hamlet         │    55 │  $650.00 │\n\
+             │ As You Like It │    35 │  $580.00 │\n\
+             ├────────────────┼───────┼──────────┤\n\
+             │ Subtotal       │    90 │ $1230.00 │\n\
+             └────────────────┴───────┴──────────┘\n\
+             Amount owed is $1230.00\n\
+             You earned 47 credits\n"
+        );
+    }
+
+    #[test]
+    fn test_ascii_and_wide_characters() {
+        let mut data = statement(&[("ハムレット", 55, 65000), ("Othello", 40, 50000)]);
+        data.locale = Some(Locale::JaJp);
+        let table = TableRenderer {
+            ascii: true,
+            ..Default::default()
+        }
+        .render(&data)
+        .unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[1], "+------------+------+-----------+");
+        assert_eq!(lines[4], "| ハムレット |   55 |   $650.00 |");
+        assert_eq!(lines[5], "| Othello    |   40 |   $500.00 |");
+        assert_eq!(lines[7], "| 小計       |   95 | $1,150.00 |");
+        // Every row spans the same number of terminal columns.
+        assert!(lines[1..=8].iter().all(|line| width(line) == 33));
+    }
+
+    #[test]
+    fn test_colour_codes() {
+        let mut data = statement(&[("Hamlet", 55, -100)]);
+        data.total_volume_credits = 0;
+        let table = TableRenderer {
+            color: true,
+            ..Default::default()
+        }
+        .render(&data)
+        .unwrap();
+        assert!(table.starts_with("\x1b[1mStatement for BigCo\x1b[0m\n\x1b[2m┌"));
+        assert!(table.contains("\x1b[1mplay    \x1b[0m"));
+        assert!(table.contains("\x1b[31m-$1.00\x1b[0m"));
+        assert!(table.contains("\x1b[1mAmount owed is -$1.00\x1b[0m\n"));
+        assert_eq!(width("e\u{301}"), 1);
+    }
+}
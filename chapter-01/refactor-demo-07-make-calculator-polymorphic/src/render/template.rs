@@ -0,0 +1,678 @@
+use std::{collections::HashMap, fs, path::Path, sync::OnceLock};
+
+use serde_json::Value;
+
+use super::StatementRenderer;
+use crate::{
+    create_statement_data::StatementData,
+    error::StatementError,
+    locale::{self, Locale, MessageArg},
+    money::{Currency, Money},
+};
+
+/// The plain-text statement; [`super::TextRenderer`] renders with it.
+const BUILTIN: &str = include_str!("template/statement.txt");
+
+/// A parsed Handlebars-style template.
+///
+/// Templates see the statement data as serialized to JSON, plus a `detailed`
+/// flag at the top level. The supported tags are:
+///
+/// - `{{customer}}`, `{{play.name}}`: print a value; names not found in the
+///   current `each` item are looked up in the enclosing ones, and `null` prints
+///   as nothing
+/// - `{{usd total_gross}}`: format an amount for the statement's locale
+/// - `{{plural total_volume_credits "credit" "credits"}}`: pick a word by count
+/// - `{{t "amount-owed" amount=total_gross}}`: a message from the locale's
+///   catalogue, with amounts formatted and counts choosing plural forms
+/// - `{{#each performances}}…{{/each}}`, with `{{this}}` and `{{@index}}`
+/// - `{{#if venue}}…{{else}}…{{/if}}`: `false`, `null`, `0`, `""` and `[]` are
+///   false
+/// - `{{! comment }}`
+///
+/// A block tag alone on its line does not leave an empty line behind.
+#[derive(Debug, Clone)]
+pub(crate) struct Template {
+    /// Shown in errors: the file path, or `built-in`.
+    pub name: String,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Value {
+        path: String,
+        line: usize,
+    },
+    Index {
+        line: usize,
+    },
+    Usd {
+        path: String,
+        line: usize,
+    },
+    Plural {
+        path: String,
+        one: String,
+        other: String,
+        line: usize,
+    },
+    Message {
+        id: String,
+        /// Message variables and the paths of their values.
+        args: Vec<(String, String)>,
+        line: usize,
+    },
+    Each {
+        path: String,
+        body: Vec<Node>,
+        line: usize,
+    },
+    If {
+        path: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+        line: usize,
+    },
+}
+
+enum Arg {
+    Path(String),
+    Literal(String),
+    Named(String, String),
+}
+
+enum Token {
+    Text(String),
+    Tag { content: String, line: usize },
+}
+
+// A template error before the template name is known.
+struct Error {
+    line: usize,
+    message: String,
+}
+
+fn error(line: usize, message: impl Into<String>) -> Error {
+    Error {
+        line,
+        message: message.into(),
+    }
+}
+
+fn is_block_tag(content: &str) -> bool {
+    content.starts_with(['#', '/', '!']) || content == "else"
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let line_of = |offset: usize| source[..offset].matches('\n').count() + 1;
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(found) = source[pos..].find("{{") {
+        let start = pos + found;
+        let line = line_of(start);
+        let end = source[start..]
+            .find("}}")
+            .map(|found| start + found)
+            .ok_or_else(|| error(line, "unclosed {{"))?;
+        let content = source[start + 2..end].trim();
+        let mut text = &source[pos..start];
+        let mut next = end + 2;
+        if is_block_tag(content) {
+            // Standalone when only whitespace shares the line with the tag.
+            let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = source[next..].find('\n').map(|i| next + i);
+            let after = &source[next..line_end.unwrap_or(source.len())];
+            if line_start >= pos
+                && source[line_start..start].trim().is_empty()
+                && after.trim().is_empty()
+            {
+                text = &source[pos..line_start];
+                next = line_end.map_or(source.len(), |i| i + 1);
+            }
+        }
+        if !text.is_empty() {
+            tokens.push(Token::Text(text.to_string()));
+        }
+        tokens.push(Token::Tag {
+            content: content.to_string(),
+            line,
+        });
+        pos = next;
+    }
+    if pos < source.len() {
+        tokens.push(Token::Text(source[pos..].to_string()));
+    }
+    Ok(tokens)
+}
+
+// Splits tag content into words, keeping "quoted strings" together.
+fn arguments(content: &str, line: usize) -> Result<Vec<Arg>, Error> {
+    let mut args = Vec::new();
+    let mut rest = content.trim_start();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| error(line, "unclosed string"))?;
+            args.push(Arg::Literal(quoted[..end].to_string()));
+            rest = quoted[end + 1..].trim_start();
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = &rest[..end];
+            args.push(match word.split_once('=') {
+                Some((name, path)) => Arg::Named(name.to_string(), path.to_string()),
+                None => Arg::Path(word.to_string()),
+            });
+            rest = rest[end..].trim_start();
+        }
+    }
+    Ok(args)
+}
+
+// The `else` or closing tag that ended a run of nodes, with its line.
+type End = (String, usize);
+
+struct Parser {
+    tokens: std::vec::IntoIter<Token>,
+}
+
+impl Parser {
+    /// Parses nodes up to the end of input or an `else` or closing tag, which
+    /// is returned with its line.
+    fn nodes(&mut self) -> Result<(Vec<Node>, Option<End>), Error> {
+        let mut nodes = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            let (content, line) = match token {
+                Token::Text(text) => {
+                    nodes.push(Node::Text(text));
+                    continue;
+                }
+                Token::Tag { content, line } => (content, line),
+            };
+            if content == "else" || content.starts_with('/') {
+                return Ok((nodes, Some((content, line))));
+            }
+            if content.starts_with('!') {
+                continue;
+            }
+            if let Some(block) = content.strip_prefix('#') {
+                nodes.push(self.block(block, line)?);
+                continue;
+            }
+            nodes.push(Self::expression(&content, line)?);
+        }
+        Ok((nodes, None))
+    }
+
+    fn expression(content: &str, line: usize) -> Result<Node, Error> {
+        let args = arguments(content, line)?;
+        let Some((Arg::Path(name), rest)) = args.split_first() else {
+            return Err(error(line, "expected a name"));
+        };
+        match (name.as_str(), rest) {
+            ("@index", []) => Ok(Node::Index { line }),
+            (_, []) => Ok(Node::Value {
+                path: name.clone(),
+                line,
+            }),
+            ("usd", [Arg::Path(path)]) => Ok(Node::Usd {
+                path: path.clone(),
+                line,
+            }),
+            ("usd", _) => Err(error(line, "expected {{usd amount}}")),
+            ("plural", [Arg::Path(path), Arg::Literal(one), Arg::Literal(other)]) => {
+                Ok(Node::Plural {
+                    path: path.clone(),
+                    one: one.clone(),
+                    other: other.clone(),
+                    line,
+                })
+            }
+            ("t", [Arg::Literal(id), named @ ..]) => {
+                if !locale::has_message(id) {
+                    return Err(error(line, format!("unknown message '{id}'")));
+                }
+                let args = named
+                    .iter()
+                    .map(|arg| match arg {
+                        Arg::Named(name, path) => Ok((name.clone(), path.clone())),
+                        _ => Err(error(line, "expected name=value after the message id")),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Node::Message {
+                    id: id.clone(),
+                    args,
+                    line,
+                })
+            }
+            ("t", _) => Err(error(line, "expected {{t \"message-id\" name=value…}}")),
+            ("plural", _) => Err(error(
+                line,
+                "expected {{plural count \"singular\" \"plural\"}}",
+            )),
+            _ => Err(error(line, format!("unknown helper '{name}'"))),
+        }
+    }
+
+    fn block(&mut self, block: &str, line: usize) -> Result<Node, Error> {
+        let (kind, path) = block.split_once(char::is_whitespace).unwrap_or((block, ""));
+        let path = path.trim().to_string();
+        if kind != "each" && kind != "if" {
+            return Err(error(line, format!("unknown block '#{kind}'")));
+        }
+        if path.is_empty() {
+            return Err(error(line, format!("'#{kind}' needs a value")));
+        }
+        let (body, end) = self.nodes()?;
+        if kind == "each" {
+            self.expect_close(end, "each", line)?;
+            return Ok(Node::Each { path, body, line });
+        }
+        let (otherwise, end) = match end {
+            Some((tag, _)) if tag == "else" => self.nodes()?,
+            end => (Vec::new(), end),
+        };
+        self.expect_close(end, "if", line)?;
+        Ok(Node::If {
+            path,
+            then: body,
+            otherwise,
+            line,
+        })
+    }
+
+    fn expect_close(&self, end: Option<End>, kind: &str, open_line: usize) -> Result<(), Error> {
+        match end {
+            Some((tag, _)) if tag == format!("/{kind}") => Ok(()),
+            Some((tag, line)) => Err(error(
+                line,
+                format!(
+                    "expected {{{{/{kind}}}}} for the block opened on line {open_line}, found {{{{{tag}}}}}"
+                ),
+            )),
+            None => Err(error(open_line, format!("{{{{#{kind}}}}} is never closed"))),
+        }
+    }
+}
+
+// One level of the context stack: the statement itself, or an `each` item.
+struct Scope<'a> {
+    value: &'a Value,
+    index: Option<usize>,
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+fn lookup<'a>(scopes: &[Scope<'a>], path: &str, line: usize) -> Result<&'a Value, Error> {
+    let innermost = scopes.last().expect("the root scope is always present");
+    if path == "this" {
+        return Ok(innermost.value);
+    }
+    let mut segments = path.split('.');
+    let first = segments.next().unwrap_or_default();
+    let mut value = scopes
+        .iter()
+        .rev()
+        .find_map(|scope| scope.value.get(first))
+        .ok_or_else(|| error(line, format!("unknown value '{path}'")))?;
+    for segment in segments {
+        if value.is_null() {
+            break;
+        }
+        value = value
+            .get(segment)
+            .ok_or_else(|| error(line, format!("unknown value '{path}'")))?;
+    }
+    Ok(value)
+}
+
+// Formats an amount with its currency; bare numbers are taken as US cents.
+fn usd(value: &Value, locale: Option<Locale>, line: usize) -> Result<String, Error> {
+    let money = match value {
+        Value::Null => return Ok(String::new()),
+        Value::Number(n) => n
+            .as_i64()
+            .map(|cents| Money::new(cents, Currency::Usd))
+            .ok_or_else(|| error(line, format!("'usd' expects whole cents, found {n}")))?,
+        _ => serde_json::from_value::<Money>(value.clone())
+            .map_err(|_| error(line, "'usd' expects an amount"))?,
+    };
+    Ok(locale::format_money(locale, money))
+}
+
+// Amounts are formatted for the locale, whole numbers are left for the
+// catalogue to format and to pick plural forms with.
+fn message_arg(
+    value: &Value,
+    path: &str,
+    locale: Option<Locale>,
+    line: usize,
+) -> Result<MessageArg, Error> {
+    match value {
+        Value::Number(n) if n.is_i64() => Ok(MessageArg::Number(n.as_i64().unwrap_or_default())),
+        Value::Object(_) => usd(value, locale, line).map(MessageArg::Text),
+        _ => print(value, path, line).map(MessageArg::Text),
+    }
+}
+
+fn print(value: &Value, path: &str, line: usize) -> Result<String, Error> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(s.clone()),
+        Value::Array(_) | Value::Object(_) => Err(error(
+            line,
+            format!("'{path}' is a list or object and cannot be printed"),
+        )),
+    }
+}
+
+impl Template {
+    pub fn parse(name: impl Into<String>, source: &str) -> Result<Self, StatementError> {
+        let name = name.into();
+        let parsed = tokenize(source).and_then(|tokens| {
+            let mut parser = Parser {
+                tokens: tokens.into_iter(),
+            };
+            match parser.nodes()? {
+                (nodes, None) => Ok(nodes),
+                (_, Some((tag, line))) => Err(error(line, format!("unexpected {{{{{tag}}}}}"))),
+            }
+        });
+        match parsed {
+            Ok(nodes) => Ok(Template { name, nodes }),
+            Err(err) => Err(Self::error(&name, err)),
+        }
+    }
+
+    /// The template behind the plain-text statement.
+    pub fn builtin() -> &'static Template {
+        static BUILTIN_TEMPLATE: OnceLock<Template> = OnceLock::new();
+        BUILTIN_TEMPLATE
+            .get_or_init(|| Template::parse("built-in", BUILTIN).expect("built-in template parses"))
+    }
+
+    fn error(name: &str, err: Error) -> StatementError {
+        StatementError::Template {
+            template: name.to_string(),
+            line: err.line,
+            message: err.message,
+        }
+    }
+
+    /// Renders with `locale`'s messages and number formats, or the English
+    /// messages and plain numbers without one.
+    pub fn render(
+        &self,
+        context: &Value,
+        locale: Option<Locale>,
+    ) -> Result<String, StatementError> {
+        let mut out = String::new();
+        let mut scopes = vec![Scope {
+            value: context,
+            index: None,
+        }];
+        Self::render_nodes(&self.nodes, &mut scopes, locale, &mut out)
+            .map_err(|err| Self::error(&self.name, err))?;
+        Ok(out)
+    }
+
+    /// Renders `data` in its locale, with the `detailed` flag set as given.
+    pub fn render_statement(
+        &self,
+        data: &StatementData,
+        detailed: bool,
+    ) -> Result<String, StatementError> {
+        // Cannot fail, for the reason given in `JsonRenderer`.
+        let mut context = serde_json::to_value(data).expect("statement data serializes");
+        context["detailed"] = Value::Bool(detailed);
+        self.render(&context, data.locale)
+    }
+
+    fn render_nodes<'a>(
+        nodes: &[Node],
+        scopes: &mut Vec<Scope<'a>>,
+        locale: Option<Locale>,
+        out: &mut String,
+    ) -> Result<(), Error> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => *out += text,
+                Node::Value { path, line } => {
+                    *out += &print(lookup(scopes, path, *line)?, path, *line)?;
+                }
+                Node::Index { line } => {
+                    let index = scopes
+                        .last()
+                        .and_then(|scope| scope.index)
+                        .ok_or_else(|| error(*line, "@index is only available in {{#each}}"))?;
+                    *out += &index.to_string();
+                }
+                Node::Usd { path, line } => {
+                    *out += &usd(lookup(scopes, path, *line)?, locale, *line)?;
+                }
+                Node::Plural {
+                    path,
+                    one,
+                    other,
+                    line,
+                } => {
+                    let count = lookup(scopes, path, *line)?
+                        .as_i64()
+                        .ok_or_else(|| error(*line, format!("'{path}' is not a whole number")))?;
+                    let singular = match locale {
+                        Some(locale) => locale.plural(count) == "one",
+                        None => count == 1,
+                    };
+                    *out += if singular { one } else { other };
+                }
+                Node::Message { id, args, line } => {
+                    let args = args
+                        .iter()
+                        .map(|(name, path)| {
+                            let value = lookup(scopes, path, *line)?;
+                            Ok((name.as_str(), message_arg(value, path, locale, *line)?))
+                        })
+                        .collect::<Result<Vec<_>, Error>>()?;
+                    *out += &locale::message(locale, id, &args)
+                        .ok_or_else(|| error(*line, format!("unknown message '{id}'")))?;
+                }
+                Node::Each { path, body, line } => match lookup(scopes, path, *line)? {
+                    Value::Null => {}
+                    Value::Array(items) => {
+                        for (index, item) in items.iter().enumerate() {
+                            scopes.push(Scope {
+                                value: item,
+                                index: Some(index),
+                            });
+                            let rendered = Self::render_nodes(body, scopes, locale, out);
+                            scopes.pop();
+                            rendered?;
+                        }
+                    }
+                    _ => return Err(error(*line, format!("cannot loop over '{path}'"))),
+                },
+                Node::If {
+                    path,
+                    then,
+                    otherwise,
+                    line,
+                } => {
+                    let branch = if truthy(lookup(scopes, path, *line)?) {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    Self::render_nodes(branch, scopes, locale, out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Loads every file in `dir` as a template named after its file stem, so
+/// `templates/letter.txt` becomes `letter`.
+pub(crate) fn load_templates(
+    dir: impl AsRef<Path>,
+) -> Result<HashMap<String, Template>, StatementError> {
+    let dir = dir.as_ref();
+    let mut templates = HashMap::new();
+    for entry in fs::read_dir(dir).map_err(|err| StatementError::io(dir, err))? {
+        let path = entry.map_err(|err| StatementError::io(dir, err))?.path();
+        let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if !path.is_file() || stem.starts_with('.') {
+            continue;
+        }
+        let source = fs::read_to_string(&path).map_err(|err| StatementError::io(&path, err))?;
+        let template = Template::parse(path.display().to_string(), &source)?;
+        templates.insert(stem.to_string(), template);
+    }
+    Ok(templates)
+}
+
+/// Renders statements through a user-supplied [`Template`].
+#[derive(Debug, Clone)]
+pub(crate) struct TemplateRenderer {
+    pub template: Template,
+    /// Passed to the template as `detailed`.
+    pub detailed: bool,
+}
+
+impl StatementRenderer for TemplateRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        self.template.render_statement(data, self.detailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(source: &str, context: Value) -> Result<String, StatementError> {
+        Template::parse("test", source)?.render(&context, None)
+    }
+
+    fn line_of(err: StatementError) -> usize {
+        match err {
+            StatementError::Template { line, .. } => line,
+            other => panic!("expected a template error, got {other}"),
+        }
+    }
+
+    #[test]
+    fn test_loops_conditions_and_helpers() {
+        let context = json!({
+            "customer": "BigCo",
+            "credits": 1,
+            "fee": 250,
+            "refund": false,
+            "total": {"minor_units": 173000, "currency": "USD"},
+            "performances": [
+                {"play": {"name": "Hamlet"}, "venue": "globe", "seats": 55},
+                {"play": {"name": "Othello"}, "venue": null, "seats": 1},
+            ],
+        });
+        let source = "Statement for {{customer}}\n\
+                      {{#each performances}}\n\
+                      {{@index}}. {{play.name}}{{#if venue}} at {{venue}}{{/if}}, \
+                      {{seats}} {{plural seats \"seat\" \"seats\"}} for {{customer}}\n\
+                      {{/each}}\n\
+                      {{! not printed }}\n\
+                      {{#if refund}}\n\
+                      Refund\n\
+                      {{else}}\n\
+                      Owed: {{usd total}} ({{usd fee}}), {{credits}} {{plural credits \"credit\" \"credits\"}}\n\
+                      {{/if}}\n";
+        assert_eq!(
+            render(source, context).unwrap(),
+            "Statement for BigCo\n0. Hamlet at globe, 55 seats for BigCo\n1. Othello, 1 seat for BigCo\nOwed: $1730.00 ($2.50), 1 credit\n"
+        );
+    }
+
+    #[test]
+    fn test_errors_carry_line_numbers() {
+        let err = Template::parse(
+            "letter.txt",
+            "Dear {{customer}},\n{{#each performances}}\n{{/if}}\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "template letter.txt, line 3: expected {{/each}} for the block opened on line 2, found {{/if}}"
+        );
+        assert_eq!(
+            line_of(Template::parse("t", "a\n\n{{#if x}}\nb\n").unwrap_err()),
+            3
+        );
+        assert_eq!(
+            line_of(Template::parse("t", "a\n{{money x}}").unwrap_err()),
+            2
+        );
+        assert_eq!(line_of(Template::parse("t", "a\nb {{x").unwrap_err()), 2);
+
+        let err = render(
+            "{{customer}}\n{{#each performances}}\n{{play.title}}\n{{/each}}",
+            json!({
+                "customer": "BigCo",
+                "performances": [{"play": {"name": "Hamlet"}}],
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "template test, line 3: unknown value 'play.title'"
+        );
+        assert_eq!(
+            line_of(render("\n{{usd customer}}", json!({"customer": "BigCo"})).unwrap_err()),
+            2
+        );
+    }
+
+    #[test]
+    fn test_templates_load_from_a_directory() {
+        let dir = std::env::temp_dir().join(format!("templates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("short.txt"),
+            "{{customer}} owes {{usd total_gross}}\n",
+        )
+        .unwrap();
+        let templates = load_templates(&dir).unwrap();
+        assert_eq!(templates.keys().collect::<Vec<_>>(), ["short"]);
+        let data = StatementData {
+            customer: "BigCo".to_string(),
+            total_gross: Money::new(173000, Currency::Usd),
+            ..Default::default()
+        };
+        let renderer = TemplateRenderer {
+            template: templates["short"].clone(),
+            detailed: false,
+        };
+        assert_eq!(renderer.render(&data).unwrap(), "BigCo owes $1730.00\n");
+
+        fs::write(dir.join("broken.txt"), "{{#each performances}}\n").unwrap();
+        let err = load_templates(&dir).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            err.to_string()
+                .contains("broken.txt, line 1: {{#each}} is never closed")
+        );
+    }
+}
@@ -0,0 +1,16 @@
+use super::{StatementRenderer, Template};
+use crate::{create_statement_data::StatementData, error::StatementError};
+
+/// The plain-text statement, one line per performance, rendered with the
+/// built-in template.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TextRenderer {
+    /// Follow each performance with its price components.
+    pub detailed: bool,
+}
+
+impl StatementRenderer for TextRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        Template::builtin().render_statement(data, self.detailed)
+    }
+}
@@ -0,0 +1,500 @@
+use super::StatementRenderer;
+use crate::{
+    create_statement_data::{PerformanceData, StatementData},
+    error::StatementError,
+    exchange::{Rate, RoundingMode},
+    money::Money,
+    party::Party,
+};
+
+mod validator;
+
+pub(crate) use validator::validate;
+
+const CUSTOMIZATION_ID: &str =
+    "urn:cen.eu:en16931:2017#compliant#urn:fdc:peppol.eu:2017:poacc:billing:3.0";
+const PROFILE_ID: &str = "urn:fdc:peppol.eu:2017:poacc:billing:01:1.0";
+/// A commercial invoice, UNTDID 1001.
+const INVOICE_TYPE_CODE: &str = "380";
+/// "One", UN/ECE recommendation 20: each line is one performance.
+const UNIT_CODE: &str = "C62";
+
+/// A UBL 2.1 Invoice following Peppol BIS Billing 3.0.
+///
+/// Each performance becomes an invoice line taxed at the sum of its tax
+/// rates, since EN 16931 knows only one VAT category per line. Redeemed
+/// credits are a discount on the amount owed, so they become a document-level
+/// allowance in each VAT category, in proportion to the category's share of
+/// the gross amount.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UblRenderer {
+    pub seller: Option<Party>,
+}
+
+/// Builds indented XML, escaping all text and attribute values.
+struct Xml {
+    out: String,
+    depth: usize,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Xml {
+    fn indent(&mut self) {
+        self.out += &"  ".repeat(self.depth);
+    }
+
+    fn open(&mut self, name: &str) {
+        self.indent();
+        self.out += &format!("<{}>\n", name);
+        self.depth += 1;
+    }
+
+    fn close(&mut self, name: &str) {
+        self.depth -= 1;
+        self.indent();
+        self.out += &format!("</{}>\n", name);
+    }
+
+    fn leaf(&mut self, name: &str, text: &str) {
+        self.indent();
+        self.out += &format!("<{}>{}</{}>\n", name, escape(text), name);
+    }
+
+    fn leaf_with(&mut self, name: &str, attribute: (&str, &str), text: &str) {
+        self.indent();
+        self.out += &format!(
+            "<{} {}=\"{}\">{}</{}>\n",
+            name,
+            attribute.0,
+            escape(attribute.1),
+            escape(text),
+            name
+        );
+    }
+
+    fn amount(&mut self, name: &str, money: Money) {
+        self.leaf_with(
+            name,
+            ("currencyID", money.currency.code()),
+            &money.format_amount(),
+        );
+    }
+
+    fn party(&mut self, role: &str, party: &Party) {
+        self.open(role);
+        self.open("cac:Party");
+        if let Some(endpoint) = &party.endpoint {
+            self.leaf_with(
+                "cbc:EndpointID",
+                ("schemeID", &endpoint.scheme),
+                &endpoint.id,
+            );
+        }
+        self.open("cac:PartyName");
+        self.leaf("cbc:Name", &party.name);
+        self.close("cac:PartyName");
+        self.open("cac:PostalAddress");
+        if let Some(street) = &party.street {
+            self.leaf("cbc:StreetName", street);
+        }
+        if let Some(city) = &party.city {
+            self.leaf("cbc:CityName", city);
+        }
+        if let Some(postal_code) = &party.postal_code {
+            self.leaf("cbc:PostalZone", postal_code);
+        }
+        self.open("cac:Country");
+        self.leaf("cbc:IdentificationCode", &party.country);
+        self.close("cac:Country");
+        self.close("cac:PostalAddress");
+        if let Some(vat_id) = &party.vat_id {
+            self.open("cac:PartyTaxScheme");
+            self.leaf("cbc:CompanyID", vat_id);
+            self.tax_scheme();
+            self.close("cac:PartyTaxScheme");
+        }
+        self.open("cac:PartyLegalEntity");
+        self.leaf("cbc:RegistrationName", &party.name);
+        self.close("cac:PartyLegalEntity");
+        self.close("cac:Party");
+        self.close(role);
+    }
+
+    fn tax_scheme(&mut self) {
+        self.open("cac:TaxScheme");
+        self.leaf("cbc:ID", "VAT");
+        self.close("cac:TaxScheme");
+    }
+
+    /// Exemption reasons are given in the VAT breakdown only.
+    fn tax_category(&mut self, name: &str, category: &TaxCategory, reason: Option<&str>) {
+        self.open(name);
+        self.leaf("cbc:ID", category.id);
+        self.leaf("cbc:Percent", &category.percent);
+        if let Some(reason) = reason {
+            self.leaf("cbc:TaxExemptionReason", reason);
+        }
+        self.tax_scheme();
+        self.close(name);
+    }
+}
+
+/// A VAT category code (`S` standard, `E` exempt, `Z` zero rated when the
+/// invoice has no jurisdiction) with its rate.
+#[derive(PartialEq)]
+struct TaxCategory {
+    id: &'static str,
+    percent: String,
+    /// The summed rate of `S`; the other categories are untaxed.
+    rate: Option<Rate>,
+    exemption_reason: Option<String>,
+}
+
+impl TaxCategory {
+    fn of(perf: &PerformanceData) -> Self {
+        let scale = perf.tax_lines.iter().map(|l| l.rate.scale).max();
+        match scale {
+            Some(scale) => {
+                let digits = perf
+                    .tax_lines
+                    .iter()
+                    .map(|l| l.rate.digits * 10i128.pow(scale - l.rate.scale))
+                    .sum();
+                let rate = Rate { digits, scale };
+                TaxCategory {
+                    id: "S",
+                    percent: rate.to_string(),
+                    rate: Some(rate),
+                    exemption_reason: None,
+                }
+            }
+            None if !perf.tax_exemptions.is_empty() => TaxCategory {
+                id: "E",
+                percent: "0".to_string(),
+                rate: None,
+                exemption_reason: Some(format!(
+                    "Exempt from {}",
+                    perf.tax_exemptions.join(" and ")
+                )),
+            },
+            None => TaxCategory {
+                id: "Z",
+                percent: "0".to_string(),
+                rate: None,
+                exemption_reason: None,
+            },
+        }
+    }
+
+    /// The net part of `gross`, in minor units.
+    fn net_of(&self, gross: i128) -> Option<i128> {
+        let Some(rate) = self.rate else {
+            return Some(gross);
+        };
+        let hundred = 100 * 10i128.pow(rate.scale);
+        Some(RoundingMode::default().divide(
+            gross.checked_mul(hundred)?,
+            hundred.checked_add(rate.digits)?,
+        ))
+    }
+}
+
+/// The lines of one VAT category and their share of the discount, in minor
+/// units.
+struct CategoryTotal {
+    category: TaxCategory,
+    net: i128,
+    tax: i128,
+    allowance: i128,
+    allowance_tax: i128,
+}
+
+impl CategoryTotal {
+    fn taxable(&self) -> i128 {
+        self.net - self.allowance
+    }
+
+    fn tax_after_allowance(&self) -> i128 {
+        self.tax - self.allowance_tax
+    }
+}
+
+/// Sums the lines by VAT category, then splits the gross `discount` across
+/// the categories by their gross amounts; the last one takes the remainder.
+fn category_totals(data: &StatementData, discount: i128) -> Option<Vec<CategoryTotal>> {
+    let mut totals: Vec<CategoryTotal> = Vec::new();
+    for perf in &data.performances {
+        let category = TaxCategory::of(perf);
+        let net = i128::from(perf.net_amount.minor_units);
+        let tax = i128::from(perf.gross_amount.minor_units) - net;
+        match totals.iter_mut().find(|t| t.category == category) {
+            Some(total) => {
+                total.net += net;
+                total.tax += tax;
+            }
+            None => totals.push(CategoryTotal {
+                category,
+                net,
+                tax,
+                allowance: 0,
+                allowance_tax: 0,
+            }),
+        }
+    }
+    let gross: i128 = totals.iter().map(|t| t.net + t.tax).sum();
+    if discount == 0 || gross <= 0 {
+        return Some(totals);
+    }
+    let mut left = discount;
+    let count = totals.len();
+    for (index, total) in totals.iter_mut().enumerate() {
+        let share = if index + 1 == count {
+            left
+        } else {
+            discount.checked_mul(total.net + total.tax)? / gross
+        };
+        left -= share;
+        total.allowance = total.category.net_of(share)?;
+        total.allowance_tax = share - total.allowance;
+    }
+    Some(totals)
+}
+
+impl StatementRenderer for UblRenderer {
+    fn render(&self, data: &StatementData) -> Result<String, StatementError> {
+        let mut xml = Xml {
+            out: String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"),
+            depth: 0,
+        };
+        xml.out += "<Invoice xmlns=\"urn:oasis:names:specification:ubl:schema:xsd:Invoice-2\"\n \
+                    xmlns:cac=\"urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2\"\n \
+                    xmlns:cbc=\"urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2\">\n";
+        xml.depth = 1;
+        xml.leaf("cbc:CustomizationID", CUSTOMIZATION_ID);
+        xml.leaf("cbc:ProfileID", PROFILE_ID);
+        // Missing identifiers are left out for the validator to report.
+        if let Some(id) = &data.invoice_id {
+            xml.leaf("cbc:ID", id);
+        }
+        if let Some(date) = data.invoice_date {
+            xml.leaf("cbc:IssueDate", &date.to_string());
+        }
+        xml.leaf("cbc:InvoiceTypeCode", INVOICE_TYPE_CODE);
+        let currency = data.total_gross.currency;
+        xml.leaf("cbc:DocumentCurrencyCode", currency.code());
+        xml.leaf("cbc:BuyerReference", &data.customer);
+        if let Some(seller) = &self.seller {
+            xml.party("cac:AccountingSupplierParty", seller);
+        }
+        if let Some(buyer) = &data.buyer {
+            xml.party("cac:AccountingCustomerParty", buyer);
+        }
+
+        let overflow = || StatementError::TotalOverflow {
+            customer: data.customer.clone(),
+        };
+        let money = |minor: i128| {
+            i64::try_from(minor)
+                .map(|minor| Money::new(minor, currency))
+                .map_err(|_| overflow())
+        };
+        let discount = i128::from(data.discount.minor_units);
+        let totals = category_totals(data, discount).ok_or_else(overflow)?;
+        let allowances: i128 = totals.iter().map(|t| t.allowance).sum();
+        let tax: i128 = totals.iter().map(CategoryTotal::tax_after_allowance).sum();
+
+        for total in totals.iter().filter(|t| t.allowance != 0) {
+            xml.open("cac:AllowanceCharge");
+            xml.leaf("cbc:ChargeIndicator", "false");
+            xml.leaf(
+                "cbc:AllowanceChargeReason",
+                &format!("{} volume credits redeemed", data.credits_redeemed),
+            );
+            xml.amount("cbc:Amount", money(total.allowance)?);
+            xml.tax_category("cac:TaxCategory", &total.category, None);
+            xml.close("cac:AllowanceCharge");
+        }
+
+        xml.open("cac:TaxTotal");
+        xml.amount("cbc:TaxAmount", money(tax)?);
+        for total in &totals {
+            xml.open("cac:TaxSubtotal");
+            xml.amount("cbc:TaxableAmount", money(total.taxable())?);
+            xml.amount("cbc:TaxAmount", money(total.tax_after_allowance())?);
+            xml.tax_category(
+                "cac:TaxCategory",
+                &total.category,
+                total.category.exemption_reason.as_deref(),
+            );
+            xml.close("cac:TaxSubtotal");
+        }
+        xml.close("cac:TaxTotal");
+
+        let tax_exclusive = i128::from(data.total_net.minor_units) - allowances;
+        xml.open("cac:LegalMonetaryTotal");
+        xml.amount("cbc:LineExtensionAmount", data.total_net);
+        xml.amount("cbc:TaxExclusiveAmount", money(tax_exclusive)?);
+        xml.amount("cbc:TaxInclusiveAmount", money(tax_exclusive + tax)?);
+        if allowances != 0 {
+            xml.amount("cbc:AllowanceTotalAmount", money(allowances)?);
+        }
+        xml.amount("cbc:PayableAmount", data.balance_due);
+        xml.close("cac:LegalMonetaryTotal");
+
+        for (index, perf) in data.performances.iter().enumerate() {
+            xml.open("cac:InvoiceLine");
+            xml.leaf("cbc:ID", &(index + 1).to_string());
+            xml.leaf_with("cbc:InvoicedQuantity", ("unitCode", UNIT_CODE), "1");
+            xml.amount("cbc:LineExtensionAmount", perf.net_amount);
+            if let Some(date) = perf.date {
+                xml.open("cac:InvoicePeriod");
+                xml.leaf("cbc:StartDate", &date.to_string());
+                xml.leaf("cbc:EndDate", &date.to_string());
+                xml.close("cac:InvoicePeriod");
+            }
+            xml.open("cac:Item");
+            let venue = perf
+                .venue
+                .as_ref()
+                .map(|v| format!(" at {}", v))
+                .unwrap_or_default();
+            xml.leaf(
+                "cbc:Description",
+                &format!("{} seats{}", perf.audience, venue),
+            );
+            xml.leaf("cbc:Name", &perf.play.name);
+            xml.tax_category("cac:ClassifiedTaxCategory", &TaxCategory::of(perf), None);
+            xml.close("cac:Item");
+            xml.open("cac:Price");
+            xml.amount("cbc:PriceAmount", perf.net_amount);
+            xml.close("cac:Price");
+            xml.close("cac:InvoiceLine");
+        }
+        xml.out += "</Invoice>\n";
+        Ok(xml.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        calculator_registry::CalculatorRegistry,
+        create_statement_data::{StatementContext, create_statement_data},
+        load_fixtures,
+        party::Endpoint,
+        pricing::PricingRules,
+        tax::TaxEngine,
+    };
+
+    fn party(name: &str, country: &str, vat_id: &str) -> Party {
+        Party {
+            name: name.to_string(),
+            street: Some("1 Main Street".to_string()),
+            city: Some("Springfield".to_string()),
+            postal_code: Some("12345".to_string()),
+            country: country.to_string(),
+            vat_id: Some(vat_id.to_string()),
+            endpoint: Some(Endpoint {
+                scheme: "9930".to_string(),
+                id: vat_id.to_string(),
+            }),
+        }
+    }
+
+    fn export(jurisdiction: &str, buyer: Option<Party>) -> String {
+        let (plays, mut invoices) = load_fixtures();
+        let registry = CalculatorRegistry::from_rules(&PricingRules::default());
+        let taxes = TaxEngine::from_file("../taxes.json").unwrap();
+        let context = StatementContext::new(&plays, &registry).with_taxes(&taxes);
+        let invoice = &mut invoices[0];
+        invoice.id = Some("INV-2024-001".to_string());
+        invoice.date = "2024-03-15".parse().ok();
+        invoice.jurisdiction = Some(jurisdiction.to_string());
+        invoice.credit_balance = 10;
+        invoice.redeem_credits = 10;
+        invoice.buyer = buyer;
+        let data = create_statement_data(invoice, &context).unwrap();
+        UblRenderer {
+            seller: Some(party("Bankside Players & Co", "GB", "GB980780684")),
+        }
+        .render(&data)
+        .unwrap()
+    }
+
+    #[test]
+    fn test_exported_invoice_passes_validation() {
+        let xml = export("DE", Some(party("BigCo GmbH", "DE", "DE123456789")));
+        assert_eq!(validate(&xml), []);
+
+        assert!(xml.contains("<cbc:Name>Bankside Players &amp; Co</cbc:Name>\n"));
+        assert!(xml.contains("<cbc:ID>S</cbc:ID>\n        <cbc:Percent>7</cbc:Percent>\n"));
+        // The 10.00 of redeemed credits are 9.35 net of the 7% VAT.
+        assert!(xml.contains("<cbc:Amount currencyID=\"USD\">9.35</cbc:Amount>\n"));
+        assert!(xml.contains("<cbc:TaxAmount currencyID=\"USD\">112.52</cbc:TaxAmount>\n"));
+        assert!(xml.contains(
+            "<cbc:TaxExclusiveAmount currencyID=\"USD\">1607.48</cbc:TaxExclusiveAmount>\n"
+        ));
+        assert!(xml.contains(
+            "<cbc:AllowanceTotalAmount currencyID=\"USD\">9.35</cbc:AllowanceTotalAmount>\n"
+        ));
+        assert!(!xml.contains("PrepaidAmount"));
+        assert!(
+            xml.contains("<cbc:PayableAmount currencyID=\"USD\">1720.00</cbc:PayableAmount>\n")
+        );
+
+        let xml = export("UK", Some(party("BigCo Ltd", "GB", "GB123456789")));
+        assert_eq!(validate(&xml), []);
+        assert!(xml.contains(
+            "<cbc:ID>E</cbc:ID>\n        <cbc:Percent>0</cbc:Percent>\n        \
+             <cbc:TaxExemptionReason>Exempt from VAT</cbc:TaxExemptionReason>\n"
+        ));
+    }
+
+    #[test]
+    fn test_validator_reports_broken_rules() {
+        let rules = |xml: &str| -> Vec<&'static str> {
+            validate(xml).into_iter().map(|v| v.rule).collect()
+        };
+        assert_eq!(
+            rules(&export("DE", None)),
+            ["BR-07", "BR-10", "BR-11", "PEPPOL-EN16931-R010"]
+        );
+
+        let xml = export("DE", Some(party("BigCo GmbH", "DE", "DE123456789")));
+        let tampered = xml.replace(
+            "<cbc:PayableAmount currencyID=\"USD\">1720.00",
+            "<cbc:PayableAmount currencyID=\"USD\">1730.00",
+        );
+        assert_eq!(rules(&tampered), ["BR-CO-16"]);
+        let tampered = xml.replacen(
+            "<cbc:LineExtensionAmount currencyID=\"USD\">607.48",
+            "<cbc:LineExtensionAmount currencyID=\"EUR\">607.48",
+            1,
+        );
+        assert_eq!(rules(&tampered), ["PEPPOL-EN16931-R051"]);
+        let tampered = xml.replace(
+            "<cbc:AllowanceTotalAmount currencyID=\"USD\">9.35",
+            "<cbc:AllowanceTotalAmount currencyID=\"USD\">9.36",
+        );
+        assert_eq!(rules(&tampered), ["BR-CO-11", "BR-CO-13"]);
+        let tampered = xml.replace(
+            "<cbc:Percent>7</cbc:Percent>",
+            "<cbc:Percent>19</cbc:Percent>",
+        );
+        assert_eq!(rules(&tampered), ["BR-S-09"]);
+        let xml = export("UK", Some(party("BigCo Ltd", "GB", "GB123456789")));
+        let tampered = xml.replace(
+            "<cbc:TaxExemptionReason>Exempt from VAT</cbc:TaxExemptionReason>",
+            "",
+        );
+        assert_eq!(rules(&tampered), ["BR-E-10"]);
+        assert_eq!(rules(&xml[..xml.len() - 12]), ["XML"]);
+    }
+}
@@ -0,0 +1,708 @@
+//! Offline checks of the EN 16931 and Peppol BIS 3.0 business rules that a
+//! statement export can break. This is not a schema or Schematron validator:
+//! elements are matched by local name and only the rules below are checked.
+
+use std::fmt;
+
+/// A broken business rule, identified as in the Peppol BIS specification.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Violation {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.rule, self.message)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Element {
+    /// The name without its namespace prefix.
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn path(&self, names: &[&str]) -> Option<&Element> {
+        names
+            .iter()
+            .try_fold(self, |element, name| element.child(name))
+    }
+
+    /// Trimmed text of the element at `names`, if present and not blank.
+    fn text_at(&self, names: &[&str]) -> Option<&str> {
+        self.path(names)
+            .map(|e| e.text.trim())
+            .filter(|t| !t.is_empty())
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn descendants(&self) -> Vec<&Element> {
+        let mut result = vec![self];
+        for child in &self.children {
+            result.extend(child.descendants());
+        }
+        result
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result += &rest[..start];
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| "unterminated entity".to_string())?;
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("unknown entity &{};", entity))?,
+        };
+        result.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    result += rest;
+    Ok(result)
+}
+
+/// A small parser for the elements, attributes and text of well-formed XML;
+/// enough to read documents back, not a conforming XML processor.
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        let before = &self.input[..self.position];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        format!("{} at line {} column {}", message, line, column)
+    }
+
+    fn rest(&self) -> &str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.position = self.input.len() - trimmed.len();
+    }
+
+    /// Skips the declaration, comments and processing instructions.
+    fn skip_misc(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_whitespace();
+            let end = if self.rest().starts_with("<?") {
+                "?>"
+            } else if self.rest().starts_with("<!--") {
+                "-->"
+            } else {
+                return Ok(());
+            };
+            let length = self
+                .rest()
+                .find(end)
+                .ok_or_else(|| self.error("unterminated markup"))?;
+            self.position += length + end.len();
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let length = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || matches!(c, '>' | '/' | '='))
+            .unwrap_or(self.rest().len());
+        if length == 0 {
+            return Err(self.error("expected a name"));
+        }
+        let name = self.rest()[..length].to_string();
+        self.position += length;
+        Ok(name)
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if !self.rest().starts_with(token) {
+            return Err(self.error(&format!("expected {:?}", token)));
+        }
+        self.position += token.len();
+        Ok(())
+    }
+
+    fn element(&mut self) -> Result<Element, String> {
+        self.expect("<")?;
+        let name = self.name()?;
+        let mut element = Element {
+            name: local_name(&name).to_string(),
+            ..Default::default()
+        };
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.position += 1;
+                break;
+            }
+            let attribute = self.name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = if self.rest().starts_with('\'') {
+                "'"
+            } else {
+                "\""
+            };
+            self.expect(quote)?;
+            let length = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| self.error("unterminated attribute value"))?;
+            let value = unescape(&self.rest()[..length]).map_err(|err| self.error(&err))?;
+            self.position += length + 1;
+            element
+                .attributes
+                .push((local_name(&attribute).to_string(), value));
+        }
+        loop {
+            let length = self
+                .rest()
+                .find('<')
+                .ok_or_else(|| self.error(&format!("unclosed element <{}>", name)))?;
+            element.text += &unescape(&self.rest()[..length]).map_err(|err| self.error(&err))?;
+            self.position += length;
+            if self.rest().starts_with("</") {
+                self.position += 2;
+                let closing = self.name()?;
+                if closing != name {
+                    return Err(self.error(&format!("</{}> closes <{}>", closing, name)));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            }
+            if self.rest().starts_with("<!--") || self.rest().starts_with("<?") {
+                self.skip_misc()?;
+                continue;
+            }
+            element.children.push(self.element()?);
+        }
+    }
+
+    fn document(mut self) -> Result<Element, String> {
+        self.skip_misc()?;
+        let root = self.element()?;
+        self.skip_misc()?;
+        if !self.rest().is_empty() {
+            return Err(self.error("content after the root element"));
+        }
+        Ok(root)
+    }
+}
+
+/// An amount in thousandths, so that every ISO 4217 precision is exact.
+fn parse_amount(text: &str) -> Option<i128> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty()
+        || fraction.len() > 3
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let whole: i128 = whole.parse().ok()?;
+    let fraction: i128 = format!("{:0<3}", fraction).parse().ok()?;
+    Some(sign * (whole * 1000 + fraction))
+}
+
+fn format_amount(thousandths: i128) -> String {
+    let sign = if thousandths < 0 { "-" } else { "" };
+    let abs = thousandths.unsigned_abs();
+    let text = format!("{}{}.{:03}", sign, abs / 1000, abs % 1000);
+    text.strip_suffix('0').map(str::to_string).unwrap_or(text)
+}
+
+/// The VAT category code and rate of a `TaxCategory` or
+/// `ClassifiedTaxCategory`, blank when missing.
+fn category_key(category: Option<&Element>) -> (String, String) {
+    let text = |name| {
+        category
+            .and_then(|c| c.text_at(&[name]))
+            .unwrap_or_default()
+            .to_string()
+    };
+    (text("ID"), text("Percent"))
+}
+
+fn add_to_category(
+    totals: &mut Vec<((String, String), i128)>,
+    key: (String, String),
+    amount: i128,
+) {
+    match totals.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, total)) => *total += amount,
+        None => totals.push((key, amount)),
+    }
+}
+
+struct Checker {
+    violations: Vec<Violation>,
+}
+
+impl Checker {
+    fn report(&mut self, rule: &'static str, message: impl Into<String>) {
+        self.violations.push(Violation {
+            rule,
+            message: message.into(),
+        });
+    }
+
+    fn require(&mut self, rule: &'static str, value: Option<&str>, what: &str) {
+        if value.is_none() {
+            self.report(rule, format!("{} is missing", what));
+        }
+    }
+
+    /// The amount at `names`, reporting `rule` when it is missing or invalid.
+    fn amount(&mut self, rule: &'static str, element: &Element, names: &[&str]) -> Option<i128> {
+        let Some(text) = element.text_at(names) else {
+            self.report(rule, format!("{} is missing", names.join("/")));
+            return None;
+        };
+        let amount = parse_amount(text);
+        if amount.is_none() {
+            self.report(
+                rule,
+                format!("{} is not an amount: {:?}", names.join("/"), text),
+            );
+        }
+        amount
+    }
+
+    fn sum_matches(&mut self, rule: &'static str, what: &str, expected: i128, actual: i128) {
+        if expected != actual {
+            self.report(
+                rule,
+                format!(
+                    "{} is {} but should be {}",
+                    what,
+                    format_amount(actual),
+                    format_amount(expected)
+                ),
+            );
+        }
+    }
+
+    /// The rules of a VAT breakdown's category: the tax of `S` is its rate of
+    /// the taxable amount, give or take the one currency unit that rounding
+    /// per line allows, and `Z` and `E` are untaxed, `E` with a reason.
+    fn subtotal(&mut self, subtotal: &Element) {
+        let id = subtotal.text_at(&["TaxCategory", "ID"]).unwrap_or_default();
+        let percent = subtotal
+            .text_at(&["TaxCategory", "Percent"])
+            .unwrap_or_default();
+        let taxable = subtotal.text_at(&["TaxableAmount"]).and_then(parse_amount);
+        let tax = subtotal.text_at(&["TaxAmount"]).and_then(parse_amount);
+        let (Some(taxable), Some(tax)) = (taxable, tax) else {
+            return;
+        };
+        let untaxed = |checker: &mut Self, rule| {
+            if tax != 0 {
+                checker.report(
+                    rule,
+                    format!(
+                        "VAT amount for category {} is {} but should be 0",
+                        id,
+                        format_amount(tax)
+                    ),
+                );
+            }
+        };
+        match id {
+            "S" => {
+                let Ok(rate) = percent.parse::<crate::exchange::Rate>() else {
+                    self.report("BR-S-09", format!("VAT rate {:?} is not a rate", percent));
+                    return;
+                };
+                let hundred = 100 * 10i128.pow(rate.scale);
+                let difference = taxable
+                    .checked_mul(rate.digits)
+                    .and_then(|expected| tax.checked_mul(hundred)?.checked_sub(expected));
+                if difference.is_none_or(|d| d.abs() >= 1000 * hundred) {
+                    self.report(
+                        "BR-S-09",
+                        format!(
+                            "VAT amount for category S {}% is {} but should be {}% of {}",
+                            percent,
+                            format_amount(tax),
+                            percent,
+                            format_amount(taxable)
+                        ),
+                    );
+                }
+            }
+            "Z" => untaxed(self, "BR-Z-09"),
+            "E" => {
+                untaxed(self, "BR-E-09");
+                self.require(
+                    "BR-E-10",
+                    subtotal
+                        .text_at(&["TaxCategory", "TaxExemptionReason"])
+                        .or_else(|| subtotal.text_at(&["TaxCategory", "TaxExemptionReasonCode"])),
+                    "VAT exemption reason for category E",
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn party(&mut self, root: &Element, role: &str, rules: [&'static str; 4]) {
+        let party = root.path(&[role, "Party"]);
+        let [name, address, country, endpoint] = rules;
+        let who = if role == "AccountingSupplierParty" {
+            "seller"
+        } else {
+            "buyer"
+        };
+        self.require(
+            name,
+            party.and_then(|p| p.text_at(&["PartyLegalEntity", "RegistrationName"])),
+            &format!("{} name", who),
+        );
+        if party.and_then(|p| p.child("PostalAddress")).is_none() {
+            self.report(address, format!("{} postal address is missing", who));
+        }
+        self.require(
+            country,
+            party.and_then(|p| p.text_at(&["PostalAddress", "Country", "IdentificationCode"])),
+            &format!("{} country code", who),
+        );
+        let has_scheme = party
+            .and_then(|p| p.child("EndpointID"))
+            .is_some_and(|e| e.attribute("schemeID").is_some());
+        self.require(
+            endpoint,
+            party
+                .and_then(|p| p.text_at(&["EndpointID"]))
+                .filter(|_| has_scheme),
+            &format!("{} electronic address with scheme", who),
+        );
+    }
+}
+
+/// Checks `xml` against the mandatory business rules, returning every broken
+/// rule sorted by rule id; an empty list means the invoice passed.
+pub(crate) fn validate(xml: &str) -> Vec<Violation> {
+    let mut checker = Checker {
+        violations: Vec::new(),
+    };
+    let root = match (Parser {
+        input: xml,
+        position: 0,
+    })
+    .document()
+    {
+        Ok(root) => root,
+        Err(err) => {
+            checker.report("XML", err);
+            return checker.violations;
+        }
+    };
+    if root.name != "Invoice" {
+        checker.report(
+            "XML",
+            format!("root element is <{}>, not <Invoice>", root.name),
+        );
+        return checker.violations;
+    }
+
+    if root.text_at(&["CustomizationID"]) != Some(super::CUSTOMIZATION_ID) {
+        checker.report(
+            "PEPPOL-EN16931-R004",
+            "specification identifier is not Peppol BIS Billing 3.0",
+        );
+    }
+    checker.require(
+        "PEPPOL-EN16931-R001",
+        root.text_at(&["ProfileID"]),
+        "business process (ProfileID)",
+    );
+    checker.require("BR-02", root.text_at(&["ID"]), "invoice number");
+    match root.text_at(&["IssueDate"]) {
+        Some(date) if date.parse::<crate::date::Date>().is_err() => {
+            checker.report("BR-03", format!("issue date {:?} is not YYYY-MM-DD", date));
+        }
+        date => checker.require("BR-03", date, "issue date"),
+    }
+    checker.require(
+        "BR-04",
+        root.text_at(&["InvoiceTypeCode"]),
+        "invoice type code",
+    );
+    let currency = root.text_at(&["DocumentCurrencyCode"]);
+    checker.require("BR-05", currency, "invoice currency code");
+    checker.require(
+        "PEPPOL-EN16931-R003",
+        root.text_at(&["BuyerReference"])
+            .or_else(|| root.text_at(&["OrderReference", "ID"])),
+        "buyer reference or purchase order reference",
+    );
+    checker.party(
+        &root,
+        "AccountingSupplierParty",
+        ["BR-06", "BR-08", "BR-09", "PEPPOL-EN16931-R020"],
+    );
+    checker.party(
+        &root,
+        "AccountingCustomerParty",
+        ["BR-07", "BR-10", "BR-11", "PEPPOL-EN16931-R010"],
+    );
+
+    if let Some(currency) = currency {
+        let mismatched = root
+            .descendants()
+            .into_iter()
+            .filter_map(|e| e.attribute("currencyID").map(|c| (e, c)))
+            .filter(|(_, c)| *c != currency)
+            .map(|(e, c)| format!("{} in {}", c, e.name))
+            .collect::<Vec<_>>();
+        if !mismatched.is_empty() {
+            checker.report(
+                "PEPPOL-EN16931-R051",
+                format!(
+                    "amounts must be in {}, found {}",
+                    currency,
+                    mismatched.join(", ")
+                ),
+            );
+        }
+    }
+
+    // Line checks, collecting net amounts per VAT category for the totals.
+    let lines: Vec<&Element> = root.children("InvoiceLine").collect();
+    if lines.is_empty() {
+        checker.report("BR-16", "the invoice has no lines");
+    }
+    let mut line_total = 0;
+    let mut category_totals: Vec<((String, String), i128)> = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let number = index + 1;
+        checker.require(
+            "BR-21",
+            line.text_at(&["ID"]),
+            &format!("line {} identifier", number),
+        );
+        checker.require(
+            "BR-22",
+            line.text_at(&["InvoicedQuantity"]),
+            &format!("line {} quantity", number),
+        );
+        checker.require(
+            "BR-25",
+            line.text_at(&["Item", "Name"]),
+            &format!("line {} item name", number),
+        );
+        checker.amount("BR-26", line, &["Price", "PriceAmount"]);
+        let Some(net) = checker.amount("BR-24", line, &["LineExtensionAmount"]) else {
+            continue;
+        };
+        line_total += net;
+        let key = category_key(line.path(&["Item", "ClassifiedTaxCategory"]));
+        if key.0.is_empty() {
+            checker.report("BR-CO-04", format!("line {} has no VAT category", number));
+        }
+        add_to_category(&mut category_totals, key, net);
+    }
+
+    // Document level allowances and charges, which move the taxable amount of
+    // their VAT category.
+    let mut allowance_total = 0;
+    let mut charge_total = 0;
+    for (index, allowance) in root.children("AllowanceCharge").enumerate() {
+        let number = index + 1;
+        let charge = allowance.text_at(&["ChargeIndicator"]) == Some("true");
+        let (what, [amount_rule, category_rule, reason_rule]) = if charge {
+            ("charge", ["BR-36", "BR-37", "BR-38"])
+        } else {
+            ("allowance", ["BR-31", "BR-32", "BR-33"])
+        };
+        let key = category_key(allowance.child("TaxCategory"));
+        if key.0.is_empty() {
+            checker.report(
+                category_rule,
+                format!("document level {} {} has no VAT category", what, number),
+            );
+        }
+        checker.require(
+            reason_rule,
+            allowance
+                .text_at(&["AllowanceChargeReason"])
+                .or_else(|| allowance.text_at(&["AllowanceChargeReasonCode"])),
+            &format!("document level {} {} reason", what, number),
+        );
+        let Some(amount) = checker.amount(amount_rule, allowance, &["Amount"]) else {
+            continue;
+        };
+        if charge {
+            charge_total += amount;
+            add_to_category(&mut category_totals, key, amount);
+        } else {
+            allowance_total += amount;
+            add_to_category(&mut category_totals, key, -amount);
+        }
+    }
+
+    // Document totals
+    let totals = &["LegalMonetaryTotal"];
+    let Some(monetary) = root.path(totals) else {
+        checker.report("BR-12", "document totals are missing");
+        checker.violations.sort();
+        return checker.violations;
+    };
+    let line_extension = checker.amount("BR-12", monetary, &["LineExtensionAmount"]);
+    let tax_exclusive = checker.amount("BR-13", monetary, &["TaxExclusiveAmount"]);
+    let tax_inclusive = checker.amount("BR-14", monetary, &["TaxInclusiveAmount"]);
+    let payable = checker.amount("BR-15", monetary, &["PayableAmount"]);
+    let optional = |names: &[&str]| {
+        monetary
+            .text_at(names)
+            .and_then(parse_amount)
+            .unwrap_or_default()
+    };
+    let allowances = optional(&["AllowanceTotalAmount"]);
+    let charges = optional(&["ChargeTotalAmount"]);
+    let prepaid = optional(&["PrepaidAmount"]);
+    let rounding = optional(&["PayableRoundingAmount"]);
+
+    checker.sum_matches(
+        "BR-CO-11",
+        "sum of allowances on document level",
+        allowance_total,
+        allowances,
+    );
+    checker.sum_matches(
+        "BR-CO-12",
+        "sum of charges on document level",
+        charge_total,
+        charges,
+    );
+    if let Some(line_extension) = line_extension {
+        checker.sum_matches(
+            "BR-CO-10",
+            "sum of invoice line net amounts",
+            line_extension,
+            line_total,
+        );
+        if let Some(tax_exclusive) = tax_exclusive {
+            checker.sum_matches(
+                "BR-CO-13",
+                "invoice total without VAT",
+                line_extension - allowances + charges,
+                tax_exclusive,
+            );
+        }
+    }
+
+    let tax_total = root.child("TaxTotal");
+    let tax_amount = tax_total.and_then(|t| t.text_at(&["TaxAmount"]).and_then(parse_amount));
+    if let Some(tax_total) = tax_total {
+        let subtotals: Vec<&Element> = tax_total.children("TaxSubtotal").collect();
+        let subtotal_tax = subtotals
+            .iter()
+            .filter_map(|s| s.text_at(&["TaxAmount"]).and_then(parse_amount))
+            .sum();
+        checker.sum_matches(
+            "BR-CO-14",
+            "invoice total VAT amount",
+            subtotal_tax,
+            tax_amount.unwrap_or_default(),
+        );
+        for subtotal in &subtotals {
+            checker.subtotal(subtotal);
+        }
+        for ((id, percent), expected) in &category_totals {
+            let taxable = subtotals
+                .iter()
+                .filter(|s| {
+                    s.text_at(&["TaxCategory", "ID"]) == Some(id.as_str())
+                        && s.text_at(&["TaxCategory", "Percent"]).unwrap_or_default() == percent
+                })
+                .find_map(|s| s.text_at(&["TaxableAmount"]).and_then(parse_amount));
+            let rule = match id.as_str() {
+                "S" => "BR-S-08",
+                "Z" => "BR-Z-08",
+                "E" => "BR-E-08",
+                _ => "BR-CO-18",
+            };
+            checker.sum_matches(
+                rule,
+                &format!("taxable amount for VAT category {} {}%", id, percent),
+                *expected,
+                taxable.unwrap_or_default(),
+            );
+        }
+    } else if !category_totals.is_empty() {
+        checker.report("BR-CO-18", "the invoice has no VAT breakdown");
+    }
+
+    if let (Some(tax_exclusive), Some(tax_inclusive)) = (tax_exclusive, tax_inclusive) {
+        checker.sum_matches(
+            "BR-CO-15",
+            "invoice total with VAT",
+            tax_exclusive + tax_amount.unwrap_or_default(),
+            tax_inclusive,
+        );
+    }
+    if let (Some(tax_inclusive), Some(payable)) = (tax_inclusive, payable) {
+        checker.sum_matches(
+            "BR-CO-16",
+            "amount due for payment",
+            tax_inclusive - prepaid + rounding,
+            payable,
+        );
+    }
+
+    checker.violations.sort();
+    checker.violations
+}
@@ -0,0 +1,316 @@
+use serde::Serialize;
+
+use super::{
+    create_statement_data::StatementData,
+    exchange::RoundingMode,
+    money::{Currency, Money, MoneyError},
+    render::{Datum, bar_chart, csv_field, pie_chart, stack},
+};
+
+/// Revenue and audience for one play, play kind or customer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct RevenueLine {
+    pub name: String,
+    pub performances: u64,
+    pub audience: u64,
+    pub revenue: Money,
+    /// Revenue per seat sold.
+    pub average_yield: Money,
+}
+
+/// Totals over many statements, as management reads them.
+///
+/// Revenue is the net amount of each performance: taxes are not revenue,
+/// and credit discounts are counted separately as credits redeemed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct Report {
+    pub invoices: u64,
+    pub performances: u64,
+    pub audience: u64,
+    pub revenue: Money,
+    pub average_yield: Money,
+    pub credits_issued: u64,
+    pub credits_redeemed: u64,
+    /// Highest revenue first.
+    pub by_play: Vec<RevenueLine>,
+    pub by_kind: Vec<RevenueLine>,
+    pub by_customer: Vec<RevenueLine>,
+}
+
+fn average_yield(revenue: Money, audience: u64) -> Money {
+    if audience == 0 {
+        return Money::zero(revenue.currency);
+    }
+    let per_seat =
+        RoundingMode::HalfEven.divide(i128::from(revenue.minor_units), i128::from(audience));
+    // A share of an i64 amount always fits in an i64.
+    Money::new(per_seat as i64, revenue.currency)
+}
+
+// Adds a performance to the line called `name`, creating it if needed.
+fn add(
+    lines: &mut Vec<RevenueLine>,
+    name: &str,
+    audience: u32,
+    revenue: Money,
+) -> Result<(), MoneyError> {
+    let index = match lines.iter().position(|line| line.name == name) {
+        Some(index) => index,
+        None => {
+            lines.push(RevenueLine {
+                name: name.to_string(),
+                performances: 0,
+                audience: 0,
+                revenue: Money::zero(revenue.currency),
+                average_yield: Money::zero(revenue.currency),
+            });
+            lines.len() - 1
+        }
+    };
+    let line = &mut lines[index];
+    line.performances += 1;
+    line.audience += u64::from(audience);
+    line.revenue = line.revenue.checked_add(revenue)?;
+    Ok(())
+}
+
+fn finish(mut lines: Vec<RevenueLine>) -> Vec<RevenueLine> {
+    for line in &mut lines {
+        line.average_yield = average_yield(line.revenue, line.audience);
+    }
+    lines.sort_by(|a, b| {
+        b.revenue
+            .minor_units
+            .cmp(&a.revenue.minor_units)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    lines
+}
+
+fn plural(count: u64, word: &str) -> String {
+    if count == 1 {
+        format!("{count} {word}")
+    } else {
+        format!("{count} {word}s")
+    }
+}
+
+impl Report {
+    /// Aggregates statements priced in one currency; a statement in another
+    /// currency than the first is a [`MoneyError::CurrencyMismatch`].
+    pub fn from_statements(statements: &[StatementData]) -> Result<Self, MoneyError> {
+        let currency = statements
+            .iter()
+            .flat_map(|data| &data.performances)
+            .next()
+            .map_or(Currency::default(), |perf| perf.net_amount.currency);
+        let mut revenue = Money::zero(currency);
+        let (mut by_play, mut by_kind, mut by_customer) = (Vec::new(), Vec::new(), Vec::new());
+        let (mut performances, mut audience) = (0, 0);
+        let (mut credits_issued, mut credits_redeemed) = (0, 0);
+        for data in statements {
+            for perf in &data.performances {
+                revenue = revenue.checked_add(perf.net_amount)?;
+                add(
+                    &mut by_play,
+                    &perf.play.name,
+                    perf.audience,
+                    perf.net_amount,
+                )?;
+                add(
+                    &mut by_kind,
+                    &perf.play.kind,
+                    perf.audience,
+                    perf.net_amount,
+                )?;
+                add(
+                    &mut by_customer,
+                    &data.customer,
+                    perf.audience,
+                    perf.net_amount,
+                )?;
+                performances += 1;
+                audience += u64::from(perf.audience);
+            }
+            credits_issued += u64::from(data.total_volume_credits);
+            credits_redeemed += u64::from(data.credits_redeemed);
+        }
+        Ok(Report {
+            invoices: statements.len() as u64,
+            performances,
+            audience,
+            revenue,
+            average_yield: average_yield(revenue, audience),
+            credits_issued,
+            credits_redeemed,
+            by_play: finish(by_play),
+            by_kind: finish(by_kind),
+            by_customer: finish(by_customer),
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut result = format!(
+            "Revenue report for {} and {}\n",
+            plural(self.invoices, "invoice"),
+            plural(self.performances, "performance")
+        );
+        result += &format!("Revenue is {}\n", self.revenue);
+        result += &format!("Audience is {}\n", plural(self.audience, "seat"));
+        result += &format!("Average ticket yield is {} per seat\n", self.average_yield);
+        result += &format!("Credits issued: {}\n", self.credits_issued);
+        result += &format!("Credits redeemed: {}\n", self.credits_redeemed);
+        for (title, lines) in [
+            ("play", &self.by_play),
+            ("kind", &self.by_kind),
+            ("customer", &self.by_customer),
+        ] {
+            result += &format!("\nRevenue by {title}\n");
+            for line in lines {
+                result += &format!(
+                    " {}: {} ({}, {}, {} per seat)\n",
+                    line.name,
+                    line.revenue,
+                    plural(line.performances, "performance"),
+                    plural(line.audience, "seat"),
+                    line.average_yield
+                );
+            }
+        }
+        result
+    }
+
+    /// One row per play, kind and customer, then the overall total.
+    pub fn to_csv(&self) -> String {
+        let mut result =
+            String::from("group,name,performances,audience,revenue,average_yield,currency\n");
+        let total = RevenueLine {
+            name: String::new(),
+            performances: self.performances,
+            audience: self.audience,
+            revenue: self.revenue,
+            average_yield: self.average_yield,
+        };
+        for (group, lines) in [
+            ("play", self.by_play.as_slice()),
+            ("kind", &self.by_kind),
+            ("customer", &self.by_customer),
+            ("total", std::slice::from_ref(&total)),
+        ] {
+            for line in lines {
+                result += &format!(
+                    "{},{},{},{},{},{},{}\n",
+                    group,
+                    csv_field(&line.name),
+                    line.performances,
+                    line.audience,
+                    line.revenue.format_amount(),
+                    line.average_yield.format_amount(),
+                    line.revenue.currency
+                );
+            }
+        }
+        result
+    }
+
+    /// Revenue by play as bars above revenue by play type as a pie.
+    pub fn to_svg(&self) -> String {
+        let data = |lines: &[RevenueLine]| -> Vec<Datum> {
+            lines
+                .iter()
+                .map(|line| Datum {
+                    label: line.name.clone(),
+                    value: line.revenue,
+                })
+                .collect()
+        };
+        stack(&[
+            bar_chart("Revenue by play", &data(&self.by_play), None),
+            pie_chart("Revenue by play type", &data(&self.by_kind), None),
+        ])
+    }
+
+    pub fn to_json(&self) -> String {
+        // Counts, money and lists of lines, none of which can fail to
+        // serialize.
+        serde_json::to_string_pretty(self).expect("report serializes") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Play, create_statement_data::PerformanceData};
+
+    fn statement(customer: &str, performances: &[(&str, &str, u32, i64)]) -> StatementData {
+        StatementData {
+            customer: customer.to_string(),
+            performances: performances
+                .iter()
+                .map(|&(name, kind, audience, cents)| PerformanceData {
+                    play: Play {
+                        name: name.to_string(),
+                        kind: kind.to_string(),
+                    },
+                    audience,
+                    net_amount: Money::new(cents, Currency::Usd),
+                    ..Default::default()
+                })
+                .collect(),
+            total_volume_credits: 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_revenue_is_grouped_by_play_kind_and_customer() {
+        let statements = [
+            statement(
+                "BigCo",
+                &[
+                    ("Hamlet", "tragedy", 55, 65000),
+                    ("As You Like It", "comedy", 35, 58000),
+                ],
+            ),
+            statement("Acme, Inc.", &[("Hamlet", "tragedy", 30, 40000)]),
+        ];
+        let report = Report::from_statements(&statements).unwrap();
+        assert_eq!(report.revenue, Money::new(163000, Currency::Usd));
+        assert_eq!(report.audience, 120);
+        assert_eq!(report.average_yield, Money::new(1358, Currency::Usd));
+        assert_eq!(report.credits_issued, 20);
+        let hamlet = &report.by_play[0];
+        assert_eq!(
+            (hamlet.name.as_str(), hamlet.performances, hamlet.audience),
+            ("Hamlet", 2, 85)
+        );
+        assert_eq!(hamlet.average_yield, Money::new(1235, Currency::Usd));
+        assert_eq!(report.by_kind[1].name, "comedy");
+
+        assert_eq!(
+            report.to_csv(),
+            "group,name,performances,audience,revenue,average_yield,currency\n\
+             play,Hamlet,2,85,1050.00,12.35,USD\n\
+             play,As You Like It,1,35,580.00,16.57,USD\n\
+             kind,tragedy,2,85,1050.00,12.35,USD\n\
+             kind,comedy,1,35,580.00,16.57,USD\n\
+             customer,BigCo,2,90,1230.00,13.67,USD\n\
+             customer,\"Acme, Inc.\",1,30,400.00,13.33,USD\n\
+             total,,3,120,1630.00,13.58,USD\n"
+        );
+        assert!(report.to_text().starts_with(
+            "Revenue report for 2 invoices and 3 performances\nRevenue is $1630.00\n"
+        ));
+        assert!(report.to_svg().contains(">tragedy: $1050.00 (64%)</text>"));
+        assert!(report.to_text().contains(
+            "\nRevenue by customer\n BigCo: $1230.00 (2 performances, 90 seats, $13.67 per seat)\n"
+        ));
+
+        let mut euro = statement("Eurotheater", &[("Hamlet", "tragedy", 10, 100)]);
+        euro.performances[0].net_amount.currency = Currency::Eur;
+        assert_eq!(
+            Report::from_statements(&[statements[1].clone(), euro]),
+            Err(MoneyError::CurrencyMismatch(Currency::Usd, Currency::Eur))
+        );
+    }
+}
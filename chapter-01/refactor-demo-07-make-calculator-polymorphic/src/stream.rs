@@ -0,0 +1,186 @@
+//! Invoices as newline-delimited JSON, one invoice per line, read one at a
+//! time so that a season's worth of invoices takes no more memory than one.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use super::{Invoice, error::StatementError};
+
+/// Why a line did not give an invoice.
+#[derive(Debug)]
+pub(crate) enum LineError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::Io(err) => write!(f, "failed to read: {err}"),
+            LineError::Json(err) => write!(f, "failed to parse: {err}"),
+        }
+    }
+}
+
+/// The invoices of a reader, each with the line it is on. Blank lines are
+/// skipped, and reading stops after an I/O error.
+pub(crate) struct InvoiceLines<R> {
+    reader: R,
+    line: usize,
+    buffer: String,
+    failed: bool,
+}
+
+impl<R: BufRead> InvoiceLines<R> {
+    pub fn new(reader: R) -> Self {
+        InvoiceLines {
+            reader,
+            line: 0,
+            buffer: String::new(),
+            failed: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for InvoiceLines<R> {
+    type Item = (usize, Result<Invoice, LineError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            // The buffer is reused, so memory stays at the longest line.
+            self.buffer.clear();
+            self.line += 1;
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => return None,
+                Ok(_) if self.buffer.trim().is_empty() => continue,
+                Ok(_) => {
+                    let invoice = serde_json::from_str(&self.buffer).map_err(LineError::Json);
+                    return Some((self.line, invoice));
+                }
+                Err(err) => {
+                    self.failed = true;
+                    return Some((self.line, Err(LineError::Io(err))));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorLine<'a> {
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    customer: Option<&'a str>,
+    error: String,
+}
+
+/// The side file that failed invoices are written to as NDJSON, one
+/// `{"line", "customer", "error"}` object each. It is empty after a clean run.
+pub(crate) struct ErrorLog {
+    pub path: PathBuf,
+    writer: BufWriter<File>,
+    pub count: usize,
+}
+
+impl ErrorLog {
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self, StatementError> {
+        let path = path.into();
+        let file = File::create(&path).map_err(|err| StatementError::write(&path, err))?;
+        Ok(ErrorLog {
+            path,
+            writer: BufWriter::new(file),
+            count: 0,
+        })
+    }
+
+    /// The default side file for `invoices`: `season.ndjson` logs to
+    /// `season.errors.ndjson` next to it.
+    pub fn path_for(invoices: &Path) -> PathBuf {
+        let stem = invoices
+            .file_stem()
+            .map_or_else(|| "invoices".into(), |stem| stem.to_string_lossy());
+        invoices.with_file_name(format!("{stem}.errors.ndjson"))
+    }
+
+    pub fn record(
+        &mut self,
+        line: usize,
+        customer: Option<&str>,
+        error: &dyn fmt::Display,
+    ) -> Result<(), StatementError> {
+        let entry = ErrorLine {
+            line,
+            customer,
+            error: error.to_string(),
+        };
+        serde_json::to_writer(&mut self.writer, &entry)
+            .map_err(io::Error::from)
+            .and_then(|()| self.writer.write_all(b"\n"))
+            .map_err(|err| StatementError::write(&self.path, err))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn close(mut self) -> Result<(), StatementError> {
+        self.writer
+            .flush()
+            .map_err(|err| StatementError::write(&self.path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn test_invoices_are_read_line_by_line() {
+        let input = "{\"customer\": \"BigCo\", \"performances\": []}\n\
+                     \n\
+                     {\"customer\": \n\
+                     {\"customer\": \"Acme\", \"performances\": [{\"play_id\": \"hamlet\", \"audience\": 5}]}";
+        let lines: Vec<_> = InvoiceLines::new(input.as_bytes()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0, 1);
+        assert_eq!(lines[0].1.as_ref().unwrap().customer, "BigCo");
+        assert_eq!(lines[1].0, 3);
+        assert!(
+            lines[1]
+                .1
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .starts_with("failed to parse: EOF while parsing")
+        );
+        assert_eq!(lines[2].1.as_ref().unwrap().performances[0].audience, 5);
+    }
+
+    #[test]
+    fn test_error_log_has_a_line_per_failure() {
+        assert_eq!(
+            ErrorLog::path_for(Path::new("data/season.ndjson")),
+            Path::new("data/season.errors.ndjson")
+        );
+        let path = env::temp_dir().join(format!("errors-{}.ndjson", std::process::id()));
+        let mut log = ErrorLog::create(&path).unwrap();
+        log.record(3, None, &"failed to parse").unwrap();
+        log.record(7, Some("BigCo"), &"play not found: hamlt")
+            .unwrap();
+        assert_eq!(log.count, 2);
+        log.close().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\"line\":3,\"error\":\"failed to parse\"}\n\
+             {\"line\":7,\"customer\":\"BigCo\",\"error\":\"play not found: hamlt\"}\n"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
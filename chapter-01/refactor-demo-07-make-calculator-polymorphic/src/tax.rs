@@ -0,0 +1,237 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::StatementError,
+    exchange::{Rate, RoundingMode},
+    money::{Money, MoneyError},
+    read_json,
+};
+
+/// Whether the prices from the pricing rules already include tax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum TaxPricing {
+    #[default]
+    Exclusive,
+    Inclusive,
+}
+
+/// One tax levied in a jurisdiction, e.g. a state sales tax or a VAT.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct TaxComponent {
+    pub name: String,
+    /// Percentage, e.g. `"19"` or `"8.875"`.
+    pub rate: Rate,
+    /// Reduced or increased rates for particular play kinds.
+    #[serde(default)]
+    pub kind_rates: HashMap<String, Rate>,
+    /// Play kinds this tax is not levied on, such as cultural performances.
+    #[serde(default)]
+    pub exempt: Vec<String>,
+}
+
+impl TaxComponent {
+    fn rate_for(&self, kind: &str) -> Option<Rate> {
+        if self.exempt.iter().any(|k| k == kind) {
+            return None;
+        }
+        Some(self.kind_rates.get(kind).copied().unwrap_or(self.rate))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct Jurisdiction {
+    #[serde(default)]
+    pub pricing: TaxPricing,
+    pub taxes: Vec<TaxComponent>,
+}
+
+/// A tax charged on a performance or, summed up, on a whole statement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct TaxLine {
+    pub name: String,
+    pub rate: Rate,
+    pub amount: Money,
+}
+
+impl TaxLine {
+    /// `VAT 19%`
+    pub fn label(&self) -> String {
+        format!("{} {}%", self.name, self.rate)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TaxedAmount {
+    pub net: Money,
+    pub gross: Money,
+    pub lines: Vec<TaxLine>,
+    /// The names of the taxes the play kind is exempt from.
+    pub exemptions: Vec<String>,
+}
+
+/// Tax rates per jurisdiction, as found in `taxes.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct TaxEngine {
+    #[serde(default)]
+    pub rounding: RoundingMode,
+    pub jurisdictions: HashMap<String, Jurisdiction>,
+}
+
+impl TaxEngine {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, StatementError> {
+        read_json(path)
+    }
+
+    pub fn jurisdiction(&self, id: &str) -> Option<&Jurisdiction> {
+        self.jurisdictions.get(id)
+    }
+
+    /// Splits `amount` for a play of `kind` into net, tax lines and gross.
+    pub fn apply(
+        &self,
+        jurisdiction: &Jurisdiction,
+        amount: Money,
+        kind: &str,
+    ) -> Result<TaxedAmount, MoneyError> {
+        let rates: Vec<(&TaxComponent, Rate)> = jurisdiction
+            .taxes
+            .iter()
+            .filter_map(|tax| tax.rate_for(kind).map(|rate| (tax, rate)))
+            .collect();
+        let exemptions = jurisdiction
+            .taxes
+            .iter()
+            .filter(|tax| tax.rate_for(kind).is_none())
+            .map(|tax| tax.name.clone())
+            .collect();
+        // Bring all percentages to a common scale so they can be summed.
+        let scale = rates.iter().map(|(_, r)| r.scale).max().unwrap_or(0);
+        let hundred = 100 * 10i128.pow(scale);
+        let scaled = |rate: Rate| checked(rate.digits.checked_mul(10i128.pow(scale - rate.scale)));
+        let minor = i128::from(amount.minor_units);
+
+        let net = match jurisdiction.pricing {
+            TaxPricing::Exclusive => minor,
+            TaxPricing::Inclusive => {
+                let total_rate = rates.iter().try_fold(0i128, |total, (_, rate)| {
+                    checked(total.checked_add(scaled(*rate)?))
+                })?;
+                self.rounding.divide(
+                    checked(minor.checked_mul(hundred))?,
+                    checked(hundred.checked_add(total_rate))?,
+                )
+            }
+        };
+        let mut lines = Vec::with_capacity(rates.len());
+        let mut tax_total = 0i128;
+        for (index, (tax, rate)) in rates.iter().enumerate() {
+            let is_last = index + 1 == rates.len();
+            // Inclusive prices are fixed, so the last tax takes the rounding
+            // residue and the lines always add up to the price.
+            let tax_amount = if is_last && jurisdiction.pricing == TaxPricing::Inclusive {
+                minor - net - tax_total
+            } else {
+                self.rounding
+                    .divide(checked(net.checked_mul(scaled(*rate)?))?, hundred)
+            };
+            tax_total = checked(tax_total.checked_add(tax_amount))?;
+            lines.push(TaxLine {
+                name: tax.name.clone(),
+                rate: *rate,
+                amount: to_money(tax_amount, amount)?,
+            });
+        }
+        Ok(TaxedAmount {
+            net: to_money(net, amount)?,
+            gross: to_money(checked(net.checked_add(tax_total))?, amount)?,
+            lines,
+            exemptions,
+        })
+    }
+}
+
+/// Turns an overflowed `checked_*` result into an error.
+fn checked(result: Option<i128>) -> Result<i128, MoneyError> {
+    result.ok_or(MoneyError::Overflow)
+}
+
+fn to_money(minor: i128, like: Money) -> Result<Money, MoneyError> {
+    i64::try_from(minor)
+        .map(|minor| Money::new(minor, like.currency))
+        .map_err(|_| MoneyError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Currency;
+
+    fn engine() -> TaxEngine {
+        serde_json::from_str(
+            r#"{
+                "jurisdictions": {
+                    "US-NY": { "taxes": [
+                        { "name": "State sales tax", "rate": "4" },
+                        { "name": "City sales tax", "rate": "4.875" }
+                    ] },
+                    "DE": { "pricing": "inclusive", "taxes": [
+                        { "name": "VAT", "rate": "19", "kind_rates": { "tragedy": "7" } }
+                    ] },
+                    "FR": { "pricing": "inclusive", "taxes": [
+                        { "name": "TVA", "rate": "20", "exempt": ["tragedy"] }
+                    ] }
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn apply(jurisdiction: &str, kind: &str) -> TaxedAmount {
+        let engine = engine();
+        let jurisdiction = engine.jurisdiction(jurisdiction).unwrap();
+        engine
+            .apply(jurisdiction, Money::new(65000, Currency::Usd), kind)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_exclusive_taxes_are_added() {
+        let taxed = apply("US-NY", "tragedy");
+        assert_eq!(taxed.net.minor_units, 65000);
+        assert_eq!(taxed.lines[0].amount.minor_units, 2600);
+        assert_eq!(taxed.lines[1].amount.minor_units, 3169);
+        assert_eq!(taxed.gross.minor_units, 70769);
+        assert_eq!(taxed.lines[1].label(), "City sales tax 4.875%");
+    }
+
+    #[test]
+    fn test_inclusive_taxes_are_extracted() {
+        let taxed = apply("DE", "tragedy");
+        assert_eq!(taxed.net.minor_units, 60748);
+        assert_eq!(taxed.lines[0].amount.minor_units, 4252);
+        assert_eq!(taxed.gross.minor_units, 65000);
+
+        let exempt = apply("FR", "tragedy");
+        assert!(exempt.lines.is_empty());
+        assert_eq!(exempt.exemptions, ["TVA"]);
+        assert_eq!(exempt.net, exempt.gross);
+    }
+
+    #[test]
+    fn test_overflow_is_an_error() {
+        let engine: TaxEngine = serde_json::from_str(
+            r#"{ "jurisdictions": { "XX": { "pricing": "inclusive", "taxes": [
+                { "name": "Levy", "rate": "12.345678901234567891" }
+            ] } } }"#,
+        )
+        .unwrap();
+        let jurisdiction = engine.jurisdiction("XX").unwrap();
+        assert!(matches!(
+            engine.apply(jurisdiction, Money::new(i64::MAX, Currency::Usd), "tragedy"),
+            Err(MoneyError::Overflow)
+        ));
+    }
+}
@@ -0,0 +1,584 @@
+//! Checks the play catalogue and the invoices as written, so that every
+//! problem is reported at once with the file, line and column it is on.
+//! Pricing catches the rest, but stops at the first problem of each invoice
+//! and knows nothing about where it came from. Only JSON files have
+//! positions; problems in other formats are reported by file alone.
+
+mod json;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+pub(crate) use json::Position;
+use json::{Node, Value};
+use serde::{Serialize, de::DeserializeOwned};
+
+use super::{
+    error::StatementError,
+    input::{self, DataFormat, Dataset},
+};
+
+/// More seats than any theatre has; such an audience is a typo.
+pub(crate) const MAX_AUDIENCE: u32 = 100_000;
+
+/// A data file and its contents, read once for checking and loading.
+#[derive(Debug, Clone)]
+pub(crate) struct Source {
+    pub path: PathBuf,
+    pub text: String,
+    pub format: DataFormat,
+    pub dataset: Dataset,
+}
+
+impl Source {
+    /// Reads `path` in the format `flag` names or its extension implies.
+    pub fn read(
+        path: impl AsRef<Path>,
+        flag: Option<DataFormat>,
+        dataset: Dataset,
+    ) -> Result<Self, StatementError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| StatementError::io(path, err))?;
+        Ok(Source {
+            path: path.to_path_buf(),
+            text,
+            format: DataFormat::detect(path, flag, dataset),
+            dataset,
+        })
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, StatementError> {
+        input::parse(&self.path, &self.text, self.format, self.dataset)
+    }
+
+    fn parse(&self) -> Result<Node, json::SyntaxError> {
+        match self.format {
+            DataFormat::Json => json::parse(&self.text),
+            format => input::read_value(&self.text, format, self.dataset)
+                .map(|value| Node::unplaced(&value))
+                .map_err(|message| json::SyntaxError {
+                    position: Position::default(),
+                    message,
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Severity {
+    Error,
+    /// Suspicious but priceable; does not fail validation.
+    Warning,
+}
+
+/// One problem at a place in a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct Diagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(path: &Path, position: Position, message: impl Into<String>) -> Self {
+        Diagnostic {
+            path: path.to_path_buf(),
+            line: position.line,
+            column: position.column,
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(path: &Path, position: Position, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(path, position, message)
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    /// `path:line:column: severity: message`, as compilers print them, or
+    /// `path: severity: message` without a position.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}:", self.path.display())?;
+        if self.line > 0 {
+            write!(f, "{}:{}:", self.line, self.column)?;
+        }
+        write!(f, " {}: {}", severity, self.message)
+    }
+}
+
+pub(crate) fn error_count(diagnostics: &[Diagnostic]) -> usize {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count()
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        _ => format!("{count} {noun}s"),
+    }
+}
+
+/// One line per diagnostic and a count at the end.
+pub(crate) fn to_text(diagnostics: &[Diagnostic]) -> String {
+    let mut output = String::new();
+    for diagnostic in diagnostics {
+        output += &format!("{diagnostic}\n");
+    }
+    let errors = error_count(diagnostics);
+    let warnings = diagnostics.len() - errors;
+    if diagnostics.is_empty() {
+        output += "no problems found\n";
+    } else {
+        output += &format!(
+            "{}, {}\n",
+            plural(errors, "error"),
+            plural(warnings, "warning")
+        );
+    }
+    output
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    errors: usize,
+    warnings: usize,
+    diagnostics: &'a [Diagnostic],
+}
+
+/// The diagnostics and their counts as a JSON object, for CI.
+pub(crate) fn to_json(diagnostics: &[Diagnostic]) -> Result<String, serde_json::Error> {
+    let errors = error_count(diagnostics);
+    serde_json::to_string_pretty(&JsonReport {
+        errors,
+        warnings: diagnostics.len() - errors,
+        diagnostics,
+    })
+}
+
+// Levenshtein distance, to suggest the id a typo was meant to be.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn suggestion<'a>(id: &str, known: impl Iterator<Item = &'a str>) -> String {
+    known
+        .map(|candidate| (edit_distance(id, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map_or_else(String::new, |(_, candidate)| {
+            format!(", did you mean {candidate:?}?")
+        })
+}
+
+struct Checker<'a> {
+    path: &'a Path,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, position: Position, message: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::error(self.path, position, message));
+    }
+
+    fn warning(&mut self, position: Position, message: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::warning(self.path, position, message));
+    }
+
+    /// Field `key` of `node` as a non-empty string, reporting it otherwise.
+    fn text<'n>(&mut self, node: &'n Node, key: &str, owner: &str) -> Option<&'n str> {
+        let Some(field) = node.get(key) else {
+            self.error(node.position, format!("{owner} has no \"{key}\""));
+            return None;
+        };
+        match field.as_str() {
+            Some(text) if !text.trim().is_empty() => Some(text),
+            Some(_) => {
+                self.error(field.position, format!("{owner} has an empty \"{key}\""));
+                None
+            }
+            None => {
+                self.error(
+                    field.position,
+                    format!("\"{key}\" must be a string, found {}", field.value),
+                );
+                None
+            }
+        }
+    }
+
+    fn syntax(&mut self, source: &Source) -> Option<Node> {
+        match source.parse() {
+            Ok(root) => Some(root),
+            Err(err) => {
+                self.error(err.position, err.message);
+                None
+            }
+        }
+    }
+}
+
+/// The play ids of the catalogue and where each is first defined.
+fn check_plays(checker: &mut Checker, root: &Node, kinds: &[&str]) -> HashMap<String, Position> {
+    let mut ids = HashMap::new();
+    let Value::Object(members) = &root.value else {
+        checker.error(
+            root.position,
+            format!("expected an object of plays by id, found {}", root.value),
+        );
+        return ids;
+    };
+    for member in members {
+        let owner = format!("play {:?}", member.key);
+        if let Some(first) = ids.get(&member.key) {
+            let first: &Position = first;
+            checker.error(
+                member.key_position,
+                format!(
+                    "duplicate play id {:?}, first defined on line {}",
+                    member.key, first.line
+                ),
+            );
+        } else {
+            ids.insert(member.key.clone(), member.key_position);
+        }
+        let play = &member.value;
+        if !matches!(play.value, Value::Object(_)) {
+            checker.error(
+                play.position,
+                format!("{owner} must be an object, found {}", play.value),
+            );
+            continue;
+        }
+        checker.text(play, "name", &owner);
+        if let Some(kind) = checker.text(play, "type", &owner)
+            && !kinds.contains(&kind)
+        {
+            let position = play.get("type").map_or(play.position, |node| node.position);
+            checker.error(
+                position,
+                format!(
+                    "unknown play type {kind:?} for {owner}, expected one of: {}{}",
+                    kinds.join(", "),
+                    suggestion(kind, kinds.iter().copied())
+                ),
+            );
+        }
+    }
+    ids
+}
+
+/// The play ids the invoices use.
+fn check_invoices(
+    checker: &mut Checker,
+    root: &Node,
+    plays: Option<&HashMap<String, Position>>,
+) -> HashSet<String> {
+    let mut used = HashSet::new();
+    let Value::Array(invoices) = &root.value else {
+        checker.error(
+            root.position,
+            format!("expected an array of invoices, found {}", root.value),
+        );
+        return used;
+    };
+    for (index, invoice) in invoices.iter().enumerate() {
+        if !matches!(invoice.value, Value::Object(_)) {
+            checker.error(
+                invoice.position,
+                format!(
+                    "invoice #{} must be an object, found {}",
+                    index + 1,
+                    invoice.value
+                ),
+            );
+            continue;
+        }
+        let customer = checker
+            .text(invoice, "customer", &format!("invoice #{}", index + 1))
+            .map_or_else(|| format!("invoice #{}", index + 1), str::to_string);
+        let Some(performances) = invoice.get("performances") else {
+            checker.error(
+                invoice.position,
+                format!("{customer} has no \"performances\""),
+            );
+            continue;
+        };
+        let Value::Array(performances) = &performances.value else {
+            checker.error(
+                performances.position,
+                format!(
+                    "\"performances\" must be an array, found {}",
+                    performances.value
+                ),
+            );
+            continue;
+        };
+        if performances.is_empty() {
+            checker.warning(invoice.position, format!("{customer} has no performances"));
+        }
+        for (number, performance) in performances.iter().enumerate() {
+            let owner = format!("{customer}, performance #{}", number + 1);
+            if !matches!(performance.value, Value::Object(_)) {
+                checker.error(
+                    performance.position,
+                    format!("{owner} must be an object, found {}", performance.value),
+                );
+                continue;
+            }
+            if let Some(play_id) = checker.text(performance, "play_id", &owner) {
+                used.insert(play_id.to_string());
+                if let Some(plays) = plays
+                    && !plays.contains_key(play_id)
+                {
+                    let position = performance
+                        .get("play_id")
+                        .map_or(performance.position, |node| node.position);
+                    checker.error(
+                        position,
+                        format!(
+                            "{owner}: unknown play id {play_id:?}{}",
+                            suggestion(play_id, plays.keys().map(String::as_str))
+                        ),
+                    );
+                }
+            }
+            check_audience(checker, performance, &owner);
+        }
+    }
+    used
+}
+
+fn check_audience(checker: &mut Checker, performance: &Node, owner: &str) {
+    let Some(field) = performance.get("audience") else {
+        checker.error(performance.position, format!("{owner} has no \"audience\""));
+        return;
+    };
+    let Some(audience) = field.as_u32() else {
+        checker.error(
+            field.position,
+            format!(
+                "{owner}: audience must be a whole number of seats, found {}",
+                field.value
+            ),
+        );
+        return;
+    };
+    if audience == 0 {
+        checker.warning(field.position, format!("{owner}: audience of 0 seats"));
+    } else if audience > MAX_AUDIENCE {
+        checker.error(
+            field.position,
+            format!("{owner}: audience of {audience} seats is more than {MAX_AUDIENCE}"),
+        );
+    }
+    if let Some(capacity) = performance.get("capacity").and_then(Node::as_u32)
+        && audience > capacity
+    {
+        checker.error(
+            field.position,
+            format!("{owner}: audience of {audience} exceeds the venue capacity of {capacity}"),
+        );
+    }
+}
+
+/// Checks both files and returns every problem, sorted by file and position.
+/// `kinds` are the registered play types.
+pub(crate) fn check(plays: &Source, invoices: &Source, kinds: &[&str]) -> Vec<Diagnostic> {
+    let mut play_checker = Checker {
+        path: &plays.path,
+        diagnostics: Vec::new(),
+    };
+    let mut invoice_checker = Checker {
+        path: &invoices.path,
+        diagnostics: Vec::new(),
+    };
+    let play_ids = play_checker
+        .syntax(plays)
+        .map(|root| check_plays(&mut play_checker, &root, kinds));
+    let used = invoice_checker
+        .syntax(invoices)
+        .map(|root| check_invoices(&mut invoice_checker, &root, play_ids.as_ref()));
+    // Unused plays can only be told once the invoices are readable.
+    if let (Some(play_ids), Some(used)) = (&play_ids, &used) {
+        let mut play_ids: Vec<_> = play_ids.iter().collect();
+        play_ids.sort_unstable_by_key(|(id, _)| *id);
+        for (id, position) in play_ids {
+            if !used.contains(id) {
+                play_checker.warning(*position, format!("play {id:?} is not used by any invoice"));
+            }
+        }
+    }
+    let mut diagnostics = play_checker.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| {
+        let position = diagnostic.position();
+        (position.line, position.column)
+    });
+    diagnostics.extend(invoice_checker.diagnostics);
+    diagnostics
+}
+
+/// Where each invoice object starts, to place pricing errors.
+pub(crate) fn invoice_positions(invoices: &Source) -> Vec<Position> {
+    match invoices.parse().map(|root| root.value) {
+        Ok(Value::Array(items)) => items.iter().map(|item| item.position).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(path: &str, text: &str) -> Source {
+        Source {
+            path: PathBuf::from(path),
+            text: text.to_string(),
+            format: DataFormat::Json,
+            dataset: if path.starts_with("plays") {
+                Dataset::Plays
+            } else {
+                Dataset::Invoices
+            },
+        }
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics.iter().map(ToString::to_string).collect()
+    }
+
+    const KINDS: &[&str] = &["comedy", "tragedy"];
+
+    #[test]
+    fn test_fixtures_are_clean() {
+        let plays = Source::read("../plays.json", None, Dataset::Plays).unwrap();
+        let invoices = Source::read("../invoices.json", None, Dataset::Invoices).unwrap();
+        assert_eq!(check(&plays, &invoices, KINDS), Vec::new());
+        assert_eq!(
+            invoice_positions(&invoices),
+            vec![Position { line: 2, column: 3 }]
+        );
+    }
+
+    #[test]
+    fn test_every_problem_is_reported_with_its_position() {
+        let plays = source(
+            "plays.json",
+            r#"{
+  "hamlet": { "name": "Hamlet", "type": "tragedy" },
+  "othello": { "name": "Othello", "type": "tragdy" },
+  "hamlet": { "name": "", "type": "tragedy" }
+}"#,
+        );
+        let invoices = source(
+            "invoices.json",
+            r#"[
+  {
+    "customer": " ",
+    "performances": [
+      { "play_id": "hamlt", "audience": 0 },
+      { "play_id": "hamlet", "audience": 1000000 },
+      { "play_id": "hamlet", "audience": -5 },
+      { "play_id": "hamlet", "audience": 60, "capacity": 50 }
+    ]
+  }
+]"#,
+        );
+        assert_eq!(
+            messages(&check(&plays, &invoices, KINDS)),
+            [
+                "plays.json:3:3: warning: play \"othello\" is not used by any invoice",
+                "plays.json:3:43: error: unknown play type \"tragdy\" for play \"othello\", \
+                 expected one of: comedy, tragedy, did you mean \"tragedy\"?",
+                "plays.json:4:3: error: duplicate play id \"hamlet\", first defined on line 2",
+                "plays.json:4:23: error: play \"hamlet\" has an empty \"name\"",
+                "invoices.json:3:17: error: invoice #1 has an empty \"customer\"",
+                "invoices.json:5:20: error: invoice #1, performance #1: unknown play id \
+                 \"hamlt\", did you mean \"hamlet\"?",
+                "invoices.json:5:41: warning: invoice #1, performance #1: audience of 0 seats",
+                "invoices.json:6:42: error: invoice #1, performance #2: audience of 1000000 \
+                 seats is more than 100000",
+                "invoices.json:7:42: error: invoice #1, performance #3: audience must be a \
+                 whole number of seats, found -5",
+                "invoices.json:8:42: error: invoice #1, performance #4: audience of 60 exceeds \
+                 the venue capacity of 50",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_and_json_output() {
+        let plays = source("plays.json", "{\"hamlet\": }");
+        let invoices = source("invoices.json", "[]");
+        let diagnostics = check(&plays, &invoices, KINDS);
+        assert_eq!(
+            messages(&diagnostics),
+            ["plays.json:1:12: error: expected a value, found `}`"]
+        );
+        assert_eq!(
+            to_text(&diagnostics).lines().last(),
+            Some("1 error, 0 warnings")
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&to_json(&diagnostics).unwrap()).unwrap();
+        assert_eq!(json["errors"], 1);
+        assert_eq!(json["diagnostics"][0]["severity"], "error");
+        assert_eq!(json["diagnostics"][0]["column"], 12);
+        assert_eq!(to_text(&[]), "no problems found\n");
+    }
+
+    #[test]
+    fn test_other_formats_are_checked_without_positions() {
+        let plays = Source {
+            format: DataFormat::Csv,
+            ..source("plays.csv", "id,name,type\nhamlet,Hamlet,histroy\n")
+        };
+        let invoices = Source {
+            format: DataFormat::Yaml,
+            ..source("invoices.yaml", "- customer: BigCo\n  performances: []\n")
+        };
+        assert_eq!(
+            messages(&check(&plays, &invoices, KINDS)),
+            [
+                "plays.csv: error: unknown play type \"histroy\" for play \"hamlet\", \
+                 expected one of: comedy, tragedy",
+                "plays.csv: warning: play \"hamlet\" is not used by any invoice",
+                "invoices.yaml: warning: BigCo has no performances",
+            ]
+        );
+    }
+}
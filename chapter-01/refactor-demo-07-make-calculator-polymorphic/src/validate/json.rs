@@ -0,0 +1,375 @@
+//! A JSON parser that records where each value starts. `serde_json` reports
+//! positions only for syntax errors, while validation needs them for values
+//! that parse fine but make no sense, and it keeps duplicate object keys.
+
+use std::fmt;
+
+/// A 1-based line and column, counted in characters, or zeros if unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Node>),
+    /// Members in file order, duplicates included.
+    Object(Vec<Member>),
+}
+
+/// A value and where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Node {
+    pub value: Value,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Member {
+    pub key: String,
+    pub key_position: Position,
+    pub value: Node,
+}
+
+/// A syntax error and where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SyntaxError {
+    pub position: Position,
+    pub message: String,
+}
+
+impl fmt::Display for Value {
+    /// A short description for diagnostics, e.g. `a string` or `-3`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Number(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value:?}"),
+            Value::Array(_) => f.write_str("an array"),
+            Value::Object(_) => f.write_str("an object"),
+        }
+    }
+}
+
+impl Node {
+    /// The first member called `key`, if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Node> {
+        match &self.value {
+            Value::Object(members) => members
+                .iter()
+                .find(|member| member.key == key)
+                .map(|member| &member.value),
+            _ => None,
+        }
+    }
+
+    /// `value` as a node without positions, for formats other than JSON.
+    pub fn unplaced(value: &serde_json::Value) -> Node {
+        let value = match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(value) => Value::Bool(*value),
+            serde_json::Value::Number(value) => Value::Number(value.as_f64().unwrap_or(f64::NAN)),
+            serde_json::Value::String(value) => Value::String(value.clone()),
+            serde_json::Value::Array(items) => {
+                Value::Array(items.iter().map(Node::unplaced).collect())
+            }
+            serde_json::Value::Object(members) => Value::Object(
+                members
+                    .iter()
+                    .map(|(key, value)| Member {
+                        key: key.clone(),
+                        key_position: Position::default(),
+                        value: Node::unplaced(value),
+                    })
+                    .collect(),
+            ),
+        };
+        Node {
+            value,
+            position: Position::default(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value as a whole number from 0 to `u32::MAX`.
+    pub fn as_u32(&self) -> Option<u32> {
+        match self.value {
+            Value::Number(n) if n.fract() == 0.0 && (0.0..=f64::from(u32::MAX)).contains(&n) => {
+                Some(n as u32)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+    position: Position,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, SyntaxError> {
+        Err(SyntaxError {
+            position: self.position,
+            message: message.into(),
+        })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SyntaxError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => self.error(format!("expected `{expected}`, found `{c}`")),
+            None => self.error(format!("expected `{expected}`, found the end of the file")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Node, SyntaxError> {
+        self.skip_whitespace();
+        let position = self.position;
+        let value = match self.peek() {
+            Some('{') => self.object()?,
+            Some('[') => self.array()?,
+            Some('"') => Value::String(self.string()?),
+            Some('-' | '0'..='9') => self.number()?,
+            Some('t') => self.literal("true", Value::Bool(true))?,
+            Some('f') => self.literal("false", Value::Bool(false))?,
+            Some('n') => self.literal("null", Value::Null)?,
+            Some(c) => return self.error(format!("expected a value, found `{c}`")),
+            None => return self.error("expected a value, found the end of the file"),
+        };
+        Ok(Node { value, position })
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, SyntaxError> {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return self.error(format!("expected `{word}`"));
+            }
+            self.bump();
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, SyntaxError> {
+        let start = self.index;
+        while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            self.bump();
+        }
+        let text: String = self.chars[start..self.index].iter().collect();
+        match text.parse() {
+            Ok(n) => Ok(Value::Number(n)),
+            Err(_) => self.error(format!("invalid number `{text}`")),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, SyntaxError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let Some(digit) = self.peek().and_then(|c| c.to_digit(16)) else {
+                return self.error("expected four hex digits after `\\u`");
+            };
+            self.bump();
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, SyntaxError> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(result),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut code = self.hex4()?;
+                            // A high surrogate must be followed by a low one.
+                            if (0xd800..0xdc00).contains(&code) {
+                                if self.bump() != Some('\\') || self.bump() != Some('u') {
+                                    return self.error("unpaired surrogate in `\\u` escape");
+                                }
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            match char::from_u32(code) {
+                                Some(c) => c,
+                                None => return self.error("invalid `\\u` escape"),
+                            }
+                        }
+                        _ => return self.error("invalid escape in string"),
+                    };
+                    result.push(escaped);
+                }
+                Some(c) if c < ' ' => return self.error("control character in string"),
+                Some(c) => result.push(c),
+                None => return self.error("unterminated string"),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, SyntaxError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(']') => {
+                    self.bump();
+                    return Ok(Value::Array(items));
+                }
+                _ => return self.error("expected `,` or `]` in array"),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, SyntaxError> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key_position = self.position;
+            if self.peek() != Some('"') {
+                return self.error("expected a string key in object");
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            members.push(Member {
+                key,
+                key_position,
+                value,
+            });
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some('}') => {
+                    self.bump();
+                    return Ok(Value::Object(members));
+                }
+                _ => return self.error("expected `,` or `}` in object"),
+            }
+        }
+    }
+}
+
+/// Parses a whole JSON document.
+pub(crate) fn parse(text: &str) -> Result<Node, SyntaxError> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        index: 0,
+        position: Position { line: 1, column: 1 },
+    };
+    let root = parser.value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(root),
+        Some(c) => parser.error(format!("unexpected `{c}` after the document")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions_and_duplicate_keys() {
+        let root = parse("{\n  \"a\": [1, \"x\\u00e9\\ud83c\\udfad\"],\n  \"a\": null\n}").unwrap();
+        let Value::Object(members) = &root.value else {
+            panic!("not an object");
+        };
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[1].key_position, Position { line: 3, column: 3 });
+        let Value::Array(items) = &members[0].value.value else {
+            panic!("not an array");
+        };
+        assert_eq!(
+            items[1].position,
+            Position {
+                line: 2,
+                column: 12
+            }
+        );
+        assert_eq!(items[1].as_str(), Some("xé🎭"));
+        assert_eq!(items[0].as_u32(), Some(1));
+        assert_eq!(root.get("a"), Some(&members[0].value));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let error = |text| parse(text).unwrap_err();
+        assert_eq!(
+            error("[1,\n 2"),
+            SyntaxError {
+                position: Position { line: 2, column: 3 },
+                message: "expected `,` or `]` in array".to_string()
+            }
+        );
+        assert_eq!(error("{\"a\" 1}").message, "expected `:`, found `1`");
+        assert_eq!(error("[1] x").message, "unexpected `x` after the document");
+        assert_eq!(error("\"abc").message, "unterminated string");
+    }
+}